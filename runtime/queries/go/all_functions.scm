@@ -3,3 +3,12 @@
 
  (function_declaration
    name: (identifier) @func.name))
+
+((package_clause
+   (package_identifier) @pack.name)
+
+ (method_declaration
+   receiver: (parameter_list
+     (parameter_declaration
+       type: (_) @method.receiver))
+   name: (field_identifier) @func.name))
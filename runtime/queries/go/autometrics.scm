@@ -9,4 +9,16 @@
    name: (identifier) @func.name)
  (#match? @dir.comment "^//autometrics:(inst|doc)"))
 
+((package_clause
+   (package_identifier) @pack.name)
 
+ (comment) @dir.comment
+ .
+ (comment)*
+ .
+ (method_declaration
+   receiver: (parameter_list
+     (parameter_declaration
+       type: (_) @method.receiver))
+   name: (field_identifier) @func.name)
+ (#match? @dir.comment "^//autometrics:(inst|doc)"))
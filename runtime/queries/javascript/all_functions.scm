@@ -0,0 +1,35 @@
+(function_declaration
+ name: (identifier) @func.name)
+
+(function_expression
+ name: (identifier) @func.name)
+
+;; Anonymous functions get the name of the variable they are bound to:
+;; `const handler = async (req) => {}` and `const handler = function () {}`
+(variable_declarator
+ name: (identifier) @func.name
+ value: [(arrow_function) (function_expression !name)])
+
+(assignment_expression
+ left: (identifier) @func.name
+ right: [(arrow_function) (function_expression !name)])
+
+;; Object literal methods are named after their key:
+;; `{ handler() {} }` and `{ handler: () => {} }`
+(object
+ (method_definition
+  name: (property_identifier) @func.name))
+
+(object
+ (pair
+  key: (property_identifier) @func.name
+  value: [(arrow_function) (function_expression !name)]))
+
+(class_declaration
+ name: (identifier) @type.name
+ body: (class_body
+        [(method_definition
+          name: (property_identifier) @method.name)
+         (field_definition
+          property: (property_identifier) @method.name
+          value: [(arrow_function) (function_expression !name)])]))
@@ -0,0 +1,138 @@
+;; TODO: this doesn't work as captures aren't shared between patterns.
+;; This means we can't use @wrapper.atname in an #eq expression in the (call_expression) pattern afterwards
+;; A recursion algorithm that uses a templated query might be the solution
+((import_statement
+  (import_clause
+   (named_imports
+    (import_specifier
+     .
+     name: (identifier) @wrapperdirect.name
+     .)))
+  source: (string (string_fragment) @lib.atname))
+ (#match? @lib.atname "@autometrics\/autometrics|autometrics")
+ (#eq? @wrapperdirect.name "autometrics"))
+
+((import_statement
+  (import_clause
+   (named_imports
+    (import_specifier
+     name: (identifier) @real.name
+     alias: (identifier) @wrapperdirect.name)))
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @real.name "autometrics"))
+
+
+
+;; TODO: this doesn't work as captures aren't shared between patterns.
+;; This means we can't use @wrapperdirect.name in an #eq expression in the (call_expression) pattern afterwards
+;; A recursion algorithm that uses a templated query might be the solution
+((import_statement
+  (import_clause
+   (named_imports
+    (import_specifier
+     .
+     name: (identifier) @wrapper.name
+     .)))
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @wrapper.name "autometrics"))
+
+((import_statement
+  (import_clause
+   (named_imports
+    (import_specifier
+     name: (identifier) @real.name
+     alias: (identifier) @wrapper.name)))
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @real.name "autometrics"))
+
+;; The whole module can also be imported, in which case the wrappers are
+;; called as members of the module object (`am.autometrics(fn)`):
+;; `import * as am from`, default imports and `const am = require()`.
+((import_statement
+  (import_clause
+   (namespace_import (identifier) @autometrics.namespace))
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+((import_statement
+  (import_clause
+   .
+   (identifier) @autometrics.namespace)
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+((variable_declarator
+  name: (identifier) @autometrics.namespace
+  value: (call_expression
+          function: (identifier) @require.call
+          arguments: (arguments . (string (string_fragment) @lib.name) .)))
+ (#eq? @require.call "require")
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+;; CommonJS destructuring: `const { autometrics } = require()` and
+;; `const { autometrics: am } = require()`.
+((variable_declarator
+  name: (object_pattern
+         (shorthand_property_identifier_pattern) @wrapperdirect.name @wrapper.name)
+  value: (call_expression
+          function: (identifier) @require.call
+          arguments: (arguments . (string (string_fragment) @lib.name) .)))
+ (#eq? @require.call "require")
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @wrapperdirect.name "autometrics"))
+
+((variable_declarator
+  name: (object_pattern
+         (pair_pattern
+          key: (property_identifier) @real.name
+          value: (identifier) @wrapperdirect.name @wrapper.name))
+  value: (call_expression
+          function: (identifier) @require.call
+          arguments: (arguments . (string (string_fragment) @lib.name) .)))
+ (#eq? @require.call "require")
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @real.name "autometrics"))
+
+;; Class decorators instrument all the methods of the class. They can be
+;; called with options: `@Autometrics`, `@Autometrics()` or
+;; `@Autometrics({ objective })`.
+((class_declaration
+  decorator: (decorator
+              [(identifier) @decorator.name
+               (call_expression
+                function: (identifier) @decorator.name
+                arguments: (arguments (object)? @decorator.options))]) @class.decorator
+  name: (identifier) @type.name
+  body: (class_body
+         (method_definition
+          name: (property_identifier) @method.name)))
+ (#eq? @decorator.name "Autometrics"))
+
+((export_statement
+  decorator: (decorator
+              [(identifier) @decorator.name
+               (call_expression
+                function: (identifier) @decorator.name
+                arguments: (arguments (object)? @decorator.options))]) @class.decorator
+  declaration: (class_declaration
+                name: (identifier) @type.name
+                body: (class_body
+                       (method_definition
+                        name: (property_identifier) @method.name))))
+ (#eq? @decorator.name "Autometrics"))
+
+;; Method decorators only instrument the decorated method.
+((class_declaration
+  name: (identifier) @type.name
+  body: (class_body
+         (method_definition
+          decorator: (decorator
+                      [(identifier) @decorator.name
+                       (call_expression
+                        function: (identifier) @decorator.name
+                        arguments: (arguments (object)? @decorator.options))]) @method.decorator
+          name: (property_identifier) @method.name)))
+ (#eq? @decorator.name "Autometrics"))
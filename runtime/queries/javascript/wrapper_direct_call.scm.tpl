@@ -0,0 +1,22 @@
+((call_expression
+   function: [(identifier) (member_expression)] @wrapper.call
+   arguments: (arguments
+               .
+               [(identifier) (member_expression)] @func.name))
+ (#eq? @wrapper.call "{0}"))
+
+((call_expression
+   function: [(identifier) (member_expression)] @wrapper.call
+   arguments: (arguments
+               .
+               (function_expression
+                 name: (identifier) @func.name)))
+ (#eq? @wrapper.call "{0}"))
+
+;; Anonymous functions are named after the variable holding the wrapped function.
+((call_expression
+   function: [(identifier) (member_expression)] @wrapper.call
+   arguments: (arguments
+               .
+               [(arrow_function) (function_expression !name)] @func.name))
+ (#eq? @wrapper.call "{0}"))
@@ -0,0 +1,10 @@
+;; The decorator expressions are filtered afterwards, as the names under which
+;; the autometrics decorator is available depend on the imports of the file.
+(decorated_definition
+  (decorator
+    [(identifier) @decorator.name
+     (attribute) @decorator.name
+     (call
+       function: [(identifier) (attribute)] @decorator.name)])
+  definition: (function_definition
+               name: (identifier) @func.name))
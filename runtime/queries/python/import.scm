@@ -1,9 +1,14 @@
+;; `from module import name` and `from module import name as alias`
 (import_from_statement
-  module_name: (dotted_name (identifier) @import.module)
-  (#eq? @import.module "autometrics")
-  name:[
-        (dotted_name (identifier) @import.name)
-        (aliased_import
-          name: (dotted_name (identifier) @import.name)
-          alias: (identifier) @import.alias)]
-  (#eq? @import.name "autometrics"))
+  module_name: [(dotted_name) (relative_import)] @import.module
+  name: [(dotted_name) @import.name
+         (aliased_import
+           name: (dotted_name) @import.name
+           alias: (identifier) @import.alias)])
+
+;; `import module` and `import module as alias`
+(import_statement
+  name: [(dotted_name) @import.module
+         (aliased_import
+           name: (dotted_name) @import.module
+           alias: (identifier) @import.alias)])
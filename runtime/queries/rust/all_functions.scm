@@ -1,3 +1,5 @@
+;; This also matches the default methods of traits and the functions nested in
+;; function bodies, whose kind and name are computed from their ancestors.
 ((function_item
   name: (identifier) @func.name))
 
@@ -17,6 +19,10 @@
 ;; We also consider that an "impl block" is an "in-file" module for the purpose of recursion
 ;; This allows to detect functions that have the autometrics annotation within an impl block,
 ;; _while allowing to skip functions in impl blocks detected by the main query_.
+;;
+;; The type of the impl block can be generic (`Repo<T>`) or scoped (`db::Pool`),
+;; the name of the type is extracted from the captured node.
 ((impl_item
-  type: (type_identifier) @impl.type
+  trait: (_)? @impl.trait
+  type: (_) @impl.type
   body: (declaration_list) @impl.contents))
@@ -1,30 +1,30 @@
+;; The attributes are matched against the names of the autometrics attribute in
+;; the file when reading the captures, as they can be path-qualified
+;; (`#[autometrics::autometrics]`), aliased by a `use` declaration, or wrapped in
+;; a `cfg_attr`.
 ((attribute_item)*
  .
  (attribute_item
-   (attribute
-     (identifier) @attr))
+   (attribute) @am.attribute)
  .
  (attribute_item)*
  .
  (function_item
-   name: (identifier) @func.name)
- (#eq? @attr "autometrics"))
+   name: (identifier) @func.name))
 
 ((attribute_item)*
  .
  (attribute_item
-   (attribute
-     (identifier) @attr))
+   (attribute) @am.attribute)
  .
  (attribute_item)*
  .
  (impl_item
-   type: (type_identifier) @type.impl
+   trait: (_)? @inner.trait
+   type: (_) @type.impl
    body: (declaration_list
            (function_item
-             name: (identifier) @inner.func.name)))
-
- (#eq? @attr "autometrics"))
+             name: (identifier) @inner.func.name))))
 
 ;; It is impossible to do arbitrary levels of nesting, so we just detect module declarations to
 ;; call this query recursively on the declaration_list of the module.
@@ -42,6 +42,10 @@
 ;; We also consider that an "impl block" is an "in-file" module for the purpose of recursion
 ;; This allows to detect functions that have the autometrics annotation within an impl block,
 ;; _while allowing to skip functions in impl blocks detected by the main query_.
+;;
+;; The type of the impl block can be generic (`Repo<T>`) or scoped (`db::Pool`),
+;; the name of the type is extracted from the captured node.
 ((impl_item
-  type: (type_identifier) @impl.type
+  trait: (_)? @impl.trait
+  type: (_) @impl.type
   body: (declaration_list) @impl.contents))
@@ -0,0 +1,5 @@
+;; The use trees are flattened when reading the captures, to find the aliases of
+;; the autometrics crate (`use autometrics as am;`) and of the autometrics
+;; attribute macro (`use autometrics::autometrics as am;`).
+(use_declaration
+  argument: (_) @import.tree)
@@ -4,10 +4,34 @@
 (function
  name: (identifier) @func.name)
 
+;; Anonymous functions get the name of the variable they are bound to:
+;; `const handler = async (req) => {}` and `const handler = function () {}`
+(variable_declarator
+ name: (identifier) @func.name
+ value: [(arrow_function) (function !name)])
+
+(assignment_expression
+ left: (identifier) @func.name
+ right: [(arrow_function) (function !name)])
+
+;; Object literal methods are named after their key:
+;; `{ handler() {} }` and `{ handler: () => {} }`
+(object
+ (method_definition
+  name: (property_identifier) @func.name))
+
+(object
+ (pair
+  key: (property_identifier) @func.name
+  value: [(arrow_function) (function !name)]))
+
 (class_declaration
  name: (type_identifier) @type.name
  body: (class_body
         [(method_signature
           name: (property_identifier) @method.name)
          (method_definition
-          name: (property_identifier) @method.name)]))
+          name: (property_identifier) @method.name)
+         (public_field_definition
+          name: (property_identifier) @method.name
+          value: [(arrow_function) (function !name)])]))
@@ -48,8 +48,70 @@
  (#match? @lib.name "@autometrics\/autometrics|autometrics")
  (#eq? @real.name "autometrics"))
 
+;; The whole module can also be imported, in which case the wrappers are
+;; called as members of the module object (`am.autometrics(fn)`):
+;; `import * as am from`, default imports and `const am = require()`.
+((import_statement
+  (import_clause
+   (namespace_import (identifier) @autometrics.namespace))
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+((import_statement
+  (import_clause
+   .
+   (identifier) @autometrics.namespace)
+  source: (string (string_fragment) @lib.name))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+((variable_declarator
+  name: (identifier) @autometrics.namespace
+  value: (call_expression
+          function: (identifier) @require.call
+          arguments: (arguments . (string (string_fragment) @lib.name) .)))
+ (#eq? @require.call "require")
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+;; CommonJS destructuring: `const { autometrics } = require()` and
+;; `const { autometrics: am } = require()`.
+((variable_declarator
+  name: (object_pattern
+         (shorthand_property_identifier_pattern) @wrapperdirect.name @wrapper.name)
+  value: (call_expression
+          function: (identifier) @require.call
+          arguments: (arguments . (string (string_fragment) @lib.name) .)))
+ (#eq? @require.call "require")
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @wrapperdirect.name "autometrics"))
+
+((variable_declarator
+  name: (object_pattern
+         (pair_pattern
+          key: (property_identifier) @real.name
+          value: (identifier) @wrapperdirect.name @wrapper.name))
+  value: (call_expression
+          function: (identifier) @require.call
+          arguments: (arguments . (string (string_fragment) @lib.name) .)))
+ (#eq? @require.call "require")
+ (#match? @lib.name "@autometrics\/autometrics|autometrics")
+ (#eq? @real.name "autometrics"))
+
+;; Typescript-specific CommonJS import: `import am = require()`.
+((import_statement
+  (import_require_clause
+   (identifier) @autometrics.namespace
+   source: (string (string_fragment) @lib.name)))
+ (#match? @lib.name "@autometrics\/autometrics|autometrics"))
+
+;; Class decorators instrument all the methods of the class. They can be
+;; called with options: `@Autometrics`, `@Autometrics()` or
+;; `@Autometrics({ objective })`.
 ((class_declaration
-  decorator: (decorator (identifier) @decorator.name)
+  decorator: (decorator
+              [(identifier) @decorator.name
+               (call_expression
+                function: (identifier) @decorator.name
+                arguments: (arguments (object)? @decorator.options))]) @class.decorator
   name: (type_identifier) @type.name
   body: (class_body
          [(method_signature
@@ -57,3 +119,34 @@
           (method_definition
            name: (property_identifier) @method.name)]))
  (#eq? @decorator.name "Autometrics"))
+
+((export_statement
+  decorator: (decorator
+              [(identifier) @decorator.name
+               (call_expression
+                function: (identifier) @decorator.name
+                arguments: (arguments (object)? @decorator.options))]) @class.decorator
+  declaration: (class_declaration
+                name: (type_identifier) @type.name
+                body: (class_body
+                       [(method_signature
+                         name: (property_identifier) @method.name)
+                        (method_definition
+                         name: (property_identifier) @method.name)])))
+ (#eq? @decorator.name "Autometrics"))
+
+;; Method decorators only instrument the decorated method.
+((class_declaration
+  name: (type_identifier) @type.name
+  body: (class_body
+         (decorator
+          [(identifier) @decorator.name
+           (call_expression
+            function: (identifier) @decorator.name
+            arguments: (arguments (object)? @decorator.options))]) @method.decorator
+         .
+         (decorator)*
+         .
+         (method_definition
+          name: (property_identifier) @method.name)))
+ (#eq? @decorator.name "Autometrics"))
@@ -0,0 +1,80 @@
+;; This query extracts the top-level definitions and the exports of the current source,
+;; to build the project-wide symbol table used to resolve imported functions.
+
+;; Top-level definitions
+(program
+ [(function_declaration name: (identifier) @definition.name)
+  (generator_function_declaration name: (identifier) @definition.name)
+  (class_declaration name: (type_identifier) @definition.name)
+  (lexical_declaration (variable_declarator name: (identifier) @definition.name))
+  (variable_declaration (variable_declarator name: (identifier) @definition.name))])
+
+(export_statement
+ declaration: [(function_declaration name: (identifier) @definition.name)
+               (generator_function_declaration name: (identifier) @definition.name)
+               (class_declaration name: (type_identifier) @definition.name)
+               (lexical_declaration (variable_declarator name: (identifier) @definition.name))
+               (variable_declaration (variable_declarator name: (identifier) @definition.name))])
+
+;; `export function foo() {}`, `export const foo = ...`
+(export_statement
+ !source
+ declaration: [(function_declaration name: (identifier) @export.local)
+               (generator_function_declaration name: (identifier) @export.local)
+               (class_declaration name: (type_identifier) @export.local)
+               (lexical_declaration (variable_declarator name: (identifier) @export.local))
+               (variable_declaration (variable_declarator name: (identifier) @export.local))])
+
+;; `export default function foo() {}`
+(export_statement
+ "default" @export.default
+ declaration: [(function_declaration name: (identifier) @export.local)
+               (generator_function_declaration name: (identifier) @export.local)
+               (class_declaration name: (type_identifier) @export.local)])
+
+;; `export default foo;`
+(export_statement
+ "default" @export.default
+ value: (identifier) @export.local)
+
+;; `export { foo }`
+(export_statement
+ !source
+ (export_clause
+  (export_specifier
+   name: (identifier) @export.local
+   !alias)))
+
+;; `export { foo as bar }`
+(export_statement
+ !source
+ (export_clause
+  (export_specifier
+   name: (identifier) @export.local
+   alias: (identifier) @export.name)))
+
+;; `export * from './module'`
+(export_statement
+ "*" @reexport.all
+ source: (string (string_fragment) @reexport.source))
+
+;; `export * as ns from './module'`
+(export_statement
+ (namespace_export (identifier) @reexport.namespace)
+ source: (string (string_fragment) @reexport.source))
+
+;; `export { foo } from './module'`
+(export_statement
+ (export_clause
+  (export_specifier
+   name: (identifier) @export.local
+   !alias))
+ source: (string (string_fragment) @reexport.source))
+
+;; `export { foo as bar } from './module'`
+(export_statement
+ (export_clause
+  (export_specifier
+   name: (identifier) @export.local
+   alias: (identifier) @export.name))
+ source: (string (string_fragment) @reexport.source))
@@ -1,29 +1,10 @@
+;; The options object gives the function and module names, defaulting to the
+;; name of the wrapped function and the current module.
 ((call_expression
-   function: (identifier) @wrapper.call
+   function: [(identifier) (member_expression)] @wrapper.call
    arguments: (arguments
                .
-               (object
-                (pair
-                 key: (property_identifier) @func.prop
-                 value: (string (string_fragment) @func.name))
-                (pair
-                 key: (property_identifier) @mod.prop
-                 value: (string (string_fragment) @module.name)))))
- (#eq? @wrapper.call "{0}")
- (#eq? @func.prop "functionName")
- (#eq? @mod.prop "moduleName"))
-
-((call_expression
-   function: (identifier) @wrapper.call
-   arguments: (arguments
+               (object) @wrapper.options
                .
-               (object
-                (pair
-                 key: (property_identifier) @mod.prop
-                 value: (string (string_fragment) @module.name))
-                (pair
-                 key: (property_identifier) @func.prop
-                 value: (string (string_fragment) @func.name)))))
- (#eq? @wrapper.call "{0}")
- (#eq? @func.prop "functionName")
- (#eq? @mod.prop "moduleName"))
+               (_) @func.name))
+ (#eq? @wrapper.call "{0}"))
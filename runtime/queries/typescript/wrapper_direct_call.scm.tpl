@@ -1,16 +1,22 @@
 ((call_expression
-   function: (identifier) @wrapper.call
-   arguments: (arguments (identifier) @func.name))
+   function: [(identifier) (member_expression)] @wrapper.call
+   arguments: (arguments
+               .
+               [(identifier) (member_expression)] @func.name))
  (#eq? @wrapper.call "{0}"))
 
 ((call_expression
-   function: (identifier) @wrapper.call
-   arguments: (arguments (member_expression) @func.name))
+   function: [(identifier) (member_expression)] @wrapper.call
+   arguments: (arguments
+               .
+               (function
+                 name: (identifier) @func.name)))
  (#eq? @wrapper.call "{0}"))
 
+;; Anonymous functions are named after the variable holding the wrapped function.
 ((call_expression
-   function: (identifier) @wrapper.call
+   function: [(identifier) (member_expression)] @wrapper.call
    arguments: (arguments
-                (function
-                  name: (identifier) @func.name)))
+               .
+               [(arrow_function) (function !name)] @func.name))
  (#eq? @wrapper.call "{0}"))
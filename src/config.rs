@@ -0,0 +1,233 @@
+//! Project-level configuration for am_list.
+//!
+//! The configuration lives in an `am_list.toml` file, usually at the root of
+//! the project, so that teams can commit their am_list setup instead of
+//! repeating long command lines:
+//!
+//! ```toml
+//! [rust]
+//! exclude = ["benches/**"]
+//! module_prefix = "my_crate::"
+//! min_coverage = 60.0
+//!
+//! [typescript]
+//! roots = ["frontend", "backend"]
+//! include = ["src/**"]
+//! wrappers = ["instrument"]
+//! ```
+//!
+//! Each language section is optional, and the presence of a section is what
+//! enables a language when no language is given on the command line.
+
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use walkdir::DirEntry;
+
+use crate::{AmlError, FunctionInfo, Result};
+
+/// Name of the configuration file discovered at the root of a project.
+pub const CONFIG_FILE_NAME: &str = "am_list.toml";
+
+/// Configuration of am_list for a project.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Directory containing user-supplied tree-sitter queries, relative to the
+    /// directory containing the configuration file.
+    pub queries_dir: Option<PathBuf>,
+    pub rust: Option<LanguageConfig>,
+    pub go: Option<LanguageConfig>,
+    pub typescript: Option<LanguageConfig>,
+    pub python: Option<LanguageConfig>,
+}
+
+impl Config {
+    /// Read the configuration file at the given path.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let contents = read_to_string(path).map_err(|err| AmlError::Io(path.to_path_buf(), err))?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Look for an `am_list.toml` file directly in the given directory.
+    pub fn discover(root: &Path) -> Result<Option<Self>> {
+        let path = root.join(CONFIG_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Self::from_path(&path).map(Some)
+    }
+
+    /// Load the configuration of the project at `project_root`: the file at
+    /// `path` if given, or else the configuration file discovered in the
+    /// project.
+    ///
+    /// The configuration is returned with the directory containing its file,
+    /// that the paths written in the configuration are relative to.
+    pub fn load(project_root: &Path, path: Option<&Path>) -> Result<(Self, PathBuf)> {
+        match path {
+            Some(path) => Ok((
+                Self::from_path(path)?,
+                path.parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."))
+                    .to_path_buf(),
+            )),
+            None => Ok((
+                Self::discover(project_root)?.unwrap_or_default(),
+                project_root.to_path_buf(),
+            )),
+        }
+    }
+}
+
+/// Configuration for a single language.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LanguageConfig {
+    /// Roots of the projects to scan, relative to the directory containing
+    /// the configuration file. Defaults to the project root given on the
+    /// command line only.
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    /// Glob patterns of the files to scan, relative to each root. Defaults to
+    /// all the files.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Glob patterns of the files and directories to skip, relative to each root.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Additional names of the autometrics decorator (Python only).
+    #[serde(default)]
+    pub decorators: Vec<String>,
+    /// Additional names of the autometrics wrapper function (Typescript only).
+    #[serde(default)]
+    pub wrappers: Vec<String>,
+    /// Report the path of the compiled files as modules, instead of the path of
+    /// the sources (Typescript only).
+    #[serde(default)]
+    pub compiled_modules: bool,
+    /// Build output directory containing the source maps of the compiled
+    /// files, relative to each root. Implies `compiled_modules` (Typescript only).
+    pub build_dir: Option<PathBuf>,
+    /// Kinds of the cargo targets to list, like `["lib", "bin"]` to only list
+    /// production code. Defaults to all the targets (Rust only).
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Skip the test code, like the `#[cfg(test)]` modules in Rust or the
+    /// `*_test.go` files in Go.
+    #[serde(default)]
+    pub exclude_tests: bool,
+    /// Target operating system for the build constraints. Defaults to `GOOS`,
+    /// or the host system (Go only).
+    pub goos: Option<String>,
+    /// Target architecture for the build constraints. Defaults to `GOARCH`,
+    /// or the host architecture (Go only).
+    pub goarch: Option<String>,
+    /// Build tags satisfied by the build constraints (Go only).
+    #[serde(default)]
+    pub build_tags: Vec<String>,
+    /// Prefix to prepend verbatim to all the reported module names.
+    pub module_prefix: Option<String>,
+    /// Minimum percentage of functions that must be instrumented.
+    pub min_coverage: Option<f64>,
+}
+
+impl LanguageConfig {
+    /// Return the roots to scan: the configured ones resolved against
+    /// `config_dir`, the directory containing the configuration file, or
+    /// else the `project_root` itself.
+    pub fn roots(&self, project_root: &Path, config_dir: &Path) -> Vec<PathBuf> {
+        if self.roots.is_empty() {
+            vec![project_root.to_path_buf()]
+        } else {
+            self.roots
+                .iter()
+                .map(|root| config_dir.join(root))
+                .collect()
+        }
+    }
+
+    /// Build the filter matching the `include` and `exclude` patterns.
+    pub fn file_filter(&self) -> Result<FileFilter> {
+        FileFilter::new(&self.include, &self.exclude)
+    }
+
+    /// Prepend the configured module prefix to the module of all the functions.
+    pub fn apply_module_prefix(&self, functions: &mut [FunctionInfo]) {
+        if let Some(prefix) = &self.module_prefix {
+            for function in functions {
+                function.id.module.insert_str(0, prefix);
+            }
+        }
+    }
+}
+
+/// Filter on the files to scan, built from lists of glob patterns.
+///
+/// The patterns are matched against paths relative to the root of the project.
+#[derive(Clone, Debug, Default)]
+pub struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl FileFilter {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if one of the patterns is not a valid glob.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        fn build(patterns: &[String]) -> Result<Option<GlobSet>> {
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(Glob::new(pattern)?);
+            }
+            Ok(Some(builder.build()?))
+        }
+
+        Ok(Self {
+            include: build(include)?,
+            exclude: build(exclude)?,
+        })
+    }
+
+    /// Return true if the walked entry should be scanned.
+    pub fn accepts(&self, project_root: &Path, entry: &DirEntry) -> bool {
+        match entry.path().strip_prefix(project_root) {
+            Ok(relative) => self.accepts_path(relative, entry.file_type().is_dir()),
+            Err(_) => true,
+        }
+    }
+
+    /// Return true if the path, relative to the project root, should be scanned.
+    ///
+    /// Directories are only rejected when they match an `exclude` pattern, so
+    /// that the `include` patterns can still match files deeper in the tree.
+    pub fn accepts_path(&self, relative: &Path, is_dir: bool) -> bool {
+        if relative.as_os_str().is_empty() {
+            return true;
+        }
+        if self
+            .exclude
+            .as_ref()
+            .map_or(false, |exclude| exclude.is_match(relative))
+        {
+            return false;
+        }
+        is_dir
+            || self
+                .include
+                .as_ref()
+                .map_or(true, |include| include.is_match(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,129 @@
+use super::*;
+use crate::test_utils::test_project;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn parse_config() {
+    let contents = r#"
+        [rust]
+        exclude = ["benches/**"]
+        module_prefix = "my_crate::"
+        min_coverage = 60.0
+
+        [typescript]
+        roots = ["frontend", "backend"]
+        include = ["src/**"]
+        wrappers = ["instrument"]
+        "#;
+
+    let config: Config = toml::from_str(contents).unwrap();
+
+    assert_eq!(
+        config.rust,
+        Some(LanguageConfig {
+            exclude: vec!["benches/**".to_string()],
+            module_prefix: Some("my_crate::".to_string()),
+            min_coverage: Some(60.0),
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        config.typescript,
+        Some(LanguageConfig {
+            roots: vec![PathBuf::from("frontend"), PathBuf::from("backend")],
+            include: vec!["src/**".to_string()],
+            wrappers: vec!["instrument".to_string()],
+            ..Default::default()
+        })
+    );
+    assert_eq!(config.go, None);
+    assert_eq!(config.python, None);
+
+    let project_root = Path::new("/project");
+    assert_eq!(
+        config.typescript.unwrap().roots(project_root, project_root),
+        vec![
+            PathBuf::from("/project/frontend"),
+            PathBuf::from("/project/backend")
+        ]
+    );
+    assert_eq!(
+        config.rust.unwrap().roots(project_root, project_root),
+        vec![PathBuf::from("/project")]
+    );
+}
+
+#[test]
+fn load_config_outside_of_project() {
+    let dir = test_project(&[
+        (
+            "service/am_list.toml",
+            "[go]\nmodule_prefix = \"discovered.\"\n",
+        ),
+        (
+            "ci/am_list.toml",
+            r#"
+            queries_dir = "queries"
+
+            [rust]
+
+            [typescript]
+            roots = ["frontend"]
+            "#,
+        ),
+    ]);
+    let project_root = dir.path().join("service");
+    let config_path = dir.path().join("ci/am_list.toml");
+
+    let (config, config_dir) = Config::load(&project_root, Some(&config_path)).unwrap();
+    assert_eq!(
+        config.go, None,
+        "the given file replaces the discovered one"
+    );
+    assert_eq!(config_dir, dir.path().join("ci"));
+    assert_eq!(
+        config.rust.unwrap().roots(&project_root, &config_dir),
+        vec![project_root.clone()],
+        "the default root is the project root, not the directory of the file"
+    );
+    assert_eq!(
+        config.typescript.unwrap().roots(&project_root, &config_dir),
+        vec![dir.path().join("ci/frontend")]
+    );
+
+    let (config, config_dir) = Config::load(&project_root, None).unwrap();
+    assert_eq!(config_dir, project_root);
+    assert_eq!(
+        config.go.unwrap().module_prefix,
+        Some("discovered.".to_string())
+    );
+}
+
+#[test]
+fn reject_unknown_keys() {
+    let contents = r#"
+        [rust]
+        exclude_dirs = ["benches"]
+        "#;
+
+    assert!(toml::from_str::<Config>(contents).is_err());
+}
+
+#[test]
+fn filter_files() {
+    let filter = FileFilter::new(
+        &["src/**/*.rs".to_string()],
+        &["**/generated".to_string(), "**/generated/**".to_string()],
+    )
+    .unwrap();
+
+    assert!(filter.accepts_path(Path::new("src"), true));
+    assert!(filter.accepts_path(Path::new("src/lib.rs"), false));
+    assert!(filter.accepts_path(Path::new("src/foo/bar.rs"), false));
+    assert!(!filter.accepts_path(Path::new("build.rs"), false));
+    assert!(!filter.accepts_path(Path::new("src/generated"), true));
+    assert!(!filter.accepts_path(Path::new("src/generated/api.rs"), false));
+
+    let default_filter = FileFilter::default();
+    assert!(default_filter.accepts_path(Path::new("build.rs"), false));
+}
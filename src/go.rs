@@ -1,6 +1,14 @@
+mod constraints;
+mod generated;
 mod queries;
 
-use crate::{FunctionInfo, ListAmFunctions, Result};
+pub use constraints::BuildContext;
+
+use crate::{
+    config::FileFilter, user_queries::UserQueries, ExcludedFile, FunctionInfo, Lint,
+    ListAmFunctions, Location, Result, SyntaxErrors,
+};
+use log::{info, warn};
 use queries::{AllFunctionsQuery, AmQuery};
 use rayon::prelude::*;
 use std::{
@@ -11,8 +19,20 @@ use std::{
 use walkdir::{DirEntry, WalkDir};
 
 /// Implementation of the Go support for listing autometricized functions.
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Impl {}
+#[derive(Clone, Debug, Default)]
+pub struct Impl {
+    /// Filter on the files to scan in the project.
+    pub filter: FileFilter,
+    /// User-supplied queries that override or extend the built-in ones.
+    pub queries: UserQueries,
+    /// Skip the test files (`*_test.go`).
+    pub exclude_tests: bool,
+    /// Target of the build, to skip the files excluded by their build
+    /// constraints.
+    pub build: BuildContext,
+    /// Syntax errors found in the files parsed by the listings.
+    pub syntax_errors: SyntaxErrors,
+}
 
 impl Impl {
     fn is_hidden(entry: &DirEntry) -> bool {
@@ -23,8 +43,19 @@ impl Impl {
             .unwrap_or(false)
     }
 
+    /// Return true if the `entry` is a directory the go tool ignores: the
+    /// `vendor` and `testdata` directories, and the directories starting with
+    /// an underscore. The project root itself is never ignored.
+    fn is_ignored_dir(entry: &DirEntry) -> bool {
+        entry.depth() > 0
+            && entry.file_type().is_dir()
+            && entry.file_name().to_str().map_or(false, |s| {
+                s == "vendor" || s == "testdata" || s.starts_with('_')
+            })
+    }
+
     fn is_valid(entry: &DirEntry) -> bool {
-        if Impl::is_hidden(entry) {
+        if Impl::is_hidden(entry) || Impl::is_ignored_dir(entry) {
             return false;
         }
         entry.file_type().is_dir()
@@ -34,6 +65,54 @@ impl Impl {
                 .map(|s| s.ends_with(".go"))
                 .unwrap_or(false)
     }
+
+    /// Return true if the `entry` is a test file (`*_test.go`).
+    fn is_test_file(entry: &DirEntry) -> bool {
+        entry.file_type().is_file()
+            && entry
+                .file_name()
+                .to_str()
+                .map_or(false, |s| s.ends_with("_test.go"))
+    }
+
+    /// Return true if the walked `entry` must be scanned.
+    fn accepts(&self, project_root: &Path, entry: &DirEntry) -> bool {
+        Self::is_valid(entry)
+            && !(self.exclude_tests && Self::is_test_file(entry))
+            && self.filter.accepts(project_root, entry)
+    }
+
+    /// List the paths of the source files of the project.
+    ///
+    /// The build constraints of the files are only known once they are read,
+    /// see [`Self::read_built_source`].
+    fn source_paths(&self, project_root: &Path) -> Vec<String> {
+        WalkDir::new(project_root)
+            .into_iter()
+            .filter_entry(|entry| self.accepts(project_root, entry))
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                Some(
+                    entry
+                        .path()
+                        .to_str()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    /// Read the source file at `path`, unless it is excluded from the `build`
+    /// by its build constraints.
+    fn read_built_source(build: &BuildContext, path: &str) -> Option<String> {
+        let source = read_to_string(path).ok()?;
+        let file_name = Path::new(path).file_name()?.to_str()?;
+        build
+            .excluding_constraint(file_name, &source)
+            .is_none()
+            .then_some(source)
+    }
 }
 
 impl ListAmFunctions for Impl {
@@ -41,28 +120,18 @@ impl ListAmFunctions for Impl {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
 
-        let walker = WalkDir::new(project_root).into_iter();
-        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            Some(
-                entry
-                    .path()
-                    .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-            )
-        }));
+        let source_mod_pairs = self.source_paths(project_root);
 
+        let query = AmQuery::try_new(&self.queries, &self.syntax_errors)?;
+        let build = &self.build;
         list.par_extend(source_mod_pairs.par_iter().filter_map(move |path| {
-            let source = read_to_string(path).ok()?;
+            let source = Self::read_built_source(build, path)?;
             let file_name = PathBuf::from(path)
                 .strip_prefix(project_root)
                 .expect("path comes from a project_root WalkDir")
                 .to_str()
                 .expect("file_name is a valid path as it is part of `path`")
                 .to_string();
-            let query = AmQuery::try_new().ok()?;
             let names = query
                 .list_function_names(&file_name, &source)
                 .unwrap_or_default();
@@ -78,28 +147,18 @@ impl ListAmFunctions for Impl {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
 
-        let walker = WalkDir::new(project_root).into_iter();
-        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            Some(
-                entry
-                    .path()
-                    .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-            )
-        }));
+        let source_mod_pairs = self.source_paths(project_root);
 
+        let query = AllFunctionsQuery::try_new(&self.queries, &self.syntax_errors)?;
+        let build = &self.build;
         list.par_extend(source_mod_pairs.par_iter().filter_map(move |path| {
-            let source = read_to_string(path).ok()?;
+            let source = Self::read_built_source(build, path)?;
             let file_name = PathBuf::from(path)
                 .strip_prefix(project_root)
                 .expect("path comes from a project_root WalkDir")
                 .to_str()
                 .expect("file_name is a valid path as it is part of `path`")
                 .to_string();
-            let query = AllFunctionsQuery::try_new().ok()?;
             let names = query
                 .list_function_names(&file_name, &source)
                 .unwrap_or_default();
@@ -110,6 +169,64 @@ impl ListAmFunctions for Impl {
         result.extend(list.into_iter().flatten());
         Ok(result)
     }
+
+    fn list_syntax_errors(&mut self) -> Vec<Location> {
+        self.syntax_errors.take()
+    }
+
+    fn list_lints(&mut self, project_root: &Path) -> Result<Vec<Lint>> {
+        let query = AmQuery::try_new(&self.queries, &self.syntax_errors)?;
+        let source_paths = self.source_paths(project_root);
+
+        let mut lints: Vec<Lint> = source_paths
+            .par_iter()
+            .filter_map(|path| {
+                let source = Self::read_built_source(&self.build, path)?;
+                let file_name = PathBuf::from(path)
+                    .strip_prefix(project_root)
+                    .expect("path comes from a project_root WalkDir")
+                    .to_str()
+                    .expect("file_name is a valid path as it is part of `path`")
+                    .to_string();
+                query.list_lints(&file_name, &source).ok()
+            })
+            .flatten()
+            .collect();
+
+        lints.sort();
+        for lint in &lints {
+            warn!("{lint}");
+        }
+        Ok(lints)
+    }
+
+    fn list_excluded_files(&mut self, project_root: &Path) -> Result<Vec<ExcludedFile>> {
+        let mut excluded: Vec<ExcludedFile> = self
+            .source_paths(project_root)
+            .par_iter()
+            .filter_map(|path| {
+                let source = read_to_string(path).ok()?;
+                let constraint = self
+                    .build
+                    .excluding_constraint(Path::new(path).file_name()?.to_str()?, &source)?;
+                let file = Path::new(path)
+                    .strip_prefix(project_root)
+                    .expect("path comes from a project_root WalkDir")
+                    .to_str()?
+                    .to_string();
+                Some(ExcludedFile { file, constraint })
+            })
+            .collect();
+
+        excluded.sort();
+        for file in &excluded {
+            info!(
+                "{} is excluded from the build by {}",
+                file.file, file.constraint
+            );
+        }
+        Ok(excluded)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,304 @@
+//! Build constraints of Go files.
+//!
+//! Like the go tool, a file is only part of the build when:
+//! - its `//go:build` line (or, in older files, its `// +build` lines) holds
+//!   for the target operating system, architecture and build tags, and
+//! - the `_GOOS`, `_GOARCH` or `_GOOS_GOARCH` suffix of its name, if any,
+//!   matches the target.
+
+/// Operating systems known to the go tool, that can appear as a file name
+/// suffix.
+const KNOWN_OS: [&str; 17] = [
+    "aix",
+    "android",
+    "darwin",
+    "dragonfly",
+    "freebsd",
+    "hurd",
+    "illumos",
+    "ios",
+    "js",
+    "linux",
+    "nacl",
+    "netbsd",
+    "openbsd",
+    "plan9",
+    "solaris",
+    "wasip1",
+    "windows",
+];
+/// Operating systems that satisfy the `unix` build tag.
+const UNIX_OS: [&str; 12] = [
+    "aix",
+    "android",
+    "darwin",
+    "dragonfly",
+    "freebsd",
+    "hurd",
+    "illumos",
+    "ios",
+    "linux",
+    "netbsd",
+    "openbsd",
+    "solaris",
+];
+/// Architectures known to the go tool, that can appear as a file name suffix.
+const KNOWN_ARCH: [&str; 20] = [
+    "386", "amd64", "arm", "arm64", "loong64", "mips", "mips64", "mips64le", "mipsle", "ppc64",
+    "ppc64le", "riscv64", "s390x", "wasm", "amd64p32", "armbe", "arm64be", "ppc", "riscv",
+    "sparc64",
+];
+
+/// The target of the build the files are listed for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildContext {
+    /// Target operating system, like `linux`.
+    pub goos: String,
+    /// Target architecture, like `amd64`.
+    pub goarch: String,
+    /// Additional build tags, like the `-tags` option of the go tool.
+    pub tags: Vec<String>,
+}
+
+impl Default for BuildContext {
+    /// The context of the go tool: the `GOOS` and `GOARCH` environment
+    /// variables, or the host system.
+    fn default() -> Self {
+        let goos = std::env::var("GOOS").unwrap_or_else(|_| {
+            match std::env::consts::OS {
+                "macos" => "darwin",
+                os => os,
+            }
+            .to_string()
+        });
+        let goarch = std::env::var("GOARCH").unwrap_or_else(|_| {
+            match std::env::consts::ARCH {
+                "x86_64" => "amd64",
+                "x86" => "386",
+                "aarch64" => "arm64",
+                "powerpc64" => "ppc64",
+                "loongarch64" => "loong64",
+                arch => arch,
+            }
+            .to_string()
+        });
+        Self {
+            goos,
+            goarch,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl BuildContext {
+    /// Return the constraint that excludes the file named `file_name` with the
+    /// given `source` from the build, if any.
+    pub fn excluding_constraint(&self, file_name: &str, source: &str) -> Option<String> {
+        if let Some(suffix) = self.mismatched_suffix(file_name) {
+            return Some(format!("file name suffix _{suffix}"));
+        }
+        let constraint = header_constraint(source)?;
+        (!self.matches(&constraint)).then(|| constraint.to_string())
+    }
+
+    /// Return true if the build tag `tag` is satisfied.
+    fn has_tag(&self, tag: &str) -> bool {
+        tag == self.goos
+            || tag == self.goarch
+            || (tag == "unix" && UNIX_OS.contains(&self.goos.as_str()))
+            // Android and iOS builds also use the linux and darwin files.
+            || (tag == "linux" && self.goos == "android")
+            || (tag == "darwin" && self.goos == "ios")
+            || (tag == "solaris" && self.goos == "illumos")
+            || tag == "gc"
+            // The sources are assumed to target a recent enough Go release.
+            || tag.strip_prefix("go1.").map_or(false, |minor| minor.parse::<u32>().is_ok())
+            || self.tags.iter().any(|known| known == tag)
+    }
+
+    /// Return the `GOOS`, `GOARCH` or `GOOS_GOARCH` suffix of the file name,
+    /// if it does not match the context.
+    fn mismatched_suffix(&self, file_name: &str) -> Option<String> {
+        let stem = file_name.strip_suffix(".go").unwrap_or(file_name);
+        let stem = stem.strip_suffix("_test").unwrap_or(stem);
+        let parts: Vec<&str> = stem.split('_').collect();
+        // A file named like `linux.go` has no constraint, only the suffixes do.
+        let suffix = match parts.as_slice() {
+            [.., _, os, arch] if KNOWN_OS.contains(os) && KNOWN_ARCH.contains(arch) => {
+                vec![*os, *arch]
+            }
+            [.., _, last] if KNOWN_OS.contains(last) || KNOWN_ARCH.contains(last) => vec![*last],
+            _ => return None,
+        };
+        (!suffix.iter().all(|tag| self.has_tag(tag))).then(|| suffix.join("_"))
+    }
+
+    /// Evaluate a constraint.
+    fn matches(&self, constraint: &Constraint) -> bool {
+        match constraint {
+            Constraint::Expression(expression) => Parser::new(expression)
+                .parse()
+                .map_or(true, |expr| expr.eval(self)),
+            Constraint::Legacy(lines) => lines.iter().all(|line| {
+                // Options separated by spaces are alternatives, and terms
+                // separated by commas must all hold.
+                line.split_whitespace().any(|option| {
+                    option.split(',').all(|term| match term.strip_prefix('!') {
+                        Some(tag) => !self.has_tag(tag),
+                        None => self.has_tag(term),
+                    })
+                })
+            }),
+        }
+    }
+}
+
+/// The build constraint of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Constraint {
+    /// The expression of a `//go:build` line.
+    Expression(String),
+    /// The contents of the `// +build` lines.
+    Legacy(Vec<String>),
+}
+
+impl std::fmt::Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constraint::Expression(expression) => write!(f, "//go:build {expression}"),
+            Constraint::Legacy(lines) => {
+                let lines: Vec<String> = lines
+                    .iter()
+                    .map(|line| format!("// +build {line}"))
+                    .collect();
+                f.write_str(&lines.join("; "))
+            }
+        }
+    }
+}
+
+/// Return the build constraint in the header of a file, before the package
+/// clause.
+///
+/// The `//go:build` line takes precedence over the `// +build` lines.
+fn header_constraint(source: &str) -> Option<Constraint> {
+    let mut expression = None;
+    let mut legacy = Vec::new();
+    let mut in_block_comment = false;
+    for line in source.lines() {
+        let line = line.trim();
+        if in_block_comment {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with("/*") {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+        let Some(comment) = line.strip_prefix("//") else {
+            // The header ends with the package clause.
+            break;
+        };
+        if let Some(rest) = comment.strip_prefix("go:build") {
+            if rest.starts_with(char::is_whitespace) {
+                expression.get_or_insert_with(|| rest.trim().to_string());
+            }
+        } else if let Some(rest) = comment.trim_start().strip_prefix("+build") {
+            if rest.starts_with(char::is_whitespace) {
+                legacy.push(rest.trim().to_string());
+            }
+        }
+    }
+    match expression {
+        Some(expression) => Some(Constraint::Expression(expression)),
+        None if !legacy.is_empty() => Some(Constraint::Legacy(legacy)),
+        None => None,
+    }
+}
+
+/// A parsed `//go:build` expression.
+#[derive(Debug)]
+enum Expr {
+    Tag(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, context: &BuildContext) -> bool {
+        match self {
+            Expr::Tag(tag) => context.has_tag(tag),
+            Expr::Not(inner) => !inner.eval(context),
+            Expr::And(lhs, rhs) => lhs.eval(context) && rhs.eval(context),
+            Expr::Or(lhs, rhs) => lhs.eval(context) || rhs.eval(context),
+        }
+    }
+}
+
+/// Recursive descent parser of the `//go:build` expressions, where `!` binds
+/// tighter than `&&`, which binds tighter than `||`.
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &'a str) -> Self {
+        Self { rest: expression }
+    }
+
+    /// Parse the whole expression, or return `None` if it is invalid.
+    fn parse(mut self) -> Option<Expr> {
+        let expr = self.or()?;
+        self.rest.trim().is_empty().then_some(expr)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let trimmed = self.rest.trim_start();
+        match trimmed.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn or(&mut self) -> Option<Expr> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Some(expr)
+    }
+
+    fn and(&mut self) -> Option<Expr> {
+        let mut expr = self.not()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Some(expr)
+    }
+
+    fn not(&mut self) -> Option<Expr> {
+        if self.eat("!") {
+            return Some(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            return self.eat(")").then_some(expr);
+        }
+        let trimmed = self.rest.trim_start();
+        let end = trimmed
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(trimmed.len());
+        if end == 0 {
+            return None;
+        }
+        self.rest = &trimmed[end..];
+        Some(Expr::Tag(trimmed[..end].to_string()))
+    }
+}
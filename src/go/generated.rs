@@ -0,0 +1,156 @@
+//! Instrumentation code generated by `go generate`.
+//!
+//! autometrics-go does not instrument the functions annotated with
+//! `//autometrics:inst` by itself: running `go generate` adds a
+//! `defer metrics.Instrument(...)` statement at the start of their body, and
+//! keeps it in sync with the arguments of the directive. Forgetting to run the
+//! generator leaves a function uninstrumented, or instrumented with outdated
+//! labels and objectives.
+
+use tree_sitter::Node;
+
+/// Directive that asks the generator to instrument a function.
+pub const INSTRUMENT_DIRECTIVE: &str = "//autometrics:inst";
+/// Name of the function called by the generated instrumentation.
+const INSTRUMENT_FUNCTION: &str = "Instrument";
+/// Options of the generated call that bake the module label.
+const MODULE_OPTIONS: [&str; 2] = ["WithModuleName", "WithModule"];
+/// Options of the generated call that bake the function label.
+const FUNCTION_OPTIONS: [&str; 2] = ["WithFunctionName", "WithFunction"];
+/// Option of the generated call that sets the name of the objective.
+const SLO_OPTION: &str = "WithSloName";
+/// Argument of the directive that sets the name of the objective.
+const SLO_ARGUMENT: &str = "--slo";
+
+/// The generated instrumentation of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedCall<'tree> {
+    /// The `defer` statement of the instrumentation.
+    pub node: Node<'tree>,
+    /// The module label baked in the call, if any.
+    pub module: Option<String>,
+    /// The function label baked in the call, if any.
+    pub function: Option<String>,
+    /// The name of the objective of the call, if any.
+    pub slo: Option<String>,
+}
+
+impl<'tree> GeneratedCall<'tree> {
+    /// Find the generated instrumentation among the statements of the `body`
+    /// of a function.
+    pub fn find(body: Node<'tree>, source: &str) -> Option<Self> {
+        let mut cursor = body.walk();
+        let statement = body
+            .named_children(&mut cursor)
+            .filter(|statement| statement.kind() == "defer_statement")
+            .find(|statement| {
+                statement
+                    .named_child(0)
+                    .and_then(|call| call_name(call, source))
+                    == Some(INSTRUMENT_FUNCTION)
+            })?;
+
+        let mut call = Self {
+            node: statement,
+            module: None,
+            function: None,
+            slo: None,
+        };
+        call.read_options(statement, source);
+        Some(call)
+    }
+
+    /// Read the options passed to the calls nested in `node`.
+    fn read_options(&mut self, node: Node, source: &str) {
+        if let Some(name) = call_name(node, source) {
+            let value = || first_string_argument(node, source);
+            if MODULE_OPTIONS.contains(&name) {
+                self.module = value();
+            } else if FUNCTION_OPTIONS.contains(&name) {
+                self.function = value();
+            } else if name == SLO_OPTION {
+                self.slo = value();
+            }
+        }
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.read_options(child, source);
+        }
+    }
+
+    /// Describe the differences between the generated call and what the
+    /// generator would produce for the function `module.function` annotated
+    /// with `directive`.
+    pub fn stale_parts(&self, directive: &str, module: &str, function: &str) -> Vec<String> {
+        let mut parts = Vec::new();
+        if let Some(baked) = self.module.as_deref().filter(|baked| *baked != module) {
+            parts.push(format!(
+                "reports the module as `{baked}` instead of `{module}`"
+            ));
+        }
+        if let Some(baked) = self.function.as_deref().filter(|baked| *baked != function) {
+            parts.push(format!(
+                "reports the function as `{baked}` instead of `{function}`"
+            ));
+        }
+        match (self.slo.as_deref(), directive_slo(directive).as_deref()) {
+            (Some(generated), Some(expected)) if generated != expected => parts.push(format!(
+                "uses the objective `{generated}` instead of `{expected}`"
+            )),
+            (Some(generated), None) => {
+                parts.push(format!("uses the removed objective `{generated}`"))
+            }
+            (None, Some(expected)) => parts.push(format!("lacks the objective `{expected}`")),
+            _ => {}
+        }
+        parts
+    }
+}
+
+/// Return the name of the function called by `node`, without its package, if
+/// `node` is a call.
+fn call_name<'a>(node: Node, source: &'a str) -> Option<&'a str> {
+    if node.kind() != "call_expression" {
+        return None;
+    }
+    let function = node.child_by_field_name("function")?;
+    let name = match function.kind() {
+        "selector_expression" => function.child_by_field_name("field")?,
+        _ => function,
+    };
+    name.utf8_text(source.as_bytes()).ok()
+}
+
+/// Return the value of the first argument of a call, if it is a string
+/// literal.
+fn first_string_argument(call: Node, source: &str) -> Option<String> {
+    let argument = call.child_by_field_name("arguments")?.named_child(0)?;
+    if !matches!(
+        argument.kind(),
+        "interpreted_string_literal" | "raw_string_literal"
+    ) {
+        return None;
+    }
+    let text = argument.utf8_text(source.as_bytes()).ok()?;
+    Some(text[1..text.len() - 1].to_string())
+}
+
+/// Return the name of the objective given to the `--slo` argument of a
+/// directive.
+fn directive_slo(directive: &str) -> Option<String> {
+    let mut arguments = directive
+        .strip_prefix(INSTRUMENT_DIRECTIVE)?
+        .split_whitespace();
+    while let Some(argument) = arguments.next() {
+        let value = match argument.strip_prefix(SLO_ARGUMENT) {
+            Some("") => arguments.next()?,
+            Some(value) => match value.strip_prefix('=') {
+                Some(value) => value,
+                None => continue,
+            },
+            None => continue,
+        };
+        return Some(value.trim_matches('"').to_string());
+    }
+    None
+}
@@ -1,11 +1,71 @@
-use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
+use super::generated::{GeneratedCall, INSTRUMENT_DIRECTIVE};
+use crate::{
+    user_queries::UserQueries, AmlError, FunctionInfo, Lint, Location, Result, SyntaxErrors,
+    FUNC_NAME_CAPTURE,
+};
 use log::error;
-use tree_sitter::{Parser, Query};
+use tree_sitter::{Node, Parser, Query};
 use tree_sitter_go::language;
 
 const PACK_NAME_CAPTURE: &str = "pack.name";
+const DIRECTIVE_CAPTURE: &str = "dir.comment";
+const RECEIVER_CAPTURE: &str = "method.receiver";
 
-fn new_parser() -> Result<Parser> {
+/// Functions run by the Go runtime instead of being called by the code, that
+/// cannot be meaningfully instrumented: `init` functions in any package, and
+/// the `main` function of the `main` package.
+fn is_entry_point(package: &str, function: &str) -> bool {
+    function == "init" || (package == "main" && function == "main")
+}
+
+/// Return the name of the function named by `fn_node`.
+///
+/// Methods are named like the Go runtime names them, which is where
+/// autometrics-go reads the function label from: `Server.Serve` for a value
+/// receiver, `(*Server).Stop` for a pointer receiver, and with `[...]` in place
+/// of the type parameters of a generic receiver, so that
+/// `func (s *Stack[T]) Push` is `(*Stack[...]).Push`.
+fn function_name(
+    fn_node: Node,
+    receiver: Option<Node>,
+    source: &str,
+) -> std::result::Result<String, std::str::Utf8Error> {
+    let name = fn_node.utf8_text(source.as_bytes())?;
+    let Some(mut receiver) = receiver else {
+        return Ok(name.to_string());
+    };
+    let mut is_pointer = false;
+    let mut is_generic = false;
+    loop {
+        let inner = match receiver.kind() {
+            "parenthesized_type" => receiver.named_child(0),
+            "pointer_type" => {
+                is_pointer = true;
+                receiver.named_child(0)
+            }
+            "generic_type" => {
+                is_generic = true;
+                receiver.child_by_field_name("type")
+            }
+            _ => None,
+        };
+        match inner {
+            Some(inner) => receiver = inner,
+            None => break,
+        }
+    }
+    let mut receiver = receiver.utf8_text(source.as_bytes())?.to_string();
+    if is_generic {
+        receiver.push_str("[...]");
+    }
+    if is_pointer {
+        Ok(format!("(*{receiver}).{name}"))
+    } else {
+        Ok(format!("{receiver}.{name}"))
+    }
+}
+
+pub(super) fn new_parser() -> Result<Parser> {
     let mut parser = Parser::new();
     parser.set_language(language())?;
     Ok(parser)
@@ -19,35 +79,47 @@ pub(super) struct AmQuery {
     func_name_idx: u32,
     /// Index of the capture for the package name.
     mod_name_idx: u32,
+    /// Index of the capture for the receiver type of a method.
+    receiver_idx: u32,
+    /// Index of the capture for the autometrics directive of a function.
+    directive_idx: u32,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AmQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(user_queries: &UserQueries, syntax_errors: &SyntaxErrors) -> Result<Self> {
+        let loaded = user_queries.query(
             language(),
+            "go/autometrics.scm",
             include_str!("../../runtime/queries/go/autometrics.scm"),
+            &[],
         )?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
-        let mod_name_idx = query
-            .capture_index_for_name(PACK_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(PACK_NAME_CAPTURE.to_string()))?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let mod_name_idx = loaded.capture_index(PACK_NAME_CAPTURE)?;
+        let receiver_idx = loaded.capture_index(RECEIVER_CAPTURE)?;
+        let directive_idx = loaded.capture_index(DIRECTIVE_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
             func_name_idx,
             mod_name_idx,
+            receiver_idx,
+            directive_idx,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
     pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
 
         let mut cursor = tree_sitter::QueryCursor::new();
         cursor
@@ -58,9 +130,8 @@ impl AmQuery {
                     .next()
                     .map(|node| node.utf8_text(source.as_bytes()).map(ToString::to_string))?;
                 let fn_node = capture.nodes_for_capture_index(self.func_name_idx).next()?;
-                let fn_name = fn_node
-                    .utf8_text(source.as_bytes())
-                    .map(ToString::to_string);
+                let receiver = capture.nodes_for_capture_index(self.receiver_idx).next();
+                let fn_name = function_name(fn_node, receiver, source);
                 let start = fn_node.start_position();
                 let end = fn_node.end_position();
                 let instrumentation = Some(Location::from((file_name, start, end)));
@@ -71,6 +142,7 @@ impl AmQuery {
                         id: (module, function).into(),
                         instrumentation,
                         definition,
+                        ..Default::default()
                     })),
                     (Err(err_mod), _) => {
                         error!("could not fetch the package name: {err_mod}");
@@ -84,6 +156,68 @@ impl AmQuery {
             })
             .collect::<std::result::Result<Vec<_>, _>>()
     }
+
+    /// List the functions annotated with `//autometrics:inst` whose code
+    /// generated by `go generate` is missing, or does not match the directive
+    /// and the labels of the function anymore.
+    pub fn list_lints(&self, file_name: &str, source: &str) -> Result<Vec<Lint>> {
+        let mut parser = new_parser()?;
+        let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
+
+        let mut cursor = tree_sitter::QueryCursor::new();
+        Ok(cursor
+            .matches(&self.query, parsed_source.root_node(), source.as_bytes())
+            .filter_map(|capture| -> Option<Lint> {
+                let directive = capture
+                    .nodes_for_capture_index(self.directive_idx)
+                    .next()?
+                    .utf8_text(source.as_bytes())
+                    .ok()?;
+                if !directive.starts_with(INSTRUMENT_DIRECTIVE) {
+                    return None;
+                }
+                let module = capture
+                    .nodes_for_capture_index(self.mod_name_idx)
+                    .next()?
+                    .utf8_text(source.as_bytes())
+                    .ok()?;
+                let fn_node = capture.nodes_for_capture_index(self.func_name_idx).next()?;
+                let receiver = capture.nodes_for_capture_index(self.receiver_idx).next();
+                let function = function_name(fn_node, receiver, source).ok()?;
+                let body = fn_node.parent()?.child_by_field_name("body")?;
+
+                let (node, message) = match GeneratedCall::find(body, source) {
+                    None => (
+                        fn_node,
+                        "the generated instrumentation is missing (run `go generate`)".to_string(),
+                    ),
+                    Some(call) => {
+                        let stale_parts = call.stale_parts(directive, module, &function);
+                        if stale_parts.is_empty() {
+                            return None;
+                        }
+                        (
+                            call.node,
+                            format!(
+                                "the generated instrumentation is stale: it {} (run `go generate`)",
+                                stale_parts.join(", ")
+                            ),
+                        )
+                    }
+                };
+                Some(Lint {
+                    function: (module, function).into(),
+                    location: Location::from((
+                        file_name,
+                        node.start_position(),
+                        node.end_position(),
+                    )),
+                    message,
+                })
+            })
+            .collect())
+    }
 }
 
 /// Query wrapper for "all functions in source"
@@ -94,35 +228,43 @@ pub(super) struct AllFunctionsQuery {
     func_name_idx: u32,
     /// Index of the capture for the package name.
     mod_name_idx: u32,
+    /// Index of the capture for the receiver type of a method.
+    receiver_idx: u32,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AllFunctionsQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(user_queries: &UserQueries, syntax_errors: &SyntaxErrors) -> Result<Self> {
+        let loaded = user_queries.query(
             language(),
+            "go/all_functions.scm",
             include_str!("../../runtime/queries/go/all_functions.scm"),
+            &[],
         )?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
-        let mod_name_idx = query
-            .capture_index_for_name(PACK_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(PACK_NAME_CAPTURE.to_string()))?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let mod_name_idx = loaded.capture_index(PACK_NAME_CAPTURE)?;
+        let receiver_idx = loaded.capture_index(RECEIVER_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
             func_name_idx,
             mod_name_idx,
+            receiver_idx,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
     pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
 
         let mut cursor = tree_sitter::QueryCursor::new();
         cursor
@@ -133,19 +275,20 @@ impl AllFunctionsQuery {
                     .next()
                     .map(|node| node.utf8_text(source.as_bytes()).map(ToString::to_string))?;
                 let fn_node = capture.nodes_for_capture_index(self.func_name_idx).next()?;
-                let fn_name = fn_node
-                    .utf8_text(source.as_bytes())
-                    .map(ToString::to_string);
+                let receiver = capture.nodes_for_capture_index(self.receiver_idx).next();
+                let fn_name = function_name(fn_node, receiver, source);
                 let start = fn_node.start_position();
                 let end = fn_node.end_position();
                 let instrumentation = None;
                 let definition = Some(Location::from((file_name, start, end)));
 
                 match (module, fn_name) {
+                    (Ok(module), Ok(function)) if is_entry_point(&module, &function) => None,
                     (Ok(module), Ok(function)) => Some(Ok(FunctionInfo {
                         id: (module, function).into(),
                         instrumentation,
                         definition,
+                        ..Default::default()
                     })),
                     (Err(err_mod), _) => {
                         error!("could not fetch the package name: {err_mod}");
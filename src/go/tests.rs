@@ -4,9 +4,9 @@
 //! language to then merge the sets so that functions that get detected by both
 //! queries have their information merged.
 
-use crate::{Location, Position, Range};
+use crate::{test_utils::test_project, Location, Position, Range};
 
-use super::*;
+use super::{queries::new_parser, *};
 use pretty_assertions::assert_eq;
 
 const FILE_NAME: &str = "source.go";
@@ -22,9 +22,10 @@ fn detect_simple() {
         }
         "#;
 
-    let query = AmQuery::try_new().unwrap();
+    let query = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let list = query.list_function_names(FILE_NAME, source).unwrap();
-    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_query =
+        AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let all_list = all_query.list_function_names(FILE_NAME, source).unwrap();
 
     let the_one_location = Location {
@@ -45,12 +46,14 @@ fn detect_simple() {
         id: ("lambda", "the_one").into(),
         instrumentation: Some(the_one_location.clone()),
         definition: Some(the_one_location.clone()),
+        ..Default::default()
     };
 
     let the_one_all_functions = FunctionInfo {
         id: ("lambda", "the_one").into(),
         instrumentation: None,
         definition: Some(the_one_location),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 1);
@@ -77,9 +80,10 @@ fn detect_legacy() {
         }
         "#;
 
-    let query = AmQuery::try_new().unwrap();
+    let query = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let list = query.list_function_names(FILE_NAME, source).unwrap();
-    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_query =
+        AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let all_list = all_query.list_function_names(FILE_NAME, source).unwrap();
 
     let not_the_one_location = Location {
@@ -128,21 +132,25 @@ fn detect_legacy() {
         id: ("beta", "sandwiched_function").into(),
         instrumentation: Some(sandwiched_function_location.clone()),
         definition: Some(sandwiched_function_location.clone()),
+        ..Default::default()
     };
     let sandwiched_all = FunctionInfo {
         id: ("beta", "sandwiched_function").into(),
         instrumentation: None,
         definition: Some(sandwiched_function_location.clone()),
+        ..Default::default()
     };
     let not_the_one = FunctionInfo {
         id: ("beta", "not_the_one").into(),
         instrumentation: None,
         definition: Some(not_the_one_location),
+        ..Default::default()
     };
     let not_that_one = FunctionInfo {
         id: ("beta", "not_that_one_either").into(),
         instrumentation: None,
         definition: Some(not_that_one_either_location),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 1);
@@ -158,3 +166,306 @@ fn detect_legacy() {
     assert!(all_list.contains(&not_the_one));
     assert!(all_list.contains(&not_that_one));
 }
+
+#[test]
+fn detect_syntax_errors() {
+    let source = r#"
+        package lambda
+
+        //autometrics:inst
+        func the_one( {
+        	return nil
+        }
+        "#;
+
+    let tree = new_parser().unwrap().parse(source, None).unwrap();
+    let errors = crate::syntax_errors(FILE_NAME, &tree);
+
+    assert!(!errors.is_empty());
+    assert!(errors.iter().all(|error| error.file == FILE_NAME));
+}
+
+#[test]
+fn report_syntax_errors_of_listed_files() {
+    let dir = test_project(&[
+        ("server/server.go", "package server\n\nfunc Serve() {}\n"),
+        ("server/broken.go", "package server\n\nfunc Broken( {}\n"),
+    ]);
+
+    let mut implementation = Impl::default();
+    assert!(
+        implementation.list_syntax_errors().is_empty(),
+        "no file was parsed yet"
+    );
+
+    implementation
+        .list_all_function_definitions(dir.path())
+        .unwrap();
+    implementation
+        .list_autometrics_functions(dir.path())
+        .unwrap();
+    let errors = implementation.list_syntax_errors();
+    assert!(!errors.is_empty());
+    assert!(
+        errors.iter().all(|error| error.file == "server/broken.go"),
+        "Complete list is {errors:?}"
+    );
+    let mut lines: Vec<_> = errors.iter().map(|error| error.range.start.line).collect();
+    lines.dedup();
+    assert_eq!(lines.len(), errors.len(), "the errors are reported once");
+
+    assert!(
+        implementation.list_syntax_errors().is_empty(),
+        "the reported errors are forgotten"
+    );
+}
+
+#[test]
+fn exclude_test_files() {
+    let dir = test_project(&[
+        ("server/server.go", "package server\n\nfunc Serve() {}\n"),
+        (
+            "server/server_test.go",
+            "package server\n\nfunc TestServe(t *testing.T) {}\n",
+        ),
+    ]);
+
+    let functions = |implementation: &mut Impl| {
+        let mut functions: Vec<_> = implementation
+            .list_all_function_definitions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| info.id.function)
+            .collect();
+        functions.sort();
+        functions
+    };
+
+    let mut implementation = Impl::default();
+    assert_eq!(functions(&mut implementation), vec!["Serve", "TestServe"]);
+
+    implementation.exclude_tests = true;
+    assert_eq!(functions(&mut implementation), vec!["Serve"]);
+}
+
+#[test]
+fn lint_generated_instrumentation() {
+    let source = r#"
+        package lambda
+
+        //autometrics:inst --slo "API" --success-target 90
+        func UpToDate(ctx context.Context) (err error) {
+        	defer metrics.Instrument(metrics.PreInstrument(metrics.NewContext(
+        		ctx,
+        		metrics.WithModuleName("lambda"),
+        		metrics.WithFunctionName("UpToDate"),
+        		metrics.WithSloName("API"),
+        	)), &err) //autometrics:defer
+        	return nil
+        }
+
+        //autometrics:inst
+        func NotGenerated() error {
+        	return nil
+        }
+
+        //autometrics:inst --slo Checkout
+        func Renamed(ctx context.Context) (err error) {
+        	defer metrics.Instrument(metrics.PreInstrument(metrics.NewContext(
+        		ctx,
+        		metrics.WithModuleName("lambda"),
+        		metrics.WithFunctionName("OldName"),
+        		metrics.WithSloName("API"),
+        	)), &err) //autometrics:defer
+        	return nil
+        }
+
+        //autometrics:doc
+        func OnlyDocumented() error {
+        	return nil
+        }
+        "#;
+
+    let query = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
+    let lints = query.list_lints(FILE_NAME, source).unwrap();
+
+    assert_eq!(
+        lints
+            .iter()
+            .map(|lint| (
+                lint.function.function.as_str(),
+                lint.location.range.start.line
+            ))
+            .collect::<Vec<_>>(),
+        vec![("NotGenerated", 15), ("Renamed", 21)]
+    );
+    assert!(lints[0].message.contains("missing"));
+    assert_eq!(
+        lints[1].message,
+        "the generated instrumentation is stale: it reports the function as `OldName` instead of `Renamed`, uses the objective `API` instead of `Checkout` (run `go generate`)"
+    );
+}
+
+#[test]
+fn detect_generic_methods() {
+    let source = r#"
+        package main
+
+        //autometrics:inst
+        func (s *Stack[T]) Push(value T) {
+        	s.items = append(s.items, value)
+        }
+
+        func (m Map[K, V]) Get(key K) V {
+        	return m.items[key]
+        }
+
+        func (Plain) Do() {}
+
+        func init() {}
+
+        func main() {}
+        "#;
+
+    let query = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
+    let list = query.list_function_names(FILE_NAME, source).unwrap();
+    let all_query =
+        AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
+    let all_list = all_query.list_function_names(FILE_NAME, source).unwrap();
+
+    assert_eq!(
+        list.iter()
+            .map(|info| info.id.function.as_str())
+            .collect::<Vec<_>>(),
+        vec!["(*Stack[...]).Push"]
+    );
+
+    let mut all_names: Vec<_> = all_list
+        .iter()
+        .map(|info| info.id.function.as_str())
+        .collect();
+    all_names.sort();
+    assert_eq!(
+        all_names,
+        vec!["(*Stack[...]).Push", "Map[...].Get", "Plain.Do"],
+        "the entry points are not listed"
+    );
+}
+
+#[test]
+fn name_methods_like_the_runtime() {
+    let source = r#"
+        package server
+
+        //autometrics:inst
+        func (s Server) Serve() {}
+
+        //autometrics:inst
+        func (s *Server) Stop() {}
+        "#;
+
+    let query = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
+    let mut names: Vec<_> = query
+        .list_function_names(FILE_NAME, source)
+        .unwrap()
+        .into_iter()
+        .map(|info| info.id.function)
+        .collect();
+    names.sort();
+
+    assert_eq!(names, vec!["(*Server).Stop", "Server.Serve"]);
+}
+
+#[test]
+fn evaluate_build_constraints() {
+    let dir = test_project(&[
+        ("server/server.go", "package server\n\nfunc Serve() {}\n"),
+        (
+            "server/server_linux.go",
+            "package server\n\nfunc ServeLinux() {}\n",
+        ),
+        (
+            "server/server_windows.go",
+            "package server\n\nfunc ServeWindows() {}\n",
+        ),
+        (
+            "server/server_darwin_arm64.go",
+            "package server\n\nfunc ServeMac() {}\n",
+        ),
+        (
+            "server/cgo.go",
+            "// Copyright notice.\n\n//go:build cgo && linux\n\npackage server\n\nfunc ServeCgo() {}\n",
+        ),
+        (
+            "server/integration.go",
+            "//go:build (integration || e2e) && !windows\n\npackage server\n\nfunc ServeIntegration() {}\n",
+        ),
+        (
+            "server/legacy.go",
+            "// +build !linux,!darwin\n\npackage server\n\nfunc ServeLegacy() {}\n",
+        ),
+        ("vendor/dep/dep.go", "package dep\n\nfunc Vendored() {}\n"),
+        (
+            "server/testdata/fixture.go",
+            "package fixture\n\nfunc Fixture() {}\n",
+        ),
+        ("_tools/gen.go", "package tools\n\nfunc Generate() {}\n"),
+    ]);
+
+    let mut implementation = Impl {
+        build: BuildContext {
+            goos: "linux".to_string(),
+            goarch: "amd64".to_string(),
+            tags: vec!["integration".to_string()],
+        },
+        ..Impl::default()
+    };
+
+    let mut functions: Vec<_> = implementation
+        .list_all_function_definitions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| info.id.function)
+        .collect();
+    functions.sort();
+    assert_eq!(functions, vec!["Serve", "ServeIntegration", "ServeLinux"]);
+
+    let excluded = implementation.list_excluded_files(dir.path()).unwrap();
+    assert_eq!(
+        excluded
+            .iter()
+            .map(|file| (file.file.as_str(), file.constraint.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("server/cgo.go", "//go:build cgo && linux"),
+            ("server/legacy.go", "// +build !linux,!darwin"),
+            (
+                "server/server_darwin_arm64.go",
+                "file name suffix _darwin_arm64"
+            ),
+            ("server/server_windows.go", "file name suffix _windows"),
+        ]
+    );
+}
+
+#[test]
+fn list_projects_in_ignored_dirs() {
+    let dir = test_project(&[
+        (
+            "testdata/server/server.go",
+            "package server\n\nfunc Serve() {}\n",
+        ),
+        (
+            "testdata/server/testdata/fixture.go",
+            "package fixture\n\nfunc Fixture() {}\n",
+        ),
+    ]);
+
+    let functions: Vec<_> = Impl::default()
+        .list_all_function_definitions(&dir.path().join("testdata"))
+        .unwrap()
+        .into_iter()
+        .map(|info| info.id.function)
+        .collect();
+    assert_eq!(functions, vec!["Serve"]);
+}
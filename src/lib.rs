@@ -1,13 +1,24 @@
+pub mod config;
 pub mod go;
 pub mod python;
 pub mod rust;
 pub mod typescript;
+pub mod user_queries;
 
-use std::{collections::HashMap, fmt::Display, path::Path};
+#[cfg(test)]
+mod test_utils;
 
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::warn;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tree_sitter::{LanguageError, QueryError};
+use tree_sitter::{LanguageError, Node, QueryError, Tree};
 
 const FUNC_NAME_CAPTURE: &str = "func.name";
 
@@ -36,6 +47,40 @@ pub struct FunctionInfo {
     /// The location of the instrumentation of the function (e.g. where the Autometrics wrapper is called.)
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub instrumentation: Option<Location>,
+    /// The options given to the instrumentation (e.g. the arguments of a
+    /// decorator), by name, with the source text of their value.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub options: Option<BTreeMap<String, String>>,
+    /// The trait implemented by the block defining the function, for methods
+    /// of trait implementations.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub implemented_trait: Option<String>,
+    /// The compile-time condition under which the function is instrumented
+    /// (e.g. the predicate of a Rust `cfg_attr`), when it is not always.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub condition: Option<String>,
+    /// The kind of function, for languages that distinguish them.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub kind: Option<FunctionKind>,
+    /// The build target containing the function, for languages where a project
+    /// builds several artifacts (e.g. `lib` or `bin:server` for Rust).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub target: Option<String>,
+}
+
+/// The kind of a function definition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionKind {
+    /// A function defined at the level of a module.
+    Function,
+    /// A method, or associated function, of an impl block.
+    Method,
+    /// A method, or associated function, with a default implementation in a
+    /// trait definition.
+    TraitDefaultMethod,
+    /// A function defined in the body of another function.
+    NestedFunction,
 }
 
 /// A valid key to find a specific function in a codebase.
@@ -45,6 +90,10 @@ pub struct FunctionId {
     pub module: String,
     /// The name of the function.
     pub function: String,
+    /// The name of the package containing the module, for languages where a
+    /// project can contain several packages with overlapping module names.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub package: Option<String>,
 }
 
 impl<M, F> From<(M, F)> for FunctionId
@@ -56,6 +105,7 @@ where
         Self {
             module: module.to_string(),
             function: function.to_string(),
+            package: None,
         }
     }
 }
@@ -119,16 +169,63 @@ where
     }
 }
 
+impl Display for Location {
+    /// Display the location as `file:line:column`, with 1-based lines and columns
+    /// to match what editors and compilers report.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.file,
+            self.range.start.line + 1,
+            self.range.start.column + 1
+        )
+    }
+}
+
 impl Display for FunctionInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "module: {}, function: {}",
             self.id.module, self.id.function
+        )?;
+        if let Some(package) = &self.id.package {
+            write!(f, ", package: {package}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An issue found in the instrumentation of a function.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Lint {
+    pub function: FunctionId,
+    /// The location of the instrumentation with the issue.
+    pub location: Location,
+    /// Description of the issue.
+    pub message: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}::{}: {}",
+            self.location, self.function.module, self.function.function, self.message
         )
     }
 }
 
+/// A source file left out of the analysis because it is not part of the build.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ExcludedFile {
+    /// Path of the file, relative to the project root.
+    pub file: String,
+    /// The build constraint that excludes the file.
+    pub constraint: String,
+}
+
 /// Trait to implement to claim "Language support" for am_list.
 ///
 /// This means we can both list all autometricized functions in a project, and
@@ -149,16 +246,116 @@ pub trait ListAmFunctions {
             .map(|full_info| (full_info.id.clone(), full_info))
             .collect();
 
-        // Only the definition field is expected to differ
-        // between am_functions and all_function_definitions
+        // Only the definition (and the kind, that only the definition queries
+        // may know) are expected to differ between am_functions and
+        // all_function_definitions
         for function in all_function_definitions {
             info_set
                 .entry(function.id.clone())
-                .and_modify(|info| info.definition = function.definition.clone())
+                .and_modify(|info| {
+                    info.definition = function.definition.clone();
+                    info.kind = info.kind.or(function.kind);
+                })
                 .or_insert(function);
         }
         Ok(info_set.into_values().collect())
     }
+
+    /// List the locations of the syntax errors found in the files parsed by
+    /// the previous listings, and forget them.
+    ///
+    /// Tree-sitter still produces a tree for files with syntax errors, but the
+    /// queries can silently miss functions in these files, so the results for
+    /// the files listed here are only partial.
+    fn list_syntax_errors(&mut self) -> Vec<Location>;
+
+    /// List the issues found in the instrumentation of the functions of the
+    /// project.
+    ///
+    /// Languages without lints report no issues.
+    fn list_lints(&mut self, _project_root: &Path) -> Result<Vec<Lint>> {
+        Ok(Vec::new())
+    }
+
+    /// List the source files of the project that are skipped because their
+    /// build constraints exclude them from the build.
+    ///
+    /// Languages without build constraints exclude no files.
+    fn list_excluded_files(&mut self, _project_root: &Path) -> Result<Vec<ExcludedFile>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Return the locations of all the syntax errors in the tree.
+///
+/// Only the outermost `ERROR` nodes are reported, along with the `MISSING`
+/// nodes tree-sitter inserted to recover from an error.
+pub(crate) fn syntax_errors(file_name: &str, tree: &Tree) -> Vec<Location> {
+    fn collect(node: Node, file_name: &str, acc: &mut Vec<Location>) {
+        if node.is_error() || node.is_missing() {
+            acc.push(Location::from((
+                file_name,
+                node.start_position(),
+                node.end_position(),
+            )));
+            return;
+        }
+        if !node.has_error() {
+            return;
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect(child, file_name, acc);
+        }
+    }
+
+    let mut res = Vec::new();
+    if tree.root_node().has_error() {
+        collect(tree.root_node(), file_name, &mut res);
+    }
+    res
+}
+
+/// The syntax errors found in the files parsed while listing the functions of
+/// a project.
+///
+/// The queries of an implementation share the same collector, so that the
+/// errors are reported from the trees the listing already built, without
+/// parsing the files again.
+#[derive(Clone, Debug, Default)]
+pub struct SyntaxErrors(Arc<Mutex<BTreeMap<String, Vec<Location>>>>);
+
+impl SyntaxErrors {
+    /// Record the syntax errors of the `tree` parsed from the file at
+    /// `file_name`.
+    ///
+    /// Parsing the same file in several passes records its errors only once.
+    pub(crate) fn record(&self, file_name: &str, tree: &Tree) {
+        let errors = syntax_errors(file_name, tree);
+        if errors.is_empty() {
+            return;
+        }
+        self.0
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .insert(file_name.to_string(), errors);
+    }
+
+    /// Remove and return all the errors recorded so far, and report them as
+    /// warnings.
+    pub(crate) fn take(&self) -> Vec<Location> {
+        let files = std::mem::take(
+            &mut *self
+                .0
+                .lock()
+                .expect("no thread panics while holding the lock"),
+        );
+        let errors: Vec<Location> = files.into_values().flatten().collect();
+        for error in &errors {
+            warn!("{error}: syntax error, this file was only partially analyzed");
+        }
+        errors
+    }
 }
 
 pub type Result<T> = std::result::Result<T, AmlError>;
@@ -169,7 +366,7 @@ pub enum AmlError {
     #[error("Issue creating the TreeSitter parser")]
     CreateParser(#[from] LanguageError),
     /// Issue when trying to create a Tree-sitter query.
-    #[error("Issue creating the TreeSitter query")]
+    #[error("Issue creating the TreeSitter query: {0}")]
     CreateQuery(#[from] QueryError),
     /// Issue when the query is expected to have the given named capture.
     #[error("The query is missing an expected named capture: {0}")]
@@ -181,4 +378,16 @@ pub enum AmlError {
     /// String.
     #[error("Invalid text in source")]
     InvalidText,
+    /// Issue when reading a file.
+    #[error("Could not read {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+    /// Issue when the configuration file cannot be deserialized.
+    #[error("Invalid configuration file")]
+    InvalidConfig(#[from] toml::de::Error),
+    /// Issue when a user-supplied query cannot be used.
+    #[error("Invalid user query {0}: {1}")]
+    InvalidUserQuery(PathBuf, Box<AmlError>),
+    /// Issue when a file pattern is not a valid glob.
+    #[error("Invalid glob pattern")]
+    InvalidGlob(#[from] globset::Error),
 }
@@ -1,8 +1,13 @@
-use am_list::ListAmFunctions;
+use am_list::{
+    config::{Config, LanguageConfig, CONFIG_FILE_NAME},
+    user_queries::UserQueries,
+    FunctionInfo, ListAmFunctions, Range, SyntaxErrors,
+};
+use anyhow::bail;
 use clap::{Args, Parser, Subcommand};
 use flexi_logger::{AdaptiveFormat, Logger};
 use log::info;
-use std::{path::PathBuf, str::FromStr};
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -22,19 +27,73 @@ enum Command {
 #[derive(Args)]
 struct ListArgs {
     /// Language to detect autometrics functions for.
+    ///
+    /// Defaults to all the languages that have a section in the configuration file.
     #[arg(short, long, value_name = "LANGUAGE")]
-    language: Language,
+    language: Option<Language>,
     /// Root of the project to start the search on.
     /// - For Rust projects it must be where the Cargo.toml lie,
     /// - For Go projects it must be the root of the repository.
     #[arg(value_name = "ROOT")]
     root: PathBuf,
+    /// Path to the configuration file to use (defaults to ROOT/am_list.toml if it exists)
+    #[arg(short, long, value_name = "CONFIG")]
+    config: Option<PathBuf>,
+    /// Directory containing user-supplied tree-sitter queries, that override or
+    /// extend the built-in ones (see runtime/queries/README.md)
+    #[arg(long, value_name = "DIR")]
+    queries_dir: Option<PathBuf>,
     /// List all functions instead of only the autometricized ones (defaults to false)
     #[arg(short, long, default_value = "false")]
     all_functions: bool,
     /// Pretty print the resulting JSON (defaults to false)
     #[arg(short, long, default_value = "false")]
     pretty: bool,
+    /// List the files that were only partially analyzed because of syntax
+    /// errors, instead of the functions (defaults to false)
+    #[arg(long, default_value = "false")]
+    partial_files: bool,
+    /// List the issues found in the instrumentation of the functions, like
+    /// Rust functions that can never report an error, or Go functions whose
+    /// `go generate` output is missing or stale, instead of the functions
+    /// (defaults to false)
+    #[arg(long, default_value = "false")]
+    lints: bool,
+    /// List the files skipped because their build constraints exclude them
+    /// from the build, instead of the functions (Go only, defaults to false)
+    #[arg(long, default_value = "false")]
+    excluded_files: bool,
+    /// Report the path of the compiled Javascript files as modules, using the
+    /// `outDir` and `rootDir` of tsconfig.json, like autometrics-ts does at
+    /// runtime (Typescript only, defaults to false)
+    #[arg(long, default_value = "false")]
+    compiled_modules: bool,
+    /// Build output directory, relative to ROOT, whose source maps give the
+    /// compiled file of each source. Implies --compiled-modules (Typescript only)
+    #[arg(long, value_name = "DIR")]
+    build_dir: Option<PathBuf>,
+    /// Only list the functions of these kinds of cargo targets, separated by
+    /// commas: lib, bin, example, bench, test, build (Rust only, defaults to
+    /// all the targets). Use `--targets lib,bin` to only list production code
+    #[arg(long, value_name = "KINDS", value_delimiter = ',')]
+    targets: Vec<String>,
+    /// Skip the test code: Rust `#[test]` functions and `#[cfg(test)]`
+    /// modules, Go `*_test.go` files, Python `test_*.py` and `conftest.py`
+    /// files, and Typescript `*.test.ts` and `*.spec.ts` files (defaults to false)
+    #[arg(long, default_value = "false")]
+    exclude_tests: bool,
+    /// Target operating system to evaluate the build constraints with
+    /// (Go only, defaults to $GOOS or the host system)
+    #[arg(long, value_name = "GOOS")]
+    goos: Option<String>,
+    /// Target architecture to evaluate the build constraints with (Go only,
+    /// defaults to $GOARCH or the host architecture)
+    #[arg(long, value_name = "GOARCH")]
+    goarch: Option<String>,
+    /// Build tags satisfied by the build constraints, separated by commas
+    /// (Go only)
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    tags: Vec<String>,
 }
 
 #[derive(Clone, Copy)]
@@ -45,6 +104,77 @@ enum Language {
     Python,
 }
 
+impl Language {
+    const ALL: [Language; 4] = [
+        Language::Rust,
+        Language::Go,
+        Language::Typescript,
+        Language::Python,
+    ];
+
+    fn config(self, config: &Config) -> Option<&LanguageConfig> {
+        match self {
+            Language::Rust => config.rust.as_ref(),
+            Language::Go => config.go.as_ref(),
+            Language::Typescript => config.typescript.as_ref(),
+            Language::Python => config.python.as_ref(),
+        }
+    }
+
+    fn implementor(
+        self,
+        config: &LanguageConfig,
+        queries: &UserQueries,
+    ) -> anyhow::Result<Box<dyn ListAmFunctions>> {
+        let filter = config.file_filter()?;
+        let queries = queries.clone();
+        Ok(match self {
+            Language::Rust => Box::new(am_list::rust::Impl {
+                filter,
+                queries,
+                targets: config
+                    .targets
+                    .iter()
+                    .map(|kind| kind.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(anyhow::Error::msg)?,
+                exclude_tests: config.exclude_tests,
+                syntax_errors: SyntaxErrors::default(),
+            }),
+            Language::Go => {
+                let default_build = am_list::go::BuildContext::default();
+                Box::new(am_list::go::Impl {
+                    filter,
+                    queries,
+                    exclude_tests: config.exclude_tests,
+                    build: am_list::go::BuildContext {
+                        goos: config.goos.clone().unwrap_or(default_build.goos),
+                        goarch: config.goarch.clone().unwrap_or(default_build.goarch),
+                        tags: config.build_tags.clone(),
+                    },
+                    syntax_errors: SyntaxErrors::default(),
+                })
+            }
+            Language::Typescript => Box::new(am_list::typescript::Impl {
+                filter,
+                queries,
+                wrappers: config.wrappers.clone(),
+                compiled_modules: config.compiled_modules,
+                build_dir: config.build_dir.clone(),
+                exclude_tests: config.exclude_tests,
+                syntax_errors: SyntaxErrors::default(),
+            }),
+            Language::Python => Box::new(am_list::python::Impl {
+                filter,
+                queries,
+                decorators: config.decorators.clone(),
+                exclude_tests: config.exclude_tests,
+                syntax_errors: SyntaxErrors::default(),
+            }),
+        })
+    }
+}
+
 impl FromStr for Language {
     type Err = String;
 
@@ -78,22 +208,143 @@ fn main() -> anyhow::Result<()> {
 
     match args.command {
         Command::List(args) => {
-            let root = args.root;
-            info!("Autometrics functions in {}:", root.display());
-
-            let mut implementor: Box<dyn ListAmFunctions> = match args.language {
-                Language::Rust => Box::new(am_list::rust::Impl {}),
-                Language::Go => Box::new(am_list::go::Impl {}),
-                Language::Typescript => Box::new(am_list::typescript::Impl {}),
-                Language::Python => Box::new(am_list::python::Impl {}),
+            let (config, config_dir) = Config::load(&args.root, args.config.as_deref())?;
+
+            let languages: Vec<(Language, LanguageConfig)> = match args.language {
+                Some(language) => vec![(
+                    language,
+                    language.config(&config).cloned().unwrap_or_default(),
+                )],
+                None => Language::ALL
+                    .into_iter()
+                    .filter_map(|language| {
+                        language
+                            .config(&config)
+                            .map(|lang_config| (language, lang_config.clone()))
+                    })
+                    .collect(),
             };
+            if languages.is_empty() {
+                bail!("No language to list: use --language, or add a language section to {CONFIG_FILE_NAME}");
+            }
 
-            let mut res = if args.all_functions {
-                implementor.list_all_functions(&root)?
-            } else {
-                implementor.list_autometrics_functions(&root)?
+            let queries_dir = args
+                .queries_dir
+                .or_else(|| config.queries_dir.as_ref().map(|dir| config_dir.join(dir)));
+            let queries = match queries_dir {
+                Some(dir) => UserQueries::load(&dir)?,
+                None => UserQueries::default(),
             };
 
+            let mut res = Vec::new();
+            let mut partial_files: BTreeMap<String, Vec<Range>> = BTreeMap::new();
+            let mut coverage_failures = Vec::new();
+            let mut lints = Vec::new();
+            let mut excluded_files = Vec::new();
+            for (language, mut lang_config) in languages {
+                lang_config.compiled_modules |= args.compiled_modules;
+                lang_config.exclude_tests |= args.exclude_tests;
+                if let Some(build_dir) = &args.build_dir {
+                    lang_config.build_dir = Some(build_dir.clone());
+                }
+                if !args.targets.is_empty() {
+                    lang_config.targets = args.targets.clone();
+                }
+                if let Some(goos) = &args.goos {
+                    lang_config.goos = Some(goos.clone());
+                }
+                if let Some(goarch) = &args.goarch {
+                    lang_config.goarch = Some(goarch.clone());
+                }
+                if !args.tags.is_empty() {
+                    lang_config.build_tags = args.tags.clone();
+                }
+                let mut implementor = language.implementor(&lang_config, &queries)?;
+                for root in lang_config.roots(&args.root, &config_dir) {
+                    info!("Autometrics functions in {}:", root.display());
+
+                    if args.partial_files {
+                        // The syntax errors are found while listing the functions.
+                        implementor.list_all_functions(&root)?;
+                    } else if args.lints {
+                        let mut root_lints = implementor.list_lints(&root)?;
+                        if let Some(prefix) = &lang_config.module_prefix {
+                            for lint in &mut root_lints {
+                                lint.function.module.insert_str(0, prefix);
+                            }
+                        }
+                        lints.extend(root_lints);
+                    } else if args.excluded_files {
+                        excluded_files.extend(implementor.list_excluded_files(&root)?);
+                    } else {
+                        let mut functions = if args.all_functions {
+                            implementor.list_all_functions(&root)?
+                        } else {
+                            implementor.list_autometrics_functions(&root)?
+                        };
+
+                        if let Some(min_coverage) = lang_config.min_coverage {
+                            let all_functions = if args.all_functions {
+                                functions.clone()
+                            } else {
+                                implementor.list_all_functions(&root)?
+                            };
+                            let coverage = coverage(&all_functions);
+                            info!("Coverage in {}: {coverage:.1}%", root.display());
+                            if coverage < min_coverage {
+                                coverage_failures.push(format!(
+                                    "{}: {coverage:.1}% of the functions are instrumented, expected at least {min_coverage:.1}%",
+                                    root.display()
+                                ));
+                            }
+                        }
+
+                        lang_config.apply_module_prefix(&mut functions);
+                        res.extend(functions);
+                    }
+
+                    // Always report the syntax errors met by the listings as warnings
+                    for error in implementor.list_syntax_errors() {
+                        partial_files
+                            .entry(error.file)
+                            .or_default()
+                            .push(error.range);
+                    }
+                }
+            }
+
+            if args.partial_files {
+                if args.pretty {
+                    println!("{}", serde_json::to_string_pretty(&partial_files)?);
+                } else {
+                    println!("{}", serde_json::to_string(&partial_files)?);
+                }
+                info!("Total: {} partially analyzed files", partial_files.len());
+                return Ok(());
+            }
+
+            if args.lints {
+                lints.sort();
+                if args.pretty {
+                    println!("{}", serde_json::to_string_pretty(&lints)?);
+                } else {
+                    println!("{}", serde_json::to_string(&lints)?);
+                }
+                info!("Total: {} lints", lints.len());
+                return Ok(());
+            }
+
+            if args.excluded_files {
+                excluded_files.sort();
+                if args.pretty {
+                    println!("{}", serde_json::to_string_pretty(&excluded_files)?);
+                } else {
+                    println!("{}", serde_json::to_string(&excluded_files)?);
+                }
+                info!("Total: {} excluded files", excluded_files.len());
+                return Ok(());
+            }
+
             res.sort();
             if args.pretty {
                 println!("{}", serde_json::to_string_pretty(&res)?);
@@ -102,7 +353,29 @@ fn main() -> anyhow::Result<()> {
             }
             info!("Total: {} functions", res.len());
 
+            if !coverage_failures.is_empty() {
+                bail!(
+                    "Instrumentation coverage is below the threshold:\n{}",
+                    coverage_failures.join("\n")
+                );
+            }
+
             Ok(())
         }
     }
 }
+
+/// Return the percentage of the defined functions that are instrumented.
+fn coverage(functions: &[FunctionInfo]) -> f64 {
+    let defined = functions
+        .iter()
+        .filter(|function| function.definition.is_some());
+    let total = defined.clone().count();
+    if total == 0 {
+        return 100.0;
+    }
+    let instrumented = defined
+        .filter(|function| function.instrumentation.is_some())
+        .count();
+    100.0 * instrumented as f64 / total as f64
+}
@@ -1,18 +1,54 @@
+mod imports;
+mod packages;
 mod queries;
 
-use crate::{FunctionInfo, ListAmFunctions, Result};
+use crate::{
+    config::FileFilter, user_queries::UserQueries, FunctionInfo, ListAmFunctions, Location, Result,
+    SyntaxErrors,
+};
+use imports::{DecoratorExports, Import, ProjectModules};
+use packages::ProjectLayout;
 use queries::{AllFunctionsQuery, AmImportQuery, AmQuery};
 use rayon::prelude::*;
 use std::{
     collections::HashSet,
     fs::read_to_string,
-    path::{Path, PathBuf, MAIN_SEPARATOR},
+    path::{Path, PathBuf},
 };
 use walkdir::{DirEntry, WalkDir};
 
+/// A source file of the project, read by the first pass of the listing of the
+/// autometricized functions.
+struct SourceModule {
+    /// Path of the file, relative to the project root.
+    file_name: String,
+    /// Name of the module, as reported for the functions it defines.
+    module_name: String,
+    /// Dotted path under which the module is imported.
+    import_path: String,
+    /// Whether the file is the `__init__.py` of a package.
+    is_package: bool,
+    source: String,
+    /// Absolute imports of the module, resolved once all the modules of the
+    /// project are known.
+    imports: Vec<Import>,
+}
+
 /// Implementation of the Python support for listing autometricized functions.
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Impl {}
+#[derive(Clone, Debug, Default)]
+pub struct Impl {
+    /// Filter on the files to scan in the project.
+    pub filter: FileFilter,
+    /// User-supplied queries that override or extend the built-in ones.
+    pub queries: UserQueries,
+    /// Additional names of the autometrics decorator to look for, on top of the
+    /// `autometrics` import detected in each file.
+    pub decorators: Vec<String>,
+    /// Skip the test files (`test_*.py`, `*_test.py` and `conftest.py`).
+    pub exclude_tests: bool,
+    /// Syntax errors found in the files parsed by the listings.
+    pub syntax_errors: SyntaxErrors,
+}
 
 impl Impl {
     fn is_hidden(entry: &DirEntry) -> bool {
@@ -33,52 +69,115 @@ impl Impl {
                 .extension()
                 .map_or(false, |ext| ext == "py" || ext == "py3")
     }
+
+    /// Return true if the `entry` is a test file, following the naming
+    /// conventions of pytest (`test_*.py`, `*_test.py` and `conftest.py`).
+    fn is_test_file(entry: &DirEntry) -> bool {
+        if !entry.file_type().is_file() {
+            return false;
+        }
+        let Some(stem) = entry.path().file_stem().and_then(|stem| stem.to_str()) else {
+            return false;
+        };
+        stem.starts_with("test_") || stem.ends_with("_test") || stem == "conftest"
+    }
+
+    /// Return true if the walked `entry` must be scanned.
+    fn accepts(&self, project_root: &Path, entry: &DirEntry) -> bool {
+        Self::is_valid(entry)
+            && !(self.exclude_tests && Self::is_test_file(entry))
+            && self.filter.accepts(project_root, entry)
+    }
 }
 
 impl ListAmFunctions for Impl {
     fn list_autometrics_functions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
-        let root_name = project_root
-            .file_name()
-            .map(|s| s.to_str().unwrap_or_default())
-            .unwrap_or("");
+        let layout = ProjectLayout::discover(project_root)?;
 
         let walker = WalkDir::new(project_root).into_iter();
         let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            Some(
-                entry
-                    .path()
-                    .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-            )
-        }));
+        source_mod_pairs.extend(
+            walker
+                .filter_entry(|entry| self.accepts(project_root, entry))
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    Some(
+                        entry
+                            .path()
+                            .to_str()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                    )
+                }),
+        );
 
-        list.par_extend(source_mod_pairs.par_iter().filter_map(move |path| {
-            let relative_module_name = Path::new(path)
-                .strip_prefix(project_root)
-                .ok()?
-                .with_extension("")
-                .to_str()?
-                .replace(MAIN_SEPARATOR, ".");
-            let module_name = format!("{}.{}", root_name, relative_module_name);
-            let source = read_to_string(path).ok()?;
-            let import_query = AmImportQuery::try_new().ok()?;
-            let decorator_name = import_query.get_decorator_name(source.as_str()).ok()?;
-            let query = AmQuery::try_new(decorator_name.as_str()).ok()?;
-            let file_name = PathBuf::from(path)
-                .strip_prefix(project_root)
-                .expect("path comes from a project_root WalkDir")
-                .to_str()
-                .expect("file_name is a valid path as it is part of `path`")
-                .to_string();
-            let names = query
-                .list_function_names(&file_name, &source, module_name.as_str())
-                .unwrap_or_default();
-            Some(names)
+        let import_query = AmImportQuery::try_new(&self.queries, &self.syntax_errors)?;
+        let query = AmQuery::try_new(&self.queries)?;
+
+        // First pass: collect the imports of all the modules, so that re-exports
+        // of the decorator through project modules can be followed.
+        let mut modules: Vec<SourceModule> = source_mod_pairs
+            .par_iter()
+            .filter_map(|path| {
+                let relative_path = Path::new(path).strip_prefix(project_root).ok()?;
+                let file_name = relative_path.to_str()?.to_string();
+                let module_name = layout.module_name(relative_path)?;
+                let import_path = layout.import_path(relative_path)?;
+                let is_package = relative_path.file_stem().map_or(false, |s| s == "__init__");
+                let source = read_to_string(path).ok()?;
+                let imports = import_query
+                    .list_imports(&file_name, &source)
+                    .ok()?
+                    .into_iter()
+                    .map(|import| import.into_absolute(&import_path, is_package))
+                    .collect();
+                Some(SourceModule {
+                    file_name,
+                    module_name,
+                    import_path,
+                    is_package,
+                    source,
+                    imports,
+                })
+            })
+            .collect();
+
+        let project_modules =
+            ProjectModules::new(modules.iter().map(|module| module.import_path.as_str()));
+        for module in &mut modules {
+            module.imports = std::mem::take(&mut module.imports)
+                .into_iter()
+                .map(|import| {
+                    import.resolve(&module.import_path, module.is_package, &project_modules)
+                })
+                .collect();
+        }
+
+        let exports = DecoratorExports::from_project(
+            &modules
+                .iter()
+                .map(|module| (module.import_path.clone(), module.imports.clone()))
+                .collect(),
+        );
+
+        // Second pass: list the functions decorated with any name of the decorator.
+        let decorators = &self.decorators;
+        list.par_extend(modules.par_iter().filter_map(|module| {
+            let mut decorator_names = exports.decorator_names(&module.imports);
+            decorator_names.extend(decorators.iter().cloned());
+            if decorator_names.is_empty() && !query.has_extra_patterns() {
+                return None;
+            }
+            query
+                .list_function_names(
+                    &module.file_name,
+                    &module.source,
+                    &module.module_name,
+                    &decorator_names,
+                )
+                .ok()
         }));
 
         let mut result = Vec::with_capacity(PREALLOCATED_ELEMS);
@@ -89,32 +188,29 @@ impl ListAmFunctions for Impl {
     fn list_all_function_definitions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
-        let root_name = project_root
-            .file_name()
-            .map(|s| s.to_str().unwrap_or_default())
-            .unwrap_or("");
+        let layout = ProjectLayout::discover(project_root)?;
 
         let walker = WalkDir::new(project_root).into_iter();
         let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            Some(
-                entry
-                    .path()
-                    .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-            )
-        }));
+        source_mod_pairs.extend(
+            walker
+                .filter_entry(|entry| self.accepts(project_root, entry))
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    Some(
+                        entry
+                            .path()
+                            .to_str()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                    )
+                }),
+        );
 
+        let query = AllFunctionsQuery::try_new(&self.queries, &self.syntax_errors)?;
         list.par_extend(source_mod_pairs.par_iter().filter_map(move |path| {
-            let relative_module_name = Path::new(path)
-                .strip_prefix(project_root)
-                .ok()?
-                .with_extension("")
-                .to_str()?
-                .replace(MAIN_SEPARATOR, ".");
-            let module_name = format!("{}.{}", root_name, relative_module_name);
+            let module_name =
+                layout.module_name(Path::new(path).strip_prefix(project_root).ok()?)?;
             let source = read_to_string(path).ok()?;
             let file_name = PathBuf::from(path)
                 .strip_prefix(project_root)
@@ -122,7 +218,6 @@ impl ListAmFunctions for Impl {
                 .to_str()
                 .expect("file_name is a valid path as it is part of `path`")
                 .to_string();
-            let query = AllFunctionsQuery::try_new().ok()?;
             let names = query
                 .list_function_names(&file_name, &source, module_name.as_str())
                 .unwrap_or_default();
@@ -133,6 +228,10 @@ impl ListAmFunctions for Impl {
         result.extend(list.into_iter().flatten());
         Ok(result)
     }
+
+    fn list_syntax_errors(&mut self) -> Vec<Location> {
+        self.syntax_errors.take()
+    }
 }
 
 #[cfg(test)]
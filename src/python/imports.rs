@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+
+use log::debug;
+
+/// Name of the module exporting the autometrics decorator.
+const AUTOMETRICS_MODULE: &str = "autometrics";
+/// Name of the autometrics decorator in the autometrics module.
+const AUTOMETRICS_DECORATOR: &str = "autometrics";
+
+/// An import statement in a Python source file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Import {
+    /// `from module import name`, or `from module import name as alias`.
+    ///
+    /// The alias is the name itself when the import is not aliased.
+    Name {
+        module: String,
+        name: String,
+        alias: String,
+    },
+    /// `import module`, or `import module as alias`.
+    Module {
+        module: String,
+        alias: Option<String>,
+    },
+}
+
+impl Import {
+    /// Resolve relative imports (`from .telemetry import autometrics`) to
+    /// absolute module paths.
+    ///
+    /// `current_module` is the import path of the module containing the import
+    /// statement, and `is_package` tells whether this module is a package (an
+    /// `__init__.py` file).
+    pub fn into_absolute(self, current_module: &str, is_package: bool) -> Self {
+        match self {
+            Import::Name {
+                module,
+                name,
+                alias,
+            } => Import::Name {
+                module: absolute_module(&module, current_module, is_package),
+                name,
+                alias,
+            },
+            Import::Module { .. } => self,
+        }
+    }
+
+    /// Resolve the module of an absolute import to the full import path of a
+    /// project module, when the import is relative to the directory of the
+    /// importing script, or to a package root that the project layout does not
+    /// know about.
+    ///
+    /// `current_module` and `is_package` describe the module containing the
+    /// import statement, like for [`Import::into_absolute`]. Imports of modules
+    /// outside of the project are returned as is.
+    pub fn resolve(self, current_module: &str, is_package: bool, modules: &ProjectModules) -> Self {
+        let package = if is_package {
+            current_module
+        } else {
+            current_module
+                .rsplit_once('.')
+                .map_or("", |(package, _)| package)
+        };
+        match self {
+            Import::Name {
+                module,
+                name,
+                alias,
+            } => Import::Name {
+                module: modules.resolve(&module, package).unwrap_or(module),
+                name,
+                alias,
+            },
+            Import::Module { module, alias } => match modules.resolve(&module, package) {
+                // The module is still designated by the name it was imported with.
+                Some(resolved) => Import::Module {
+                    alias: Some(alias.unwrap_or(module)),
+                    module: resolved,
+                },
+                None => Import::Module { module, alias },
+            },
+        }
+    }
+}
+
+/// The import paths of the modules and packages of a project.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectModules {
+    paths: HashSet<String>,
+}
+
+impl ProjectModules {
+    /// Collect the modules with the given import paths, and the packages
+    /// containing them.
+    pub fn new<'a>(import_paths: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut paths = HashSet::new();
+        for import_path in import_paths {
+            let mut end = 0;
+            for part in import_path.split('.') {
+                end += part.len();
+                paths.insert(import_path[..end].to_string());
+                end += 1;
+            }
+        }
+        Self { paths }
+    }
+
+    /// Return the full import path of the project module imported as `module`
+    /// from a module of `package`.
+    fn resolve(&self, module: &str, package: &str) -> Option<String> {
+        if self.paths.contains(module) || module == AUTOMETRICS_MODULE {
+            return Some(module.to_string());
+        }
+
+        // The directory of a script is the first entry of `sys.path`, so its
+        // siblings can be imported without their package.
+        if !package.is_empty() {
+            let sibling = format!("{package}.{module}");
+            if self.paths.contains(&sibling) {
+                return Some(sibling);
+            }
+        }
+
+        // As a last resort, the module is found from a root that only the
+        // runtime knows, which is only unambiguous when a single project module
+        // has this name.
+        let suffix = format!(".{module}");
+        let mut candidates = self.paths.iter().filter(|path| path.ends_with(&suffix));
+        match (candidates.next(), candidates.next()) {
+            (Some(candidate), None) => Some(candidate.clone()),
+            (Some(_), Some(_)) => {
+                debug!("Ambiguous import of {module}, that several project modules match");
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+fn absolute_module(module: &str, current_module: &str, is_package: bool) -> String {
+    let relative = module.trim_start_matches('.');
+    let level = module.len() - relative.len();
+    if level == 0 {
+        return module.to_string();
+    }
+
+    let mut package: Vec<&str> = current_module
+        .split('.')
+        .filter(|s| !s.is_empty())
+        .collect();
+    // A package is its own parent for relative imports, while a plain module
+    // needs to go up one level to reach its package.
+    let levels_up = if is_package { level - 1 } else { level };
+    package.truncate(package.len().saturating_sub(levels_up));
+    if !relative.is_empty() {
+        package.push(relative);
+    }
+    package.join(".")
+}
+
+/// Project-wide map of the names under which each module exports the
+/// autometrics decorator.
+///
+/// This allows to follow re-exports of the decorator through project-internal
+/// modules, like `from myapp.telemetry import autometrics`.
+#[derive(Clone, Debug)]
+pub struct DecoratorExports {
+    exports: HashMap<String, HashSet<String>>,
+}
+
+impl Default for DecoratorExports {
+    fn default() -> Self {
+        Self {
+            exports: HashMap::from([(
+                AUTOMETRICS_MODULE.to_string(),
+                HashSet::from([AUTOMETRICS_DECORATOR.to_string()]),
+            )]),
+        }
+    }
+}
+
+impl DecoratorExports {
+    /// Compute the exports of all the modules in a project, given the
+    /// (absolute and resolved) imports of each module, indexed by module
+    /// import path.
+    pub fn from_project(modules: &HashMap<String, Vec<Import>>) -> Self {
+        let mut res = Self::default();
+
+        // Re-exports can be chained, so we iterate until we reach a fixed point.
+        loop {
+            let mut changed = false;
+            for (module, imports) in modules {
+                for import in imports {
+                    let Import::Name {
+                        module: source,
+                        name,
+                        alias,
+                    } = import
+                    else {
+                        continue;
+                    };
+                    if res.exports_decorator(source, name)
+                        && res
+                            .exports
+                            .entry(module.clone())
+                            .or_default()
+                            .insert(alias.clone())
+                    {
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        res
+    }
+
+    /// Return true if `module` exports the autometrics decorator as `name`.
+    ///
+    /// `module` is the full import path of the module, see [`Import::resolve`].
+    fn exports_decorator(&self, module: &str, name: &str) -> bool {
+        self.exports
+            .get(module)
+            .map_or(false, |names| names.contains(name))
+    }
+
+    /// Return all the names of the exports of `module`.
+    fn names_exported_by(&self, module: &str) -> HashSet<&str> {
+        self.exports
+            .get(module)
+            .map(|names| names.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Return all the expressions that designate the autometrics decorator in a
+    /// module with the given (absolute and resolved) imports.
+    ///
+    /// The expressions are the ones expected in a decorator, like `autometrics`
+    /// or `am.autometrics`.
+    pub fn decorator_names(&self, imports: &[Import]) -> HashSet<String> {
+        let mut res = HashSet::new();
+        for import in imports {
+            match import {
+                Import::Name {
+                    module,
+                    name,
+                    alias,
+                } => {
+                    if self.exports_decorator(module, name) {
+                        res.insert(alias.clone());
+                    }
+                    // `from package import module` imports a module as well.
+                    for exported in self.names_exported_by(&format!("{module}.{name}")) {
+                        res.insert(format!("{alias}.{exported}"));
+                    }
+                }
+                Import::Module { module, alias } => {
+                    let prefix = alias.as_ref().unwrap_or(module);
+                    for name in self.names_exported_by(module) {
+                        res.insert(format!("{prefix}.{name}"));
+                    }
+                }
+            }
+        }
+        res
+    }
+}
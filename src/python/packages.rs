@@ -0,0 +1,284 @@
+//! Computation of the import path of Python modules.
+//!
+//! autometrics-py labels functions with their `__module__`, which is the path
+//! used to import the module, so the layout of the project has to be taken into
+//! account:
+//! - package directories declared in `pyproject.toml` or `setup.cfg`,
+//! - `src` layouts,
+//! - regular packages, delimited by `__init__.py` files,
+//! - namespace packages, relative to the root of the project.
+
+use std::{
+    fs::read_to_string,
+    path::{Component, Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{AmlError, Result};
+
+const INIT_MODULE: &str = "__init__";
+const MAIN_MODULE: &str = "__main__";
+
+/// A directory containing the sources of the package named `package`.
+///
+/// The package is empty for directories that contain top-level packages, like
+/// `src` in a src layout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PackageDir {
+    dir: PathBuf,
+    package: String,
+}
+
+/// The layout of a Python project, used to compute the import path of its
+/// modules.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectLayout {
+    root: PathBuf,
+    /// Declared (or detected) package directories, the deepest first.
+    package_dirs: Vec<PackageDir>,
+}
+
+impl ProjectLayout {
+    /// Read the layout of the project at `project_root`.
+    ///
+    /// This only fails if a packaging configuration file exists but cannot be
+    /// read.
+    pub fn discover(project_root: &Path) -> Result<Self> {
+        // The root is made absolute to be able to look for packages above it.
+        let project_root = &project_root
+            .canonicalize()
+            .map_err(|err| AmlError::Io(project_root.to_path_buf(), err))?;
+        let mut declared = Vec::new();
+
+        let pyproject = project_root.join("pyproject.toml");
+        if pyproject.is_file() {
+            let contents =
+                read_to_string(&pyproject).map_err(|err| AmlError::Io(pyproject.clone(), err))?;
+            match contents.parse::<toml::Table>() {
+                Ok(table) => declared.extend(pyproject_package_dirs(&table)),
+                Err(err) => debug!("Ignoring invalid {}: {err}", pyproject.display()),
+            }
+        }
+
+        let setup_cfg = project_root.join("setup.cfg");
+        if setup_cfg.is_file() {
+            let contents =
+                read_to_string(&setup_cfg).map_err(|err| AmlError::Io(setup_cfg.clone(), err))?;
+            declared.extend(setup_cfg_package_dirs(&contents));
+        }
+
+        if declared.is_empty() {
+            let src = project_root.join("src");
+            if src.is_dir() && !src.join("__init__.py").is_file() {
+                declared.push((String::new(), PathBuf::from("src")));
+            }
+        }
+
+        let mut package_dirs: Vec<PackageDir> = declared
+            .into_iter()
+            .map(|(package, dir)| PackageDir {
+                dir: project_root.join(normalize(&dir)),
+                package,
+            })
+            .collect();
+        package_dirs
+            .sort_by_key(|package_dir| std::cmp::Reverse(package_dir.dir.as_os_str().len()));
+        package_dirs.dedup();
+
+        Ok(Self {
+            root: project_root.to_path_buf(),
+            package_dirs,
+        })
+    }
+
+    /// Return the import path of the module in the file at `relative_path`
+    /// (relative to the project root), like `myapp.telemetry` for
+    /// `src/myapp/telemetry.py`.
+    ///
+    /// Packages are imported through their `__init__.py` file, so the import
+    /// path of `myapp/__init__.py` is `myapp`.
+    pub fn import_path(&self, relative_path: &Path) -> Option<String> {
+        let path = self.root.join(normalize(relative_path));
+        let stem = path.file_stem()?.to_str()?;
+        let dir = path.parent()?;
+
+        let (base, package) = match self
+            .package_dirs
+            .iter()
+            .find(|package_dir| dir.starts_with(&package_dir.dir))
+        {
+            Some(package_dir) => (package_dir.dir.clone(), package_dir.package.as_str()),
+            None => (self.regular_package_base(dir), ""),
+        };
+
+        let mut components: Vec<&str> = package.split('.').filter(|s| !s.is_empty()).collect();
+        for component in dir.strip_prefix(&base).ok()?.components() {
+            components.push(component.as_os_str().to_str()?);
+        }
+        if stem != INIT_MODULE {
+            components.push(stem);
+        }
+        Some(components.join("."))
+    }
+
+    /// Return the module name that autometrics-py reports for the functions
+    /// defined in the file at `relative_path`.
+    ///
+    /// This is the import path of the module, except for `__main__.py` files
+    /// which are always run as the `__main__` module.
+    pub fn module_name(&self, relative_path: &Path) -> Option<String> {
+        if relative_path
+            .file_stem()
+            .map_or(false, |stem| stem == MAIN_MODULE)
+        {
+            return Some(MAIN_MODULE.to_string());
+        }
+        self.import_path(relative_path)
+    }
+
+    /// Return the directory from which the modules in `dir` are imported.
+    ///
+    /// Regular packages are followed up as long as there are `__init__.py`
+    /// files, even above the root of the project, so that scanning a
+    /// sub-package still gives the full import paths. Otherwise, the
+    /// directories are namespace packages relative to the project root.
+    fn regular_package_base(&self, dir: &Path) -> PathBuf {
+        if !dir.join("__init__.py").is_file() {
+            return self.root.clone();
+        }
+
+        let mut base = dir;
+        while base.join("__init__.py").is_file() {
+            match base.parent() {
+                Some(parent) => base = parent,
+                None => break,
+            }
+        }
+        base.to_path_buf()
+    }
+}
+
+/// Return the `(package, directory)` pairs declared in a `pyproject.toml` file,
+/// for the setuptools, poetry, hatch and pdm build backends.
+fn pyproject_package_dirs(pyproject: &toml::Table) -> Vec<(String, PathBuf)> {
+    let mut res = Vec::new();
+    let Some(tool) = pyproject.get("tool").and_then(toml::Value::as_table) else {
+        return res;
+    };
+
+    if let Some(setuptools) = tool.get("setuptools").and_then(toml::Value::as_table) {
+        if let Some(package_dir) = setuptools
+            .get("package-dir")
+            .and_then(toml::Value::as_table)
+        {
+            for (package, dir) in package_dir {
+                if let Some(dir) = dir.as_str() {
+                    res.push((package.clone(), PathBuf::from(dir)));
+                }
+            }
+        }
+        let wheres = setuptools
+            .get("packages")
+            .and_then(|packages| packages.get("find"))
+            .and_then(|find| find.get("where"))
+            .and_then(toml::Value::as_array);
+        for dir in wheres.into_iter().flatten().filter_map(toml::Value::as_str) {
+            res.push((String::new(), PathBuf::from(dir)));
+        }
+    }
+
+    if let Some(packages) = tool
+        .get("poetry")
+        .and_then(|poetry| poetry.get("packages"))
+        .and_then(toml::Value::as_array)
+    {
+        for package in packages {
+            if let Some(from) = package.get("from").and_then(toml::Value::as_str) {
+                res.push((String::new(), PathBuf::from(from)));
+            }
+        }
+    }
+
+    if let Some(packages) = tool
+        .get("hatch")
+        .and_then(|hatch| hatch.get("build"))
+        .and_then(|build| build.get("targets"))
+        .and_then(|targets| targets.get("wheel"))
+        .and_then(|wheel| wheel.get("packages"))
+        .and_then(toml::Value::as_array)
+    {
+        for package in packages.iter().filter_map(toml::Value::as_str) {
+            // Hatch lists the package directories themselves.
+            if let Some(parent) = Path::new(package).parent() {
+                res.push((String::new(), parent.to_path_buf()));
+            }
+        }
+    }
+
+    if let Some(dir) = tool
+        .get("pdm")
+        .and_then(|pdm| pdm.get("build"))
+        .and_then(|build| build.get("package-dir"))
+        .and_then(toml::Value::as_str)
+    {
+        res.push((String::new(), PathBuf::from(dir)));
+    }
+
+    res
+}
+
+/// Return the `(package, directory)` pairs declared in a `setup.cfg` file, in
+/// the `package_dir` option of the `[options]` section and the `where` option
+/// of the `[options.packages.find]` section.
+fn setup_cfg_package_dirs(setup_cfg: &str) -> Vec<(String, PathBuf)> {
+    let mut res = Vec::new();
+    let mut section = "";
+    let mut key = "";
+    for line in setup_cfg.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            section = name.trim();
+            key = "";
+            continue;
+        }
+
+        // Indented lines continue the value of the previous key.
+        let value = if line.starts_with(char::is_whitespace) {
+            trimmed
+        } else {
+            let Some((name, value)) = trimmed.split_once(['=', ':']) else {
+                continue;
+            };
+            key = name.trim();
+            value.trim()
+        };
+        if value.is_empty() {
+            continue;
+        }
+
+        match (section, key) {
+            ("options", "package_dir") => {
+                if let Some((package, dir)) = value.split_once('=') {
+                    res.push((package.trim().to_string(), PathBuf::from(dir.trim())));
+                }
+            }
+            ("options.packages.find", "where") => {
+                res.push((String::new(), PathBuf::from(value)));
+            }
+            _ => {}
+        }
+    }
+    res
+}
+
+/// Remove the `.` components of a relative path, so that `./src` and `src`
+/// designate the same directory.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
@@ -1,7 +1,16 @@
-use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
-use tree_sitter::{Parser, Query};
+use crate::{
+    user_queries::UserQueries, AmlError, FunctionInfo, Location, Result, SyntaxErrors,
+    FUNC_NAME_CAPTURE,
+};
+use std::collections::HashSet;
+use tree_sitter::{Node, Parser, Query};
 use tree_sitter_python::language;
 
+use super::imports::Import;
+
+const DECORATOR_NAME_CAPTURE: &str = "decorator.name";
+const IMPORT_MODULE_CAPTURE: &str = "import.module";
+const IMPORT_NAME_CAPTURE: &str = "import.name";
 const IMPORT_ALIAS_CAPTURE: &str = "import.alias";
 
 fn new_parser() -> Result<Parser> {
@@ -42,41 +51,74 @@ pub(super) struct AmQuery {
     query: Query,
     /// Index of the capture for a function name.
     func_name_idx: u32,
+    /// Index of the capture for the expression used as a decorator.
+    decorator_name_idx: u32,
+    /// Number of patterns of the built-in or overriding query, the other ones
+    /// being user-supplied extra patterns.
+    base_pattern_count: usize,
 }
 
 impl AmQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new(decorator_name: &str) -> Result<Self> {
-        let am_query_str = format!(
-            include_str!("../../runtime/queries/python/autometrics.scm.tpl"),
-            decorator_name
-        );
-        let query = Query::new(language(), &am_query_str)?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    pub fn try_new(user_queries: &UserQueries) -> Result<Self> {
+        let loaded = user_queries.query(
+            language(),
+            "python/autometrics.scm",
+            include_str!("../../runtime/queries/python/autometrics.scm"),
+            &[],
+        )?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let decorator_name_idx = loaded.capture_index(DECORATOR_NAME_CAPTURE)?;
         Ok(Self {
-            query,
+            base_pattern_count: loaded.base_pattern_count,
+            query: loaded.query,
             func_name_idx,
+            decorator_name_idx,
         })
     }
 
+    /// Return true if the query has user-supplied extra patterns.
+    pub fn has_extra_patterns(&self) -> bool {
+        self.query.pattern_count() > self.base_pattern_count
+    }
+
+    /// List the functions decorated with one of the `decorator_names`.
+    ///
+    /// The decorator names are expressions like `autometrics` or `am.autometrics`,
+    /// as computed by [`DecoratorExports::decorator_names`](super::imports::DecoratorExports::decorator_names).
+    ///
+    /// The matches of the user-supplied extra patterns are kept whatever their
+    /// decorator, as these patterns describe in-house decorators that the
+    /// imports cannot tell about.
     pub fn list_function_names(
         &self,
         file_name: &str,
         source: &str,
         module_name: &str,
+        decorator_names: &HashSet<String>,
     ) -> Result<Vec<FunctionInfo>> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
 
         let mut cursor = tree_sitter::QueryCursor::new();
-        cursor
+        let mut functions = cursor
             .matches(&self.query, parsed_source.root_node(), source.as_bytes())
             .filter_map(|m| {
+                let is_extra = m.pattern_index >= self.base_pattern_count;
+                if let Some(decorator) = m.nodes_for_capture_index(self.decorator_name_idx).next() {
+                    let decorator = decorator
+                        .utf8_text(source.as_bytes())
+                        .ok()?
+                        .split_whitespace()
+                        .collect::<String>();
+                    if !is_extra && !decorator_names.contains(&decorator) {
+                        return None;
+                    }
+                }
+
                 let node = m.nodes_for_capture_index(self.func_name_idx).next()?;
                 let start = node.start_position();
                 let end = node.end_position();
@@ -90,64 +132,102 @@ impl AmQuery {
                 } else {
                     format!("{}.{}", qualname, func_name)
                 };
-                Some(Ok(FunctionInfo {
+                Some(FunctionInfo {
                     id: (module_name, full_name).into(),
                     instrumentation,
                     definition,
-                }))
+                    ..Default::default()
+                })
             })
-            .collect::<std::result::Result<Vec<_>, _>>()
+            .collect::<Vec<_>>();
+
+        // A function can be decorated with multiple aliases of the decorator.
+        functions.sort();
+        functions.dedup();
+        Ok(functions)
     }
 }
 
-/// Query wrapper for autometrics decorator imports in source
+/// Query wrapper for the import statements in source
 #[derive(Debug)]
 pub(super) struct AmImportQuery {
     query: Query,
+    /// Index of the capture for the imported module
+    import_module_idx: u32,
+    /// Index of the capture for the name imported from a module
+    import_name_idx: u32,
     /// Index of the capture for import alias
     import_alias_idx: u32,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AmImportQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(user_queries: &UserQueries, syntax_errors: &SyntaxErrors) -> Result<Self> {
+        let loaded = user_queries.query(
             language(),
+            "python/import.scm",
             include_str!("../../runtime/queries/python/import.scm"),
+            &[],
         )?;
-        let import_alias_idx = query
-            .capture_index_for_name(IMPORT_ALIAS_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPORT_ALIAS_CAPTURE.to_string()))?;
+        let import_module_idx = loaded.capture_index(IMPORT_MODULE_CAPTURE)?;
+        let import_name_idx = loaded.capture_index(IMPORT_NAME_CAPTURE)?;
+        let import_alias_idx = loaded.capture_index(IMPORT_ALIAS_CAPTURE)?;
         Ok(Self {
-            query,
+            query: loaded.query,
+            import_module_idx,
+            import_name_idx,
             import_alias_idx,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
-    pub fn get_decorator_name(&self, source: &str) -> Result<String> {
+    /// List all the imports in the source of the file at `file_name`.
+    ///
+    /// Relative imports are returned as is, see [`Import::into_absolute`].
+    pub fn list_imports(&self, file_name: &str, source: &str) -> Result<Vec<Import>> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
+
+        let text = |node: Node| -> Result<String> {
+            node.utf8_text(source.as_bytes())
+                .map(|text| text.split_whitespace().collect())
+                .map_err(|_| AmlError::InvalidText)
+        };
 
         let mut cursor = tree_sitter::QueryCursor::new();
-        let matches = cursor
-            .matches(&self.query, parsed_source.root_node(), source.as_bytes())
-            .collect::<Vec<_>>();
-        if matches.len() != 1 {
-            return Err(AmlError::InvalidText);
-        }
-        let alias = matches[0]
-            .captures
-            .iter()
-            .find(|c| c.index == self.import_alias_idx)
-            .map(|c| c.node.utf8_text(source.as_bytes()).map(ToString::to_string));
-        match alias {
-            Some(Ok(alias)) => Ok(alias),
-            None => Ok("autometrics".to_string()),
-            _ => Err(AmlError::InvalidText),
+        let mut imports = Vec::new();
+        for m in cursor.matches(&self.query, parsed_source.root_node(), source.as_bytes()) {
+            let Some(module) = m.nodes_for_capture_index(self.import_module_idx).next() else {
+                continue;
+            };
+            let module = text(module)?;
+            let alias = m
+                .nodes_for_capture_index(self.import_alias_idx)
+                .next()
+                .map(text)
+                .transpose()?;
+            match m.nodes_for_capture_index(self.import_name_idx).next() {
+                Some(name) => {
+                    let name = text(name)?;
+                    imports.push(Import::Name {
+                        module,
+                        alias: alias.unwrap_or_else(|| name.clone()),
+                        name,
+                    })
+                }
+                None => imports.push(Import::Module { module, alias }),
+            }
         }
+
+        Ok(imports)
     }
 }
 
@@ -157,25 +237,30 @@ pub(super) struct AllFunctionsQuery {
     query: Query,
     /// Index of the capture for a function name.
     func_name_idx: u32,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AllFunctionsQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(user_queries: &UserQueries, syntax_errors: &SyntaxErrors) -> Result<Self> {
+        let loaded = user_queries.query(
             language(),
+            "python/all_functions.scm",
             include_str!("../../runtime/queries/python/all_functions.scm"),
+            &[],
         )?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
             func_name_idx,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
@@ -187,6 +272,7 @@ impl AllFunctionsQuery {
     ) -> Result<Vec<FunctionInfo>> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
 
         let mut cursor = tree_sitter::QueryCursor::new();
         cursor
@@ -212,6 +298,7 @@ impl AllFunctionsQuery {
                     id: (module_name, full_name).into(),
                     instrumentation,
                     definition,
+                    ..Default::default()
                 }))
             })
             .collect::<std::result::Result<Vec<_>, _>>()
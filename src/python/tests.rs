@@ -4,14 +4,35 @@
 //! language to then merge the sets so that functions that get detected by both
 //! queries have their information merged.
 
-use crate::{Location, Position, Range};
+use crate::{test_utils::test_project, Location, Position, Range};
+use std::collections::HashMap;
 
 use super::*;
+use packages::ProjectLayout;
 use pretty_assertions::assert_eq;
 
 const DUMMY_MODULE: &str = "dummy";
 const FILE_NAME: &str = "source.py";
 
+/// Names of the autometrics decorator in a source file outside of any project.
+fn decorator_names(source: &str) -> HashSet<String> {
+    let import_query =
+        AmImportQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
+    let imports = import_query.list_imports(FILE_NAME, source).unwrap();
+    DecoratorExports::default().decorator_names(&imports)
+}
+
+/// Names of the instrumented functions in `source`, with the given decorator names.
+fn instrumented_names(source: &str, decorator_names: &HashSet<String>) -> Vec<String> {
+    let query = AmQuery::try_new(&UserQueries::default()).unwrap();
+    query
+        .list_function_names(FILE_NAME, source, DUMMY_MODULE, decorator_names)
+        .unwrap()
+        .into_iter()
+        .map(|info| info.id.function)
+        .collect()
+}
+
 #[test]
 fn detect_simple() {
     let source = r#"
@@ -22,13 +43,12 @@ fn detect_simple() {
             return 'wake up, Neo'
         "#;
 
-    let import_query = AmImportQuery::try_new().unwrap();
-    let import_name = import_query.get_decorator_name(source).unwrap();
-    let query = AmQuery::try_new(import_name.as_str()).unwrap();
+    let query = AmQuery::try_new(&UserQueries::default()).unwrap();
     let list = query
-        .list_function_names(FILE_NAME, source, DUMMY_MODULE)
+        .list_function_names(FILE_NAME, source, DUMMY_MODULE, &decorator_names(source))
         .unwrap();
-    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_query =
+        AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let all_list = all_query
         .list_function_names(FILE_NAME, source, DUMMY_MODULE)
         .unwrap();
@@ -51,12 +71,14 @@ fn detect_simple() {
         id: ("dummy", "the_one").into(),
         instrumentation: None,
         definition: Some(the_one_location.clone()),
+        ..Default::default()
     };
 
     let the_one_instrumented = FunctionInfo {
         id: ("dummy", "the_one").into(),
         instrumentation: Some(the_one_location.clone()),
         definition: Some(the_one_location),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 1);
@@ -75,13 +97,12 @@ fn detect_alias() {
             return 'wake up, Neo'
         "#;
 
-    let import_query = AmImportQuery::try_new().unwrap();
-    let import_name = import_query.get_decorator_name(source).unwrap();
-    let query = AmQuery::try_new(import_name.as_str()).unwrap();
+    let query = AmQuery::try_new(&UserQueries::default()).unwrap();
     let list = query
-        .list_function_names(FILE_NAME, source, DUMMY_MODULE)
+        .list_function_names(FILE_NAME, source, DUMMY_MODULE, &decorator_names(source))
         .unwrap();
-    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_query =
+        AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let all_list = all_query
         .list_function_names(FILE_NAME, source, DUMMY_MODULE)
         .unwrap();
@@ -104,12 +125,14 @@ fn detect_alias() {
         id: ("dummy", "the_one").into(),
         instrumentation: None,
         definition: Some(the_one_location.clone()),
+        ..Default::default()
     };
 
     let the_one_instrumented = FunctionInfo {
         id: ("dummy", "the_one").into(),
         instrumentation: Some(the_one_location.clone()),
         definition: Some(the_one_location),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 1);
@@ -131,13 +154,12 @@ fn detect_nested() {
             return the_two()
         "#;
 
-    let import_query = AmImportQuery::try_new().unwrap();
-    let import_name = import_query.get_decorator_name(source).unwrap();
-    let query = AmQuery::try_new(import_name.as_str()).unwrap();
+    let query = AmQuery::try_new(&UserQueries::default()).unwrap();
     let list = query
-        .list_function_names(FILE_NAME, source, DUMMY_MODULE)
+        .list_function_names(FILE_NAME, source, DUMMY_MODULE, &decorator_names(source))
         .unwrap();
-    let all_query = AllFunctionsQuery::try_new().unwrap();
+    let all_query =
+        AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
     let all_list = all_query
         .list_function_names(FILE_NAME, source, DUMMY_MODULE)
         .unwrap();
@@ -174,21 +196,25 @@ fn detect_nested() {
         id: ("dummy", "the_one").into(),
         instrumentation: None,
         definition: Some(the_one_location.clone()),
+        ..Default::default()
     };
     let the_two = FunctionInfo {
         id: ("dummy", "the_one.<locals>.the_two").into(),
         instrumentation: None,
         definition: Some(the_two_location.clone()),
+        ..Default::default()
     };
     let the_one_instrumented = FunctionInfo {
         id: ("dummy", "the_one").into(),
         instrumentation: Some(the_one_location.clone()),
         definition: Some(the_one_location),
+        ..Default::default()
     };
     let the_two_instrumented = FunctionInfo {
         id: ("dummy", "the_one.<locals>.the_two").into(),
         instrumentation: Some(the_two_location.clone()),
         definition: Some(the_two_location),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 2);
@@ -198,3 +224,343 @@ fn detect_nested() {
     assert!(all_list.contains(&the_one));
     assert!(all_list.contains(&the_two));
 }
+
+#[test]
+fn detect_module_imports() {
+    let source = r#"
+        import autometrics
+        import autometrics as am
+
+        @autometrics.autometrics
+        def plain():
+            pass
+
+        @am.autometrics(objective=API_SLO)
+        def aliased():
+            pass
+
+        @other.autometrics
+        def not_instrumented():
+            pass
+        "#;
+
+    assert_eq!(
+        instrumented_names(source, &decorator_names(source)),
+        vec!["aliased", "plain"]
+    );
+}
+
+#[test]
+fn detect_aliased_decorator() {
+    let source = r#"
+        from autometrics import autometrics as am
+
+        @am
+        def aliased():
+            pass
+
+        @autometrics
+        def not_imported():
+            pass
+        "#;
+
+    assert_eq!(
+        instrumented_names(source, &decorator_names(source)),
+        vec!["aliased"]
+    );
+}
+
+#[test]
+fn detect_project_reexports() {
+    let telemetry = r#"
+        from autometrics import autometrics as instrumented
+        "#;
+    let utils = r#"
+        from .telemetry import instrumented
+        "#;
+    let source = r#"
+        from myapp import utils
+        from myapp.utils import instrumented as track
+
+        @track
+        def by_name():
+            pass
+
+        @utils.instrumented
+        def by_module():
+            pass
+        "#;
+
+    let import_query =
+        AmImportQuery::try_new(&UserQueries::default(), &SyntaxErrors::default()).unwrap();
+    let imports = |module: &str, is_package: bool, source: &str| {
+        import_query
+            .list_imports(FILE_NAME, source)
+            .unwrap()
+            .into_iter()
+            .map(|import| import.into_absolute(module, is_package))
+            .collect::<Vec<_>>()
+    };
+    let modules = HashMap::from([
+        (
+            "myapp.telemetry".to_string(),
+            imports("myapp.telemetry", false, telemetry),
+        ),
+        (
+            "myapp.utils".to_string(),
+            imports("myapp.utils", false, utils),
+        ),
+        (
+            "myapp.main".to_string(),
+            imports("myapp.main", false, source),
+        ),
+    ]);
+    let exports = DecoratorExports::from_project(&modules);
+    let names = exports.decorator_names(&modules["myapp.main"]);
+
+    assert_eq!(
+        instrumented_names(source, &names),
+        vec!["by_module", "by_name"]
+    );
+}
+
+#[test]
+fn resolve_project_reexports() {
+    let dir = test_project(&[
+        (
+            "billing/telemetry.py",
+            "from autometrics import autometrics as instrumented\n",
+        ),
+        (
+            "billing/api.py",
+            "from telemetry import instrumented\n\n@instrumented\ndef charge():\n    pass\n",
+        ),
+        (
+            "search/telemetry.py",
+            "def instrumented(func):\n    return func\n",
+        ),
+        (
+            "search/api.py",
+            "from telemetry import instrumented\n\n@instrumented\ndef query():\n    pass\n",
+        ),
+        (
+            "scripts/report.py",
+            "from telemetry import instrumented\n\n@instrumented\ndef report():\n    pass\n",
+        ),
+        (
+            "jobs/nightly.py",
+            "from billing_metrics import instrumented\n\n@instrumented\ndef nightly():\n    pass\n",
+        ),
+        (
+            "shared/billing_metrics.py",
+            "from autometrics import autometrics as instrumented\n",
+        ),
+    ]);
+
+    let mut functions: Vec<_> = Impl::default()
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+    functions.sort();
+
+    // `search.api` imports its sibling module, that does not export the
+    // decorator, and `scripts.report` could import both telemetry modules.
+    // The only `billing_metrics` module is found from any root.
+    assert_eq!(
+        functions,
+        vec![
+            ("billing.api".to_string(), "charge".to_string()),
+            ("jobs.nightly".to_string(), "nightly".to_string()),
+        ]
+    );
+}
+
+/// Create the given (empty) files in a temporary directory.
+fn project(files: &[&str]) -> tempfile::TempDir {
+    let files: Vec<(&str, &str)> = files.iter().map(|file| (*file, "")).collect();
+    test_project(&files)
+}
+
+#[test]
+fn module_names_regular_packages() {
+    let dir = project(&[
+        "app/__init__.py",
+        "app/api/__init__.py",
+        "app/api/routes.py",
+        "app/__main__.py",
+        "scripts/ns/tool.py",
+        "main.py",
+    ]);
+    let layout = ProjectLayout::discover(dir.path()).unwrap();
+    let module = |path: &str| layout.module_name(Path::new(path)).unwrap();
+
+    assert_eq!(module("app/__init__.py"), "app");
+    assert_eq!(module("app/api/routes.py"), "app.api.routes");
+    assert_eq!(module("app/__main__.py"), "__main__");
+    assert_eq!(
+        layout.import_path(Path::new("app/__main__.py")).unwrap(),
+        "app.__main__"
+    );
+    assert_eq!(module("scripts/ns/tool.py"), "scripts.ns.tool");
+    assert_eq!(module("main.py"), "main");
+
+    // Scanning from a sub-package still gives the full import path.
+    let layout = ProjectLayout::discover(&dir.path().join("app/api")).unwrap();
+    assert_eq!(
+        layout.module_name(Path::new("routes.py")).unwrap(),
+        "app.api.routes"
+    );
+}
+
+#[test]
+fn module_names_src_layout() {
+    let dir = project(&[
+        "src/app/__init__.py",
+        "src/app/core.py",
+        "tests/test_core.py",
+    ]);
+    let layout = ProjectLayout::discover(dir.path()).unwrap();
+
+    assert_eq!(
+        layout.module_name(Path::new("src/app/core.py")).unwrap(),
+        "app.core"
+    );
+    assert_eq!(
+        layout.module_name(Path::new("tests/test_core.py")).unwrap(),
+        "tests.test_core"
+    );
+}
+
+#[test]
+fn module_names_declared_packages() {
+    let dir = project(&[
+        "lib/core.py",
+        "python/app/__init__.py",
+        "python/app/core.py",
+    ]);
+    std::fs::write(
+        dir.path().join("pyproject.toml"),
+        r#"
+        [tool.setuptools.package-dir]
+        mylib = "lib"
+
+        [tool.setuptools.packages.find]
+        where = ["python"]
+        "#,
+    )
+    .unwrap();
+    let layout = ProjectLayout::discover(dir.path()).unwrap();
+    assert_eq!(
+        layout.module_name(Path::new("lib/core.py")).unwrap(),
+        "mylib.core"
+    );
+    assert_eq!(
+        layout.module_name(Path::new("python/app/core.py")).unwrap(),
+        "app.core"
+    );
+
+    std::fs::remove_file(dir.path().join("pyproject.toml")).unwrap();
+    std::fs::write(
+        dir.path().join("setup.cfg"),
+        "[metadata]\nname = app\n\n[options]\npackage_dir =\n    =python\n    mylib = lib\n",
+    )
+    .unwrap();
+    let layout = ProjectLayout::discover(dir.path()).unwrap();
+    assert_eq!(
+        layout.module_name(Path::new("lib/core.py")).unwrap(),
+        "mylib.core"
+    );
+    assert_eq!(
+        layout.module_name(Path::new("python/app/core.py")).unwrap(),
+        "app.core"
+    );
+}
+
+#[test]
+fn exclude_test_files() {
+    let dir = test_project(&[
+        ("app/__init__.py", ""),
+        ("app/server.py", "def serve():\n    pass\n"),
+        ("tests/__init__.py", ""),
+        ("tests/test_server.py", "def test_serve():\n    pass\n"),
+        ("tests/server_test.py", "def test_routes():\n    pass\n"),
+        ("tests/conftest.py", "def client():\n    pass\n"),
+    ]);
+
+    let functions = |implementation: &mut Impl| {
+        let mut functions: Vec<_> = implementation
+            .list_all_function_definitions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| info.id.function)
+            .collect();
+        functions.sort();
+        functions
+    };
+
+    let mut implementation = Impl::default();
+    assert_eq!(
+        functions(&mut implementation),
+        vec!["client", "serve", "test_routes", "test_serve"]
+    );
+
+    implementation.exclude_tests = true;
+    assert_eq!(functions(&mut implementation), vec!["serve"]);
+}
+
+#[test]
+fn detect_with_user_queries() {
+    let queries_dir = test_project(&[(
+        "python/autometrics.extra.scm",
+        r#"
+(decorated_definition
+  (decorator (identifier) @decorator.name)
+  definition: (function_definition
+               name: (identifier) @func.name)
+  (#eq? @decorator.name "instrumented"))
+
+(decorated_definition
+  (decorator (identifier) @traced)
+  definition: (function_definition
+               name: (identifier) @func.name)
+  (#eq? @traced "traced"))
+"#,
+    )]);
+    let dir = test_project(&[
+        ("app/__init__.py", ""),
+        (
+            "app/server.py",
+            r#"
+from app.tracing import instrumented, traced, logged
+
+@instrumented
+def serve():
+    pass
+
+@traced
+def route():
+    pass
+
+@logged
+def log():
+    pass
+"#,
+        ),
+    ]);
+
+    let mut implementation = Impl {
+        queries: UserQueries::load(queries_dir.path()).unwrap(),
+        ..Impl::default()
+    };
+    let mut functions: Vec<_> = implementation
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| info.id.function)
+        .collect();
+    functions.sort();
+
+    assert_eq!(functions, vec!["route", "serve"]);
+}
@@ -1,7 +1,20 @@
+mod attributes;
 mod queries;
+mod targets;
+mod test_code;
 
-use self::queries::{AllFunctionsQuery, AmQuery};
-use crate::{FunctionInfo, ListAmFunctions, Result};
+pub use targets::TargetKind;
+
+use self::{
+    queries::{AllFunctionsQuery, AmQuery},
+    targets::CargoTargets,
+    test_code::TestFiles,
+};
+use crate::{
+    config::FileFilter, user_queries::UserQueries, FunctionInfo, Lint, ListAmFunctions, Location,
+    Result, SyntaxErrors,
+};
+use log::warn;
 use rayon::prelude::*;
 use std::{
     collections::{HashSet, VecDeque},
@@ -10,15 +23,22 @@ use std::{
 };
 use walkdir::{DirEntry, WalkDir};
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct AmStruct {
-    module: String,
-    strc: String,
-}
-
 /// Implementation of the Rust support for listing autometricized functions.
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Impl {}
+#[derive(Clone, Debug, Default)]
+pub struct Impl {
+    /// Filter on the files to scan in the project.
+    pub filter: FileFilter,
+    /// User-supplied queries that override or extend the built-in ones.
+    pub queries: UserQueries,
+    /// Kinds of the cargo targets to list the functions of. All the targets
+    /// are listed when empty.
+    pub targets: Vec<TargetKind>,
+    /// Skip the test code: `#[test]` functions, `#[cfg(test)]` modules and
+    /// the integration tests.
+    pub exclude_tests: bool,
+    /// Syntax errors found in the files parsed by the listings.
+    pub syntax_errors: SyntaxErrors,
+}
 
 impl Impl {
     fn is_hidden(entry: &DirEntry) -> bool {
@@ -76,71 +96,121 @@ impl Impl {
 
         itertools::intersperse(mod_name_elements, "::".to_string()).collect()
     }
-}
 
-impl ListAmFunctions for Impl {
-    fn list_autometrics_functions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
+    /// Tag the functions with the cargo target of the file defining them, and
+    /// keep only the functions of the selected target kinds.
+    ///
+    /// Functions in files that do not belong to a known target are kept.
+    fn set_targets(&self, project_root: &Path, functions: &mut Vec<FunctionInfo>) -> Result<()> {
+        let cargo_targets = CargoTargets::discover(project_root)?;
+        functions.retain_mut(|function| {
+            let target = function
+                .definition
+                .as_ref()
+                .or(function.instrumentation.as_ref())
+                .and_then(|location| cargo_targets.target_of(Path::new(&location.file)));
+            let keep = self.targets.is_empty()
+                || target
+                    .as_ref()
+                    .map_or(true, |target| self.targets.contains(&target.kind));
+            function.target = target.map(|target| target.to_string());
+            keep
+        });
+        Ok(())
+    }
+
+    /// Walk the source files and directories of the project.
+    fn walk_sources(&self, project_root: &Path) -> Vec<DirEntry> {
+        WalkDir::new(project_root)
+            .into_iter()
+            .filter_entry(|entry| Self::is_valid(entry) && self.filter.accepts(project_root, entry))
+            .filter_map(|entry| entry.ok())
+            .collect()
+    }
+
+    /// Find the files that only contain test code, when they are excluded.
+    fn test_files(&self, project_root: &Path, entries: &[DirEntry]) -> Result<Option<TestFiles>> {
+        if !self.exclude_tests {
+            return Ok(None);
+        }
+        TestFiles::discover(project_root, entries).map(Some)
+    }
+
+    fn is_test_file(project_root: &Path, test_files: Option<&TestFiles>, path: &Path) -> bool {
+        test_files.map_or(false, |test_files| {
+            path.strip_prefix(project_root)
+                .map_or(false, |relative_path| test_files.contains(relative_path))
+        })
+    }
+
+    /// List the paths of the source files of the project, with their module name.
+    fn source_mod_pairs(&self, project_root: &Path) -> Result<Vec<(String, String)>> {
+        let entries = self.walk_sources(project_root);
+        let test_files = self.test_files(project_root, &entries)?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| !Self::is_test_file(project_root, test_files.as_ref(), entry.path()))
+            .map(|entry| {
+                let module = Self::fully_qualified_module_name(&entry);
+                (
+                    entry
+                        .path()
+                        .to_str()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                    module,
+                )
+            })
+            .collect())
+    }
+
+    /// List the instrumented functions of the project, and the lints about
+    /// them, parsing each file once.
+    fn list_instrumented(&self, project_root: &Path) -> Result<(Vec<FunctionInfo>, Vec<Lint>)> {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
 
-        let walker = WalkDir::new(project_root).into_iter();
-        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        let query = AmQuery::try_new()?;
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            let module = Self::fully_qualified_module_name(&entry);
-            Some((
-                entry
-                    .path()
+        let query = AmQuery::try_new(&self.queries, &self.syntax_errors)?
+            .with_tests_excluded(self.exclude_tests);
+        let source_mod_pairs = self.source_mod_pairs(project_root)?;
+
+        let (functions, lints): (Vec<_>, Vec<_>) = source_mod_pairs
+            .par_iter()
+            .filter_map(move |(path, module)| {
+                let source = read_to_string(path).ok()?;
+                let file_name = PathBuf::from(path)
+                    .strip_prefix(project_root)
+                    .expect("path comes from a project_root WalkDir")
                     .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-                module,
-            ))
-        }));
-
-        list.par_extend(
-            source_mod_pairs
-                .par_iter()
-                .filter_map(move |(path, module)| {
-                    let source = read_to_string(path).ok()?;
-                    let file_name = PathBuf::from(path)
-                        .strip_prefix(project_root)
-                        .expect("path comes from a project_root WalkDir")
-                        .to_str()
-                        .expect("file_name is a valid path as it is part of `path`")
-                        .to_string();
-                    let am_functions = query
-                        .list_function_names(&file_name, module.clone(), &source)
-                        .unwrap_or_default();
-                    Some(am_functions)
-                }),
-        );
+                    .expect("file_name is a valid path as it is part of `path`")
+                    .to_string();
+                query
+                    .list_functions_and_lints(&file_name, module.clone(), &source)
+                    .ok()
+            })
+            .unzip();
+        list.extend(functions.into_iter().flatten());
 
         let mut result = Vec::with_capacity(PREALLOCATED_ELEMS);
-        result.extend(list.into_iter().flatten());
-        Ok(result)
+        result.extend(list);
+        self.set_targets(project_root, &mut result)?;
+        Ok((result, lints.into_iter().flatten().collect()))
+    }
+}
+
+impl ListAmFunctions for Impl {
+    fn list_autometrics_functions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
+        let (functions, _) = self.list_instrumented(project_root)?;
+        Ok(functions)
     }
 
     fn list_all_function_definitions(&mut self, project_root: &Path) -> Result<Vec<FunctionInfo>> {
         const PREALLOCATED_ELEMS: usize = 400;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
 
-        let walker = WalkDir::new(project_root).into_iter();
-        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        let query = AllFunctionsQuery::try_new()?;
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            let module = Self::fully_qualified_module_name(&entry);
-            Some((
-                entry
-                    .path()
-                    .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-                module,
-            ))
-        }));
+        let query = AllFunctionsQuery::try_new(&self.queries, &self.syntax_errors)?
+            .with_tests_excluded(self.exclude_tests);
+        let source_mod_pairs = self.source_mod_pairs(project_root)?;
 
         list.par_extend(
             source_mod_pairs
@@ -162,8 +232,23 @@ impl ListAmFunctions for Impl {
 
         let mut result = Vec::with_capacity(PREALLOCATED_ELEMS);
         result.extend(list.into_iter().flatten());
+        self.set_targets(project_root, &mut result)?;
         Ok(result)
     }
+
+    fn list_syntax_errors(&mut self) -> Vec<Location> {
+        self.syntax_errors.take()
+    }
+
+    fn list_lints(&mut self, project_root: &Path) -> Result<Vec<Lint>> {
+        let (_, mut lints) = self.list_instrumented(project_root)?;
+
+        lints.sort();
+        for lint in &lints {
+            warn!("{lint}");
+        }
+        Ok(lints)
+    }
 }
 
 #[cfg(test)]
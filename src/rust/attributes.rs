@@ -0,0 +1,204 @@
+//! Recognition of the autometrics attribute macro.
+//!
+//! Besides the plain `#[autometrics]`, the attribute can be path-qualified
+//! (`#[autometrics::autometrics]`), renamed through a `use` declaration of the
+//! file (`use autometrics::autometrics as am;` then `#[am]`), and applied
+//! conditionally with `#[cfg_attr(feature = "metrics", autometrics)]`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use log::warn;
+
+/// Name of the autometrics crate.
+const AUTOMETRICS_CRATE: &str = "autometrics";
+/// Name of the autometrics attribute macro in the autometrics crate.
+const AUTOMETRICS_MACRO: &str = "autometrics";
+/// Name of the conditional attribute.
+const CFG_ATTR: &str = "cfg_attr";
+/// Arguments accepted by the autometrics attribute macro.
+const MACRO_ARGUMENTS: [&str; 5] = [
+    "objective",
+    "track_concurrency",
+    "ok_if",
+    "error_if",
+    "struct_name",
+];
+
+/// An item imported by a `use` declaration.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UseImport {
+    /// Path segments of the imported item (`["autometrics", "autometrics"]`).
+    pub path: Vec<String>,
+    /// Name under which the item is imported in the file.
+    pub alias: String,
+}
+
+/// The names under which the autometrics attribute can be used in a file.
+#[derive(Clone, Debug)]
+pub struct AttributeNames {
+    /// Names of the attribute macro itself.
+    macros: HashSet<String>,
+    /// Names of the autometrics crate, to use the macro as `#[crate::autometrics]`.
+    crates: HashSet<String>,
+}
+
+impl Default for AttributeNames {
+    fn default() -> Self {
+        Self {
+            macros: HashSet::from([AUTOMETRICS_MACRO.to_string()]),
+            crates: HashSet::from([AUTOMETRICS_CRATE.to_string()]),
+        }
+    }
+}
+
+/// An autometrics attribute found on an item.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AmAttribute {
+    /// The `cfg` predicate under which the attribute applies, for attributes
+    /// given through `cfg_attr`.
+    pub condition: Option<String>,
+    /// The arguments of the attribute macro, by name, with the source text
+    /// of their value.
+    ///
+    /// Flags (`track_concurrency`) have the value `true`, and string literals
+    /// (`struct_name = "Foo"`) are unquoted.
+    pub options: Option<BTreeMap<String, String>>,
+}
+
+impl AttributeNames {
+    /// Add the aliases of the crate and the macro declared by the `use`
+    /// declarations of a file.
+    pub fn with_imports<'a>(mut self, imports: impl IntoIterator<Item = &'a UseImport>) -> Self {
+        for import in imports {
+            if import.alias == "_" {
+                continue;
+            }
+            match import
+                .path
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .as_slice()
+            {
+                [AUTOMETRICS_CRATE, AUTOMETRICS_MACRO] => {
+                    self.macros.insert(import.alias.clone());
+                }
+                [AUTOMETRICS_CRATE] => {
+                    self.crates.insert(import.alias.clone());
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+
+    /// Return the autometrics attribute described by the contents of an
+    /// attribute (the text between `#[` and `]`), if it is one.
+    pub fn parse(&self, attribute: &str) -> Option<AmAttribute> {
+        let (path, arguments) = split_attribute(attribute);
+        if path == CFG_ATTR {
+            let mut parts = split_top_level(arguments?).into_iter();
+            let condition = normalize_whitespace(parts.next()?);
+            return parts.find_map(|inner| {
+                let inner = self.parse(inner)?;
+                Some(AmAttribute {
+                    condition: Some(match inner.condition {
+                        Some(inner_condition) => format!("all({condition}, {inner_condition})"),
+                        None => condition.clone(),
+                    }),
+                    options: inner.options,
+                })
+            });
+        }
+
+        let segments: Vec<&str> = path.split("::").filter(|s| !s.is_empty()).collect();
+        let is_autometrics = match segments.as_slice() {
+            [name] => self.macros.contains(*name),
+            [krate, AUTOMETRICS_MACRO] => self.crates.contains(*krate),
+            _ => false,
+        };
+        is_autometrics.then(|| AmAttribute {
+            condition: None,
+            options: arguments
+                .map(macro_options)
+                .filter(|options| !options.is_empty()),
+        })
+    }
+}
+
+/// Parse the arguments of the autometrics attribute macro
+/// (`objective = API_SLO, track_concurrency`).
+fn macro_options(arguments: &str) -> BTreeMap<String, String> {
+    split_top_level(arguments)
+        .into_iter()
+        .map(|argument| {
+            let (name, value) = match argument.split_once('=') {
+                Some((name, value)) => {
+                    let value = value.trim();
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .map_or_else(|| normalize_whitespace(value), ToString::to_string);
+                    (name.trim().to_string(), value)
+                }
+                None => (argument.trim().to_string(), "true".to_string()),
+            };
+            if !MACRO_ARGUMENTS.contains(&name.as_str()) {
+                warn!("Unknown argument of the autometrics attribute: {name}");
+            }
+            (name, value)
+        })
+        .collect()
+}
+
+/// Split the contents of an attribute into its path, without whitespace, and
+/// the contents of its parenthesized arguments if any.
+pub(super) fn split_attribute(attribute: &str) -> (String, Option<&str>) {
+    let attribute = attribute.trim();
+    let path_end = attribute
+        .find(['(', '=', '[', '{'])
+        .unwrap_or(attribute.len());
+    let path = attribute[..path_end].split_whitespace().collect();
+    let arguments = attribute[path_end..]
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'));
+    (path, arguments)
+}
+
+/// Split a list of arguments on the commas that are not nested in
+/// parentheses, brackets, braces or string literals.
+pub(super) fn split_top_level(arguments: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in arguments.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&arguments[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&arguments[start..]);
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
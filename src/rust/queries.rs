@@ -1,24 +1,222 @@
-use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
+use super::{
+    attributes::{AmAttribute, AttributeNames, UseImport},
+    test_code::is_test_code,
+};
+use crate::{
+    user_queries::UserQueries, AmlError, FunctionInfo, FunctionKind, Lint, Location, Result,
+    SyntaxErrors, FUNC_NAME_CAPTURE,
+};
 use log::{trace, warn};
-use tree_sitter::{Node, Parser, Query};
+use tree_sitter::{Node, Parser, Query, Tree};
 use tree_sitter_rust::language;
 
 const ANNOTATED_IMPL_NAME_CAPTURE: &str = "type.impl";
 const ANNOTATED_IMPL_METHOD_NAME_CAPTURE: &str = "inner.func.name";
+const ANNOTATED_IMPL_TRAIT_CAPTURE: &str = "inner.trait";
+const AM_ATTRIBUTE_CAPTURE: &str = "am.attribute";
+const IMPORT_TREE_CAPTURE: &str = "import.tree";
 const MOD_NAME_CAPTURE: &str = "mod.name";
 const MOD_CONTENTS_CAPTURE: &str = "mod.contents";
 const IMPL_NAME_CAPTURE: &str = "impl.type";
 const IMPL_CONTENTS_CAPTURE: &str = "impl.contents";
+const IMPL_TRAIT_CAPTURE: &str = "impl.trait";
 
 const GRAMMAR_IMPL_ITEM_NODE_KIND: &str = "impl_item";
 const GRAMMAR_MOD_ITEM_NODE_KIND: &str = "mod_item";
+const GRAMMAR_FUNCTION_ITEM_NODE_KIND: &str = "function_item";
+const GRAMMAR_TRAIT_ITEM_NODE_KIND: &str = "trait_item";
 
-fn new_parser() -> Result<Parser> {
+/// Options of the autometrics attribute that classify the results of a
+/// function without looking at its return type.
+const RESULT_CLASSIFICATION_OPTIONS: [&str; 2] = ["ok_if", "error_if"];
+
+pub(super) fn new_parser() -> Result<Parser> {
     let mut parser = Parser::new();
     parser.set_language(language())?;
     Ok(parser)
 }
 
+/// The impl block that defines methods.
+#[derive(Clone, Debug)]
+struct ImplBlock {
+    /// Name of the type of the block, as reported by autometrics.
+    type_name: String,
+    /// Trait implemented by the block, if any.
+    trait_name: Option<String>,
+}
+
+impl ImplBlock {
+    /// Build the impl block from the `type` and `trait` nodes of an
+    /// `impl_item`.
+    fn from_nodes(type_node: Node, trait_node: Option<Node>, source: &str) -> Option<Self> {
+        let type_name = match impl_type_name(type_node, source) {
+            Ok(name) => name,
+            Err(e) => {
+                warn!("Could not extract the type name of the impl block: {e}");
+                return None;
+            }
+        };
+        let trait_name = match trait_node.map(|node| node.utf8_text(source.as_bytes())) {
+            Some(Ok(name)) => Some(name.to_string()),
+            Some(Err(e)) => {
+                warn!("Could not extract the trait name of the impl block: {e}");
+                return None;
+            }
+            None => None,
+        };
+        Some(Self {
+            type_name,
+            trait_name,
+        })
+    }
+}
+
+/// Return the name of the type of an impl block, the way autometrics reports
+/// it in the function label of methods: the last segment of the path of the
+/// type, without the generic arguments (`Repo` for `impl<T> crate::db::Repo<T>`).
+fn impl_type_name(
+    type_node: Node,
+    source: &str,
+) -> std::result::Result<String, std::str::Utf8Error> {
+    let inner = match type_node.kind() {
+        "generic_type" | "reference_type" | "pointer_type" => type_node.child_by_field_name("type"),
+        "scoped_type_identifier" => type_node.child_by_field_name("name"),
+        _ => None,
+    };
+    match inner {
+        Some(inner) => impl_type_name(inner, source),
+        None => type_node
+            .utf8_text(source.as_bytes())
+            .map(ToString::to_string),
+    }
+}
+
+/// Flatten a `use` tree into the list of the items it imports.
+fn flatten_use_tree(node: Node, prefix: &[String], source: &str, imports: &mut Vec<UseImport>) {
+    let segments = |node: Node| -> Vec<String> {
+        let mut segments = prefix.to_vec();
+        segments.extend(
+            node.utf8_text(source.as_bytes())
+                .unwrap_or_default()
+                .split("::")
+                .map(str::trim)
+                .filter(|segment| !segment.is_empty())
+                .map(ToString::to_string),
+        );
+        // `use autometrics::{self}` imports the crate itself.
+        if segments.last().map_or(false, |segment| segment == "self") {
+            segments.pop();
+        }
+        segments
+    };
+
+    match node.kind() {
+        "use_as_clause" => {
+            let (Some(path), Some(alias)) = (
+                node.child_by_field_name("path"),
+                node.child_by_field_name("alias"),
+            ) else {
+                return;
+            };
+            let Ok(alias) = alias.utf8_text(source.as_bytes()) else {
+                return;
+            };
+            imports.push(UseImport {
+                path: segments(path),
+                alias: alias.to_string(),
+            });
+        }
+        "scoped_use_list" => {
+            let prefix = node
+                .child_by_field_name("path")
+                .map(segments)
+                .unwrap_or_else(|| prefix.to_vec());
+            if let Some(list) = node.child_by_field_name("list") {
+                flatten_use_tree(list, &prefix, source, imports);
+            }
+        }
+        "use_list" => {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                flatten_use_tree(child, prefix, source, imports);
+            }
+        }
+        "identifier" | "scoped_identifier" | "self" | "crate" | "super" => {
+            let path = segments(node);
+            if let Some(alias) = path.last().cloned() {
+                imports.push(UseImport { path, alias });
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Return the kind of the function named by `fn_node`, found under `max_parent`
+/// while recursing in `current_impl`, along with the prefix of its name and the
+/// trait it implements.
+///
+/// Like in the labels of autometrics, default methods of traits are prefixed
+/// with the name of the trait, and nested functions are not prefixed at all.
+fn function_kind(
+    fn_node: Node,
+    max_parent: Node,
+    current_impl: &Option<ImplBlock>,
+    source: &str,
+) -> (FunctionKind, String, Option<String>) {
+    let mut walk = fn_node
+        .parent()
+        .and_then(|function_item| function_item.parent());
+    while let Some(ancestor) = walk {
+        if ancestor.id() == max_parent.id() {
+            break;
+        }
+        match ancestor.kind() {
+            GRAMMAR_FUNCTION_ITEM_NODE_KIND => {
+                return (FunctionKind::NestedFunction, String::new(), None);
+            }
+            GRAMMAR_TRAIT_ITEM_NODE_KIND => {
+                let trait_name = ancestor
+                    .child_by_field_name("name")
+                    .and_then(|name| name.utf8_text(source.as_bytes()).ok())
+                    .unwrap_or_default();
+                return (
+                    FunctionKind::TraitDefaultMethod,
+                    format!("{trait_name}::"),
+                    None,
+                );
+            }
+            _ => {}
+        }
+        walk = ancestor.parent();
+    }
+
+    match current_impl {
+        Some(block) => (
+            FunctionKind::Method,
+            format!("{}::", block.type_name),
+            block.trait_name.clone(),
+        ),
+        None => (FunctionKind::Function, String::new(), None),
+    }
+}
+
+/// Return true if the return type of a `function_item` can carry an error,
+/// so that autometrics can report it in the `result` label.
+///
+/// Any type named `...Result` is considered a `Result` alias, and `impl Trait`
+/// types are accepted if they mention a `Result` (`impl Future<Output = Result<..>>`).
+fn returns_result(function_item: Node, source: &str) -> bool {
+    let Some(return_type) = function_item.child_by_field_name("return_type") else {
+        return false;
+    };
+    if return_type.kind() == "abstract_type" {
+        return return_type
+            .utf8_text(source.as_bytes())
+            .map_or(false, |text| text.contains("Result"));
+    }
+    impl_type_name(return_type, source).map_or(false, |name| name.ends_with("Result"))
+}
+
 fn is_within_mod_item(node: Node, max_parent: Option<Node>, source: &str) -> bool {
     let mut walk = node;
     loop {
@@ -97,6 +295,16 @@ pub(super) struct AmQuery {
     /// This is an option, because when we want to list all functions, we do not want to use
     /// this capture ever (we will instead recurse into every impl block.)
     annotated_impl_method_name_idx: u32,
+    /// Index of the capture for the trait of an `#[autometrics]`-annotated impl block
+    annotated_impl_trait_idx: u32,
+    /// Index of the capture for the attribute that instruments a function or an impl block.
+    ///
+    /// Matches without this capture (from user-supplied queries) are kept as is.
+    am_attribute_idx: u32,
+    /// Query for the `use` declarations, to find the aliases of the attribute.
+    import_query: Query,
+    /// Index of the capture for the tree of a `use` declaration.
+    import_tree_idx: u32,
     /// Index of the capture for the name of a module that is defined in file.
     mod_name_idx: u32,
     /// Index of the capture for the contents of a module that is defined in file.
@@ -105,85 +313,212 @@ pub(super) struct AmQuery {
     impl_type_idx: u32,
     /// Index of a capture for the contents (the declarations) associated to any impl block in the file.
     impl_contents_idx: u32,
+    /// Index of a capture for the trait implemented by any impl block in the file.
+    impl_trait_idx: u32,
+    /// Skip the test functions and the items of test modules.
+    exclude_tests: bool,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AmQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(user_queries: &UserQueries, syntax_errors: &SyntaxErrors) -> Result<Self> {
+        let loaded = user_queries.query(
             language(),
+            "rust/autometrics.scm",
             include_str!("../../runtime/queries/rust/autometrics.scm"),
+            &[],
         )?;
-
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.into()))?;
-        let annotated_impl_type_name_idx = query
-            .capture_index_for_name(ANNOTATED_IMPL_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(ANNOTATED_IMPL_NAME_CAPTURE.into()))?;
-        let annotated_impl_method_name_idx = query
-            .capture_index_for_name(ANNOTATED_IMPL_METHOD_NAME_CAPTURE)
-            .ok_or_else(|| {
-                AmlError::MissingNamedCapture(ANNOTATED_IMPL_METHOD_NAME_CAPTURE.into())
-            })?;
-        let mod_name_idx = query
-            .capture_index_for_name(MOD_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(MOD_NAME_CAPTURE.into()))?;
-        let mod_contents_idx = query
-            .capture_index_for_name(MOD_CONTENTS_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(MOD_NAME_CAPTURE.into()))?;
-        let impl_type_idx = query
-            .capture_index_for_name(IMPL_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPL_NAME_CAPTURE.into()))?;
-        let impl_contents_idx = query
-            .capture_index_for_name(IMPL_CONTENTS_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPL_CONTENTS_CAPTURE.into()))?;
+        let imports = user_queries.query(
+            language(),
+            "rust/imports.scm",
+            include_str!("../../runtime/queries/rust/imports.scm"),
+            &[],
+        )?;
+        let import_tree_idx = imports.capture_index(IMPORT_TREE_CAPTURE)?;
+
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let annotated_impl_type_name_idx = loaded.capture_index(ANNOTATED_IMPL_NAME_CAPTURE)?;
+        let annotated_impl_method_name_idx =
+            loaded.capture_index(ANNOTATED_IMPL_METHOD_NAME_CAPTURE)?;
+        let annotated_impl_trait_idx = loaded.capture_index(ANNOTATED_IMPL_TRAIT_CAPTURE)?;
+        let am_attribute_idx = loaded.capture_index(AM_ATTRIBUTE_CAPTURE)?;
+        let mod_name_idx = loaded.capture_index(MOD_NAME_CAPTURE)?;
+        let mod_contents_idx = loaded.capture_index(MOD_CONTENTS_CAPTURE)?;
+        let impl_type_idx = loaded.capture_index(IMPL_NAME_CAPTURE)?;
+        let impl_contents_idx = loaded.capture_index(IMPL_CONTENTS_CAPTURE)?;
+        let impl_trait_idx = loaded.capture_index(IMPL_TRAIT_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
             func_name_idx,
             annotated_impl_type_name_idx,
             annotated_impl_method_name_idx,
+            annotated_impl_trait_idx,
+            am_attribute_idx,
+            import_query: imports.query,
+            import_tree_idx,
             mod_name_idx,
             mod_contents_idx,
             impl_type_idx,
             impl_contents_idx,
+            impl_trait_idx,
+            exclude_tests: false,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
+    /// Skip the test functions, and the functions of the test modules.
+    pub fn with_tests_excluded(mut self, exclude_tests: bool) -> Self {
+        self.exclude_tests = exclude_tests;
+        self
+    }
+
+    #[cfg(test)]
     pub fn list_function_names(
         &self,
         file_name: &str,
         module: String,
         source: &str,
     ) -> Result<Vec<FunctionInfo>> {
+        let parsed_source = self.parse(file_name, source)?;
+        self.list_functions_in(file_name, module, &parsed_source, source)
+    }
+
+    /// List the instrumented functions of the file, along with the functions
+    /// among them that always report an `ok` result, because they do not
+    /// return a `Result` and do not classify their results with `ok_if` or
+    /// `error_if`.
+    ///
+    /// The error ratio of these functions is always zero, which makes their
+    /// success rate objectives meaningless.
+    pub fn list_functions_and_lints(
+        &self,
+        file_name: &str,
+        module: String,
+        source: &str,
+    ) -> Result<(Vec<FunctionInfo>, Vec<Lint>)> {
+        let parsed_source = self.parse(file_name, source)?;
+        let functions = self.list_functions_in(file_name, module, &parsed_source, source)?;
+        let lints = functions
+            .iter()
+            .filter_map(|function| {
+                if function.options.as_ref().map_or(false, |options| {
+                    RESULT_CLASSIFICATION_OPTIONS
+                        .iter()
+                        .any(|option| options.contains_key(*option))
+                }) {
+                    return None;
+                }
+                let location = function.instrumentation.clone()?;
+                let range = &location.range;
+                let name_node = parsed_source.root_node().descendant_for_point_range(
+                    tree_sitter::Point::new(range.start.line, range.start.column),
+                    tree_sitter::Point::new(range.end.line, range.end.column),
+                )?;
+                let function_item = name_node
+                    .parent()
+                    .filter(|parent| parent.kind() == GRAMMAR_FUNCTION_ITEM_NODE_KIND)?;
+                if returns_result(function_item, source) {
+                    return None;
+                }
+                Some(Lint {
+                    function: function.id.clone(),
+                    location,
+                    message: "the function does not return a Result, so it always reports an ok result (use error_if to classify errors)".to_string(),
+                })
+            })
+            .collect();
+        Ok((functions, lints))
+    }
+
+    /// Parse the source of the file at `file_name`, recording its syntax errors.
+    fn parse(&self, file_name: &str, source: &str) -> Result<Tree> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
-        self.list_function_rec(file_name, module, None, parsed_source.root_node(), source)
+        self.syntax_errors.record(file_name, &parsed_source);
+        Ok(parsed_source)
+    }
+
+    fn list_functions_in(
+        &self,
+        file_name: &str,
+        module: String,
+        parsed_source: &Tree,
+        source: &str,
+    ) -> Result<Vec<FunctionInfo>> {
+        let attribute_names = AttributeNames::default()
+            .with_imports(&self.list_imports(parsed_source.root_node(), source));
+        let mut functions = self.list_function_rec(
+            file_name,
+            module,
+            None,
+            parsed_source.root_node(),
+            source,
+            &attribute_names,
+        )?;
+
+        // A function can have several attributes matched by the query.
+        functions.sort();
+        functions.dedup();
+        Ok(functions)
+    }
+
+    /// List the items imported by the `use` declarations of the file.
+    fn list_imports(&self, root: Node, source: &str) -> Vec<UseImport> {
+        let mut cursor = tree_sitter::QueryCursor::new();
+        let mut imports = Vec::new();
+        for m in cursor.matches(&self.import_query, root, source.as_bytes()) {
+            for tree in m.nodes_for_capture_index(self.import_tree_idx) {
+                flatten_use_tree(tree, &[], source, &mut imports);
+            }
+        }
+        imports
+    }
+
+    /// Return the autometrics attribute captured in a match, if the match is
+    /// an instrumentation.
+    fn matched_attribute(
+        &self,
+        capture: &tree_sitter::QueryMatch,
+        source: &str,
+        attribute_names: &AttributeNames,
+    ) -> Option<AmAttribute> {
+        match capture
+            .nodes_for_capture_index(self.am_attribute_idx)
+            .next()
+        {
+            Some(node) => attribute_names.parse(node.utf8_text(source.as_bytes()).ok()?),
+            None => Some(AmAttribute::default()),
+        }
     }
 
     fn list_function_rec(
         &self,
         file_name: &str,
         current_module: String,
-        current_type: Option<String>,
+        current_impl: Option<ImplBlock>,
         node: Node,
         source: &str,
+        attribute_names: &AttributeNames,
     ) -> Result<Vec<FunctionInfo>> {
         let mut res = Vec::new();
         let mut cursor = tree_sitter::QueryCursor::new();
 
         // Detect all functions directly in module scope
         let direct_names = self.list_direct_function_names(
-            &mut cursor,
             node,
             file_name,
             source,
-            &current_type,
+            &current_impl,
             &current_module,
+            attribute_names,
         );
         res.extend(direct_names);
 
@@ -194,6 +529,7 @@ impl AmQuery {
             file_name,
             source,
             &current_module,
+            attribute_names,
         );
         res.extend(impl_block_methods);
 
@@ -242,9 +578,10 @@ impl AmQuery {
                     let inner = self.list_function_rec(
                         file_name,
                         new_module,
-                        current_type.clone(),
+                        current_impl.clone(),
                         contents_node,
                         source,
+                        attribute_names,
                     )?;
                     res.extend(inner)
                 }
@@ -261,17 +598,10 @@ impl AmQuery {
                     continue;
                 }
 
-                let type_name = {
-                    match impl_type_node
-                        .utf8_text(source.as_bytes())
-                        .map(ToString::to_string)
-                    {
-                        Ok(val) => val,
-                        Err(e) => {
-                            warn!("Error extracting the struct name: {e}");
-                            continue;
-                        }
-                    }
+                let trait_node = capture.nodes_for_capture_index(self.impl_trait_idx).next();
+                let Some(impl_block) = ImplBlock::from_nodes(impl_type_node, trait_node, source)
+                else {
+                    continue;
                 };
 
                 if let Some(contents_node) = capture
@@ -281,7 +611,7 @@ impl AmQuery {
                     trace!(
                         "Recursing into impl block {}::{}\n{}\n\n\n",
                         current_module,
-                        type_name,
+                        impl_block.type_name,
                         contents_node
                             .utf8_text(source.as_bytes())
                             .map(ToString::to_string)
@@ -290,9 +620,10 @@ impl AmQuery {
                     let inner = self.list_function_rec(
                         file_name,
                         current_module.clone(),
-                        Some(type_name),
+                        Some(impl_block),
                         contents_node,
                         source,
+                        attribute_names,
                     )?;
                     res.extend(inner)
                 }
@@ -304,23 +635,29 @@ impl AmQuery {
 
     fn list_direct_function_names(
         &self,
-        cursor: &mut tree_sitter::QueryCursor,
         node: Node,
         file_name: &str,
         source: &str,
-        current_type: &Option<String>,
+        current_impl: &Option<ImplBlock>,
         current_module: &str,
+        attribute_names: &AttributeNames,
     ) -> Vec<FunctionInfo> {
+        let mut cursor = tree_sitter::QueryCursor::new();
         cursor
             .matches(&self.query, node, source.as_bytes())
             .filter_map(|capture| -> Option<FunctionInfo> {
                 let fn_node: Node = capture.nodes_for_capture_index(self.func_name_idx).next()?;
+                let attribute = self.matched_attribute(&capture, source, attribute_names)?;
 
                 // Ignore the matches that are within a mod_item, as the recursion will catch it later with the fully qualified module name.
                 if is_within_mod_item(fn_node, Some(node), source) {
                     return None;
                 }
 
+                if self.exclude_tests && is_test_code(fn_node, source) {
+                    return None;
+                }
+
                 // Ignore the matches that are within a impl_item, as the impl_block_names variable below catches those, applying the
                 // fully qualified module name.
                 if is_within_impl_item(fn_node, Some(node), source) {
@@ -336,16 +673,19 @@ impl AmQuery {
                     .utf8_text(source.as_bytes())
                     .map(ToString::to_string);
 
-                let type_prefix: String = current_type
-                    .as_ref()
-                    .map(|t| format!("{t}::"))
-                    .unwrap_or_default();
+                let (kind, type_prefix, implemented_trait) =
+                    function_kind(fn_node, node, current_impl, source);
 
                 match fn_name {
                     Ok(f) => Some(FunctionInfo {
                         id: (current_module, format!("{type_prefix}{f}")).into(),
                         instrumentation,
                         definition,
+                        options: attribute.options,
+                        implemented_trait,
+                        condition: attribute.condition,
+                        kind: Some(kind),
+                        ..Default::default()
                     }),
                     Err(e) => {
                         warn!("Could not get the method name: {e}");
@@ -363,6 +703,7 @@ impl AmQuery {
         file_name: &str,
         source: &str,
         current_module: &str,
+        attribute_names: &AttributeNames,
     ) -> Vec<FunctionInfo> {
         cursor
             .matches(&self.query, node, source.as_bytes())
@@ -370,19 +711,29 @@ impl AmQuery {
                 let fn_node: Node = capture
                     .nodes_for_capture_index(self.annotated_impl_method_name_idx)
                     .next()?;
+                let attribute = self.matched_attribute(&capture, source, attribute_names)?;
 
                 // Ignore the matches that are within a mod_item, as the recursion will catch it later with the fully qualified module name.
                 if is_within_mod_item(fn_node, Some(node), source) {
                     return None;
                 }
 
+                if self.exclude_tests && is_test_code(fn_node, source) {
+                    return None;
+                }
+
                 let fn_name = fn_node
                     .utf8_text(source.as_bytes())
                     .map(ToString::to_string);
-                let struct_name = capture
+                let type_node = capture
                     .nodes_for_capture_index(self.annotated_impl_type_name_idx)
+                    .next()?;
+                let struct_name = impl_type_name(type_node, source);
+                let implemented_trait = capture
+                    .nodes_for_capture_index(self.annotated_impl_trait_idx)
                     .next()
-                    .map(|node| node.utf8_text(source.as_bytes()).map(ToString::to_string))?;
+                    .and_then(|node| node.utf8_text(source.as_bytes()).ok())
+                    .map(ToString::to_string);
 
                 let start = fn_node.start_position();
                 let end = fn_node.end_position();
@@ -394,6 +745,11 @@ impl AmQuery {
                         id: (current_module, format!("{s}::{f}")).into(),
                         instrumentation,
                         definition,
+                        options: attribute.options,
+                        implemented_trait,
+                        condition: attribute.condition,
+                        kind: Some(FunctionKind::Method),
+                        ..Default::default()
                     }),
                     (Err(e), _) => {
                         warn!("Could not extract the name of the struct: {e}");
@@ -423,45 +779,55 @@ pub(super) struct AllFunctionsQuery {
     impl_type_idx: u32,
     /// Index of a capture for the contents (the declarations) associated to any impl block in the file.
     impl_contents_idx: u32,
+    /// Index of a capture for the trait implemented by any impl block in the file.
+    impl_trait_idx: u32,
+    /// Skip the test functions and the items of test modules.
+    exclude_tests: bool,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AllFunctionsQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(user_queries: &UserQueries, syntax_errors: &SyntaxErrors) -> Result<Self> {
+        let loaded = user_queries.query(
             language(),
+            "rust/all_functions.scm",
             include_str!("../../runtime/queries/rust/all_functions.scm"),
+            &[],
         )?;
 
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.into()))?;
-        let mod_name_idx = query
-            .capture_index_for_name(MOD_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(MOD_NAME_CAPTURE.into()))?;
-        let mod_contents_idx = query
-            .capture_index_for_name(MOD_CONTENTS_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(MOD_NAME_CAPTURE.into()))?;
-        let impl_type_idx = query
-            .capture_index_for_name(IMPL_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPL_NAME_CAPTURE.into()))?;
-        let impl_contents_idx = query
-            .capture_index_for_name(IMPL_CONTENTS_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPL_CONTENTS_CAPTURE.into()))?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let mod_name_idx = loaded.capture_index(MOD_NAME_CAPTURE)?;
+        let mod_contents_idx = loaded.capture_index(MOD_CONTENTS_CAPTURE)?;
+        let impl_type_idx = loaded.capture_index(IMPL_NAME_CAPTURE)?;
+        let impl_contents_idx = loaded.capture_index(IMPL_CONTENTS_CAPTURE)?;
+        let impl_trait_idx = loaded.capture_index(IMPL_TRAIT_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
             func_name_idx,
             mod_name_idx,
             mod_contents_idx,
             impl_type_idx,
             impl_contents_idx,
+            impl_trait_idx,
+            exclude_tests: false,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
+    /// Skip the test functions, and the functions of the test modules.
+    pub fn with_tests_excluded(mut self, exclude_tests: bool) -> Self {
+        self.exclude_tests = exclude_tests;
+        self
+    }
+
     pub fn list_function_names(
         &self,
         file_name: &str,
@@ -470,6 +836,7 @@ impl AllFunctionsQuery {
     ) -> Result<Vec<FunctionInfo>> {
         let mut parser = new_parser()?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
         self.list_function_rec(file_name, module, None, parsed_source.root_node(), source)
     }
 
@@ -477,7 +844,7 @@ impl AllFunctionsQuery {
         &self,
         file_name: &str,
         current_module: String,
-        current_type: Option<String>,
+        current_impl: Option<ImplBlock>,
         node: Node,
         source: &str,
     ) -> Result<Vec<FunctionInfo>> {
@@ -490,7 +857,7 @@ impl AllFunctionsQuery {
             node,
             file_name,
             source,
-            current_type,
+            &current_impl,
             &current_module,
         );
         res.extend(direct_names);
@@ -539,7 +906,7 @@ impl AllFunctionsQuery {
                     );
                     let inner =
                         self.list_function_rec(file_name, new_module, None, contents_node, source)?;
-                    res.extend(inner.into_iter())
+                    res.extend(inner)
                 }
             }
 
@@ -554,17 +921,10 @@ impl AllFunctionsQuery {
                     continue;
                 }
 
-                let type_name = {
-                    match impl_type_node
-                        .utf8_text(source.as_bytes())
-                        .map(ToString::to_string)
-                    {
-                        Ok(val) => val,
-                        Err(e) => {
-                            warn!("Could not extract the type name of the impl block: {e}");
-                            continue;
-                        }
-                    }
+                let trait_node = capture.nodes_for_capture_index(self.impl_trait_idx).next();
+                let Some(impl_block) = ImplBlock::from_nodes(impl_type_node, trait_node, source)
+                else {
+                    continue;
                 };
 
                 if let Some(contents_node) = capture
@@ -574,7 +934,7 @@ impl AllFunctionsQuery {
                     trace!(
                         "Recursing into impl block {}::{}\n{}\n\n\n",
                         current_module,
-                        type_name,
+                        impl_block.type_name,
                         contents_node
                             .utf8_text(source.as_bytes())
                             .map(ToString::to_string)
@@ -583,11 +943,11 @@ impl AllFunctionsQuery {
                     let inner = self.list_function_rec(
                         file_name,
                         current_module.clone(),
-                        Some(type_name),
+                        Some(impl_block),
                         contents_node,
                         source,
                     )?;
-                    res.extend(inner.into_iter())
+                    res.extend(inner)
                 }
             }
         }
@@ -601,7 +961,7 @@ impl AllFunctionsQuery {
         node: Node,
         file_name: &str,
         source: &str,
-        current_type: Option<String>,
+        current_impl: &Option<ImplBlock>,
         current_module: &str,
     ) -> Vec<FunctionInfo> {
         cursor
@@ -614,6 +974,10 @@ impl AllFunctionsQuery {
                     return None;
                 }
 
+                if self.exclude_tests && is_test_code(fn_node, source) {
+                    return None;
+                }
+
                 // Ignore the matches that are within a impl_item, as the impl_block_names variable below catches those, applying the
                 // fully qualified module name.
                 if is_within_impl_item(fn_node, Some(node), source) {
@@ -624,10 +988,8 @@ impl AllFunctionsQuery {
                     .utf8_text(source.as_bytes())
                     .map(ToString::to_string);
 
-                let type_prefix: String = current_type
-                    .as_ref()
-                    .map(|t| format!("{t}::"))
-                    .unwrap_or_default();
+                let (kind, type_prefix, implemented_trait) =
+                    function_kind(fn_node, node, current_impl, source);
 
                 let start = fn_node.start_position();
                 let end = fn_node.end_position();
@@ -639,6 +1001,9 @@ impl AllFunctionsQuery {
                         id: (current_module, format!("{type_prefix}{f}")).into(),
                         instrumentation,
                         definition,
+                        implemented_trait,
+                        kind: Some(kind),
+                        ..Default::default()
                     }),
                     Err(e) => {
                         warn!("Could not get the method name: {e}");
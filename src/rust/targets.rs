@@ -0,0 +1,272 @@
+//! Cargo targets of a crate.
+//!
+//! A crate builds its library, binaries, examples, benchmarks, integration
+//! tests and build script from different files. The targets are read from the
+//! `[lib]`, `[[bin]]`, `[[example]]`, `[[bench]]` and `[[test]]` sections of
+//! `Cargo.toml`, and from the directories cargo uses to discover targets
+//! automatically (`src/bin`, `examples`, `benches` and `tests`).
+
+use std::{
+    fmt::Display,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::{AmlError, Result};
+
+/// The kind of a cargo target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    Bench,
+    Test,
+    BuildScript,
+}
+
+impl Display for TargetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TargetKind::Lib => "lib",
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Bench => "bench",
+            TargetKind::Test => "test",
+            TargetKind::BuildScript => "build",
+        })
+    }
+}
+
+impl FromStr for TargetKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lib" => Ok(TargetKind::Lib),
+            "bin" => Ok(TargetKind::Bin),
+            "example" => Ok(TargetKind::Example),
+            "bench" => Ok(TargetKind::Bench),
+            "test" => Ok(TargetKind::Test),
+            "build" | "build-script" => Ok(TargetKind::BuildScript),
+            _ => Err(format!("Unknown cargo target kind: {s}")),
+        }
+    }
+}
+
+/// A cargo target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub kind: TargetKind,
+    /// Name of the target, for the kinds that can have several targets.
+    pub name: Option<String>,
+}
+
+impl Display for Target {
+    /// Display the target as `kind` or `kind:name`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}:{name}", self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawManifest {
+    package: Option<RawPackage>,
+    lib: Option<RawTarget>,
+    #[serde(default)]
+    bin: Vec<RawTarget>,
+    #[serde(default)]
+    example: Vec<RawTarget>,
+    #[serde(default)]
+    bench: Vec<RawTarget>,
+    #[serde(default)]
+    test: Vec<RawTarget>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawPackage {
+    name: Option<String>,
+    /// Either the path of the build script, or `false` to disable it.
+    build: Option<toml::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawTarget {
+    name: Option<String>,
+    path: Option<PathBuf>,
+}
+
+/// The targets of the crate at the root of a project.
+#[derive(Clone, Debug, Default)]
+pub struct CargoTargets {
+    /// Root file of each target, relative to the project root.
+    targets: Vec<(PathBuf, Target)>,
+}
+
+impl CargoTargets {
+    /// Read the targets of the crate at `project_root`.
+    ///
+    /// A project without `Cargo.toml` has no targets.
+    pub fn discover(project_root: &Path) -> Result<Self> {
+        let manifest_path = project_root.join("Cargo.toml");
+        if !manifest_path.is_file() {
+            return Ok(Self::default());
+        }
+        let contents = read_to_string(&manifest_path)
+            .map_err(|err| AmlError::Io(manifest_path.clone(), err))?;
+        let manifest: RawManifest = match toml::from_str(&contents) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                warn!("Ignoring invalid {}: {err}", manifest_path.display());
+                return Ok(Self::default());
+            }
+        };
+        let package_name = manifest
+            .package
+            .as_ref()
+            .and_then(|package| package.name.clone());
+
+        let mut targets = Vec::new();
+        let mut push = |path: PathBuf, kind: TargetKind, name: Option<String>| {
+            if !targets
+                .iter()
+                .any(|(known, _): &(PathBuf, _)| known == &path)
+            {
+                targets.push((path, Target { kind, name }));
+            }
+        };
+
+        // Declared targets take precedence over the discovered ones.
+        let lib_path = match manifest.lib.as_ref().and_then(|lib| lib.path.clone()) {
+            Some(path) => Some(path),
+            None => (manifest.lib.is_some() || project_root.join("src/lib.rs").is_file())
+                .then(|| PathBuf::from("src/lib.rs")),
+        };
+        if let Some(lib_path) = lib_path {
+            push(lib_path, TargetKind::Lib, None);
+        }
+        for (kind, declared, default_dir) in [
+            (TargetKind::Bin, &manifest.bin, "src/bin"),
+            (TargetKind::Example, &manifest.example, "examples"),
+            (TargetKind::Bench, &manifest.bench, "benches"),
+            (TargetKind::Test, &manifest.test, "tests"),
+        ] {
+            for target in declared {
+                let path = target.path.clone().or_else(|| {
+                    let name = target.name.as_ref()?;
+                    Some(Path::new(default_dir).join(format!("{name}.rs")))
+                });
+                if let Some(path) = path {
+                    let name = target.name.clone().or_else(|| file_target_name(&path));
+                    push(path, kind, name);
+                }
+            }
+        }
+
+        if project_root.join("src/main.rs").is_file() {
+            push(PathBuf::from("src/main.rs"), TargetKind::Bin, package_name);
+        }
+        for (kind, dir) in [
+            (TargetKind::Bin, "src/bin"),
+            (TargetKind::Example, "examples"),
+            (TargetKind::Bench, "benches"),
+            (TargetKind::Test, "tests"),
+        ] {
+            for (path, name) in discovered_targets(project_root, Path::new(dir)) {
+                push(path, kind, Some(name));
+            }
+        }
+
+        let build_script = match manifest.package.and_then(|package| package.build) {
+            Some(toml::Value::String(path)) => Some(PathBuf::from(path)),
+            Some(_) => None,
+            None => Some(PathBuf::from("build.rs")),
+        };
+        if let Some(build_script) = build_script {
+            push(build_script, TargetKind::BuildScript, None);
+        }
+
+        Ok(Self { targets })
+    }
+
+    /// Return the target containing the file at `relative_path`, relative to
+    /// the project root.
+    ///
+    /// Files that are not the root of a target belong to the target with the
+    /// deepest root directory containing them, and the library wins over the
+    /// binaries in `src`. Files shared by several targets of the same kind
+    /// (like `tests/common/mod.rs`) only get the kind of the targets.
+    pub fn target_of(&self, relative_path: &Path) -> Option<Target> {
+        if let Some((_, target)) = self.targets.iter().find(|(path, _)| path == relative_path) {
+            return Some(target.clone());
+        }
+
+        let candidates: Vec<(usize, &Target)> = self
+            .targets
+            .iter()
+            .filter(|(_, target)| target.kind != TargetKind::BuildScript)
+            .filter_map(|(path, target)| {
+                let dir = path.parent()?;
+                relative_path
+                    .starts_with(dir)
+                    .then(|| (dir.components().count(), target))
+            })
+            .collect();
+        let depth = candidates.iter().map(|(depth, _)| *depth).max()?;
+        let deepest: Vec<&Target> = candidates
+            .into_iter()
+            .filter(|(candidate_depth, _)| *candidate_depth == depth)
+            .map(|(_, target)| target)
+            .collect();
+        let kind = deepest.iter().map(|target| target.kind).min()?;
+        let mut of_kind = deepest.into_iter().filter(|target| target.kind == kind);
+        let first = of_kind.next()?;
+        Some(match of_kind.next() {
+            Some(_) => Target { kind, name: None },
+            None => first.clone(),
+        })
+    }
+}
+
+/// Return the name of the target whose root file is at `path`: the name of the
+/// file, or the name of its directory for `main.rs` files.
+fn file_target_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    if stem == "main" {
+        let dir = path.parent()?.file_name()?.to_str()?;
+        return Some(dir.to_string());
+    }
+    Some(stem.to_string())
+}
+
+/// List the targets cargo discovers automatically in `dir`: the `*.rs` files,
+/// and the `*/main.rs` files of its subdirectories.
+fn discovered_targets(project_root: &Path, dir: &Path) -> Vec<(PathBuf, String)> {
+    let Ok(entries) = std::fs::read_dir(project_root.join(dir)) else {
+        return Vec::new();
+    };
+    let mut targets: Vec<(PathBuf, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let file_type = entry.file_type().ok()?;
+            if file_type.is_file() {
+                let stem = name.strip_suffix(".rs")?;
+                Some((dir.join(&name), stem.to_string()))
+            } else if file_type.is_dir() && entry.path().join("main.rs").is_file() {
+                Some((dir.join(&name).join("main.rs"), name))
+            } else {
+                None
+            }
+        })
+        .collect();
+    targets.sort();
+    targets
+}
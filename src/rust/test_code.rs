@@ -0,0 +1,184 @@
+//! Test code of a crate.
+//!
+//! Rust tests live next to the code they test: test functions are annotated
+//! with `#[test]` (or a runtime-specific variant like `#[tokio::test]`), and
+//! grouped in modules only compiled with `#[cfg(test)]`, either inline or in
+//! their own file (`#[cfg(test)] mod tests;`). Integration tests are the `test`
+//! targets of the crate.
+
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use tree_sitter::Node;
+use walkdir::DirEntry;
+
+use super::{
+    attributes::{split_attribute, split_top_level},
+    queries::new_parser,
+    targets::{CargoTargets, TargetKind},
+};
+use crate::Result;
+
+/// Names of the attributes that mark a test function, on top of the ones
+/// whose last path segment is `test`.
+const TEST_ATTRIBUTES: [&str; 3] = ["rstest", "test_case", "quickcheck"];
+
+/// The files of a crate that only contain test code.
+#[derive(Clone, Debug, Default)]
+pub struct TestFiles {
+    /// Files and directories of the modules declared under `#[cfg(test)]`,
+    /// relative to the project root.
+    modules: Vec<PathBuf>,
+    targets: CargoTargets,
+}
+
+impl TestFiles {
+    /// Find the test files among the `entries` walked from `project_root`.
+    pub fn discover(project_root: &Path, entries: &[DirEntry]) -> Result<Self> {
+        let modules = entries
+            .iter()
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let relative_path = entry.path().strip_prefix(project_root).ok()?;
+                let source = read_to_string(entry.path()).ok()?;
+                Some(declared_test_modules(relative_path, &source))
+            })
+            .flatten()
+            .collect();
+        Ok(Self {
+            modules,
+            targets: CargoTargets::discover(project_root)?,
+        })
+    }
+
+    /// Return true if the file at `relative_path`, relative to the project
+    /// root, only contains test code.
+    pub fn contains(&self, relative_path: &Path) -> bool {
+        self.modules
+            .iter()
+            .any(|module| relative_path.starts_with(module))
+            || self
+                .targets
+                .target_of(relative_path)
+                .map_or(false, |target| target.kind == TargetKind::Test)
+    }
+}
+
+/// Return true if the function named by `fn_node` is test code: a test
+/// function, a function nested in one, or an item of a `#[cfg(test)]` module
+/// or impl block.
+pub fn is_test_code(fn_node: Node, source: &str) -> bool {
+    let mut walk = fn_node.parent();
+    while let Some(item) = walk {
+        if preceding_attributes(item, source).any(|attribute| is_test_attribute(&attribute)) {
+            return true;
+        }
+        walk = item.parent();
+    }
+    false
+}
+
+/// List the files and directories of the modules declared without body under
+/// `#[cfg(test)]` in the file at `relative_path`.
+fn declared_test_modules(relative_path: &Path, source: &str) -> Vec<PathBuf> {
+    let Some(tree) = new_parser()
+        .ok()
+        .and_then(|mut parser| parser.parse(source, None))
+    else {
+        return Vec::new();
+    };
+
+    // The submodules of `src/lib.rs`, `src/main.rs` and `mod.rs` files are in
+    // the same directory, the other ones in a directory named after the file.
+    let parent = relative_path.parent().unwrap_or(Path::new(""));
+    let module_dir = match relative_path.file_stem().and_then(|stem| stem.to_str()) {
+        Some("mod" | "lib" | "main") | None => parent.to_path_buf(),
+        Some(stem) => parent.join(stem),
+    };
+
+    let mut modules = Vec::new();
+    collect_test_modules(tree.root_node(), &module_dir, false, source, &mut modules);
+    modules
+}
+
+fn collect_test_modules(
+    node: Node,
+    module_dir: &Path,
+    in_test: bool,
+    source: &str,
+    modules: &mut Vec<PathBuf>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() != "mod_item" {
+            continue;
+        }
+        let Some(name) = child
+            .child_by_field_name("name")
+            .and_then(|name| name.utf8_text(source.as_bytes()).ok())
+        else {
+            continue;
+        };
+        let is_test =
+            in_test || preceding_attributes(child, source).any(|attribute| is_cfg_test(&attribute));
+        match child.child_by_field_name("body") {
+            Some(body) => {
+                collect_test_modules(body, &module_dir.join(name), is_test, source, modules)
+            }
+            None if is_test => {
+                modules.push(module_dir.join(format!("{name}.rs")));
+                modules.push(module_dir.join(name));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Iterate over the contents of the attributes placed before an item.
+fn preceding_attributes<'a>(item: Node<'a>, source: &'a str) -> impl Iterator<Item = String> + 'a {
+    std::iter::successors(item.prev_named_sibling(), |node| node.prev_named_sibling())
+        .take_while(|node| {
+            matches!(
+                node.kind(),
+                "attribute_item" | "line_comment" | "block_comment"
+            )
+        })
+        .filter(|node| node.kind() == "attribute_item")
+        .filter_map(|node| node.named_child(0))
+        .filter_map(|attribute| attribute.utf8_text(source.as_bytes()).ok())
+        .map(ToString::to_string)
+}
+
+/// Return true if the contents of an attribute mark test code.
+fn is_test_attribute(attribute: &str) -> bool {
+    if is_cfg_test(attribute) {
+        return true;
+    }
+    let (path, _) = split_attribute(attribute);
+    let name = path.rsplit("::").next().unwrap_or_default();
+    name == "test" || TEST_ATTRIBUTES.contains(&name)
+}
+
+/// Return true if the contents of an attribute are a `cfg` that only holds
+/// when compiling the tests.
+fn is_cfg_test(attribute: &str) -> bool {
+    match split_attribute(attribute) {
+        (path, Some(predicate)) if path == "cfg" => requires_test(predicate),
+        _ => false,
+    }
+}
+
+fn requires_test(predicate: &str) -> bool {
+    let predicate = predicate.trim();
+    if predicate == "test" {
+        return true;
+    }
+    match split_attribute(predicate) {
+        (operator, Some(arguments)) if operator == "all" => {
+            split_top_level(arguments).into_iter().any(requires_test)
+        }
+        _ => false,
+    }
+}
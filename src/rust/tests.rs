@@ -4,9 +4,9 @@
 //! language to then merge the sets so that functions that get detected by both
 //! queries have their information merged.
 
-use crate::{Location, Position, Range};
+use crate::{test_utils::test_project, FunctionKind, Location, Position, Range};
 
-use super::*;
+use super::{queries::new_parser, *};
 use pretty_assertions::assert_eq;
 
 const FILE_NAME: &str = "source.rs";
@@ -19,7 +19,7 @@ fn detect_single() {
         fn main() {}
         "#;
 
-    let list = AmQuery::try_new()
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
@@ -45,6 +45,8 @@ fn detect_single() {
             id: (MODULE_NAME, "main").into(),
             instrumentation: Some(location.clone()),
             definition: Some(location),
+            kind: Some(FunctionKind::Function),
+            ..Default::default()
         }
     );
 }
@@ -60,7 +62,7 @@ fn detect_impl_block() {
         }
         "#;
 
-    let list = AmQuery::try_new()
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
@@ -86,6 +88,8 @@ fn detect_impl_block() {
             id: (MODULE_NAME, "Foo::method_a").into(),
             instrumentation: Some(location.clone()),
             definition: Some(location),
+            kind: Some(FunctionKind::Method),
+            ..Default::default()
         }
     );
 }
@@ -101,7 +105,7 @@ fn detect_trait_impl_block() {
         }
         "#;
 
-    let list = AmQuery::try_new()
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
@@ -127,6 +131,9 @@ fn detect_trait_impl_block() {
             id: (MODULE_NAME, "Foo::m_a").into(),
             instrumentation: Some(location.clone()),
             definition: Some(location),
+            implemented_trait: Some("A".to_string()),
+            kind: Some(FunctionKind::Method),
+            ..Default::default()
         }
     );
 }
@@ -154,11 +161,11 @@ fn dodge_wrong_impl_block() {
         }
         "#;
 
-    let list = AmQuery::try_new()
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
-    let all = AllFunctionsQuery::try_new()
+    let all = AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
@@ -223,31 +230,43 @@ fn dodge_wrong_impl_block() {
         id: (MODULE_NAME, "Bar::method_one").into(),
         instrumentation: None,
         definition: Some(method_one_location),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
     let method_two = FunctionInfo {
         id: (MODULE_NAME, "Foo::method_two").into(),
         instrumentation: None,
         definition: Some(method_two_location.clone()),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
     let method_two_instrumented = FunctionInfo {
         id: (MODULE_NAME, "Foo::method_two").into(),
         instrumentation: Some(method_two_location.clone()),
         definition: Some(method_two_location),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
     let method_three = FunctionInfo {
         id: (MODULE_NAME, "Bar::method_three").into(),
         instrumentation: None,
         definition: Some(method_three_location),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
     let method_four = FunctionInfo {
         id: (MODULE_NAME, "Foo::method_four").into(),
         instrumentation: None,
         definition: Some(method_four_location.clone()),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
     let method_four_instrumented = FunctionInfo {
         id: (MODULE_NAME, "Foo::method_four").into(),
         instrumentation: Some(method_four_location.clone()),
         definition: Some(method_four_location),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 2);
@@ -297,7 +316,7 @@ fn detect_inner_module() {
         }
         "#;
 
-    let list = AmQuery::try_new()
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
@@ -338,6 +357,8 @@ fn detect_inner_module() {
         id: (format!("{MODULE_NAME}::inner"), "inner_function").into(),
         instrumentation: Some(inner_fn_location.clone()),
         definition: Some(inner_fn_location.clone()),
+        kind: Some(FunctionKind::Function),
+        ..Default::default()
     };
     assert!(
         list.contains(&inner_fn),
@@ -351,6 +372,8 @@ fn detect_inner_module() {
             .into(),
         instrumentation: Some(nested_fn_location.clone()),
         definition: Some(nested_fn_location),
+        kind: Some(FunctionKind::Function),
+        ..Default::default()
     };
     assert!(
         list.contains(&nested_fn),
@@ -371,11 +394,11 @@ fn detect_partially_annotated_impl_block() {
         }
         "#;
 
-    let list = AmQuery::try_new()
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
-    let all = AllFunctionsQuery::try_new()
+    let all = AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
         .unwrap()
         .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
         .unwrap();
@@ -411,18 +434,27 @@ fn detect_partially_annotated_impl_block() {
         id: (MODULE_NAME, "Foo::m_a").into(),
         instrumentation: None,
         definition: Some(m_a_location.clone()),
+        implemented_trait: Some("A".to_string()),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
 
     let m_a_instrumented = FunctionInfo {
         id: (MODULE_NAME, "Foo::m_a").into(),
         instrumentation: Some(m_a_location.clone()),
         definition: Some(m_a_location),
+        implemented_trait: Some("A".to_string()),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
 
     let dummy = FunctionInfo {
         id: (MODULE_NAME, "Foo::nothing_to_see_here").into(),
         instrumentation: None,
         definition: Some(dummy_location),
+        implemented_trait: Some("A".to_string()),
+        kind: Some(FunctionKind::Method),
+        ..Default::default()
     };
 
     assert_eq!(list.len(), 1, "Complete list is {list:?}");
@@ -441,3 +473,540 @@ fn detect_partially_annotated_impl_block() {
         "Expecting the list to contain {dummy:?}\nComplete list is {all:?}"
     );
 }
+
+#[test]
+fn detect_generic_and_scoped_impl_blocks() {
+    let source = r#"
+        #[autometrics]
+        impl<T: Clone> Repo<T> {
+            fn get(&self) {}
+        }
+
+        impl crate::db::Pool {
+            #[autometrics]
+            fn acquire(&self) {}
+
+            fn release(&self) {}
+        }
+
+        #[autometrics]
+        impl<'a> tower::Service<Request> for &'a Handler {
+            fn call(&mut self, req: Request) {}
+        }
+        "#;
+
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
+        .unwrap()
+        .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+    let all = AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
+        .unwrap()
+        .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+
+    let names = |list: &[FunctionInfo]| {
+        let mut names: Vec<_> = list
+            .iter()
+            .map(|info| (info.id.function.clone(), info.implemented_trait.clone()))
+            .collect();
+        names.sort();
+        names
+    };
+    let service = Some("tower::Service<Request>".to_string());
+
+    assert_eq!(
+        names(&list),
+        vec![
+            ("Handler::call".to_string(), service.clone()),
+            ("Pool::acquire".to_string(), None),
+            ("Repo::get".to_string(), None),
+        ]
+    );
+    assert_eq!(
+        names(&all),
+        vec![
+            ("Handler::call".to_string(), service),
+            ("Pool::acquire".to_string(), None),
+            ("Pool::release".to_string(), None),
+            ("Repo::get".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn detect_attribute_forms() {
+    let source = r#"
+        use autometrics::autometrics as am;
+        use ::autometrics::{self as metrics, objective::Objective};
+
+        #[autometrics::autometrics]
+        fn qualified() {}
+
+        #[tracing::instrument]
+        #[cfg_attr(feature = "metrics", autometrics(objective = API_SLO))]
+        fn conditional() {}
+
+        #[am]
+        fn aliased() {}
+
+        #[cfg_attr(all(feature = "metrics", not(test)), metrics::autometrics)]
+        impl Repo {
+            fn get(&self) {}
+        }
+
+        #[cfg_attr(test, derive(Debug))]
+        #[instrument]
+        fn not_instrumented() {}
+
+        #[other::autometrics]
+        fn other_crate() {}
+        "#;
+
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
+        .unwrap()
+        .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+
+    let mut functions: Vec<_> = list
+        .into_iter()
+        .map(|info| (info.id.function, info.condition))
+        .collect();
+    functions.sort();
+    assert_eq!(
+        functions,
+        vec![
+            (
+                "Repo::get".to_string(),
+                Some(r#"all(feature = "metrics", not(test))"#.to_string())
+            ),
+            ("aliased".to_string(), None),
+            (
+                "conditional".to_string(),
+                Some(r#"feature = "metrics""#.to_string())
+            ),
+            ("qualified".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn detect_macro_arguments() {
+    let source = r#"
+        #[autometrics(objective = API_SLO, track_concurrency)]
+        fn create_user() {}
+
+        #[autometrics(
+            ok_if = Option::is_some,
+            struct_name = "UserRepository",
+        )]
+        impl Repo {
+            fn get(&self) {}
+        }
+
+        #[cfg_attr(feature = "metrics", autometrics(error_if = is_server_error))]
+        fn respond() {}
+
+        #[autometrics()]
+        fn no_arguments() {}
+        "#;
+
+    let list = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
+        .unwrap()
+        .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+
+    let mut functions: Vec<_> = list
+        .into_iter()
+        .map(|info| (info.id.function, info.options))
+        .collect();
+    functions.sort();
+    let options = |pairs: &[(&str, &str)]| {
+        Some(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    };
+    assert_eq!(
+        functions,
+        vec![
+            (
+                "Repo::get".to_string(),
+                options(&[
+                    ("ok_if", "Option::is_some"),
+                    ("struct_name", "UserRepository")
+                ])
+            ),
+            (
+                "create_user".to_string(),
+                options(&[("objective", "API_SLO"), ("track_concurrency", "true")])
+            ),
+            ("no_arguments".to_string(), None),
+            (
+                "respond".to_string(),
+                options(&[("error_if", "is_server_error")])
+            ),
+        ]
+    );
+}
+
+#[test]
+fn lint_functions_without_errors() {
+    let source = r#"
+        #[autometrics]
+        fn always_ok() -> u32 { 0 }
+
+        #[autometrics]
+        fn no_return_type() {}
+
+        #[autometrics]
+        async fn fallible() -> Result<(), Error> { Ok(()) }
+
+        #[autometrics]
+        fn aliased() -> anyhow::Result<()> { Ok(()) }
+
+        #[autometrics]
+        fn future() -> impl Future<Output = Result<(), Error>> { async { Ok(()) } }
+
+        #[autometrics(error_if = is_empty)]
+        fn classified() -> Vec<u32> { vec![] }
+
+        #[autometrics]
+        impl Repo {
+            fn count(&self) -> usize { 0 }
+        }
+
+        fn not_instrumented() -> u32 { 0 }
+        "#;
+
+    let (_, lints) = AmQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
+        .unwrap()
+        .list_functions_and_lints(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+
+    let mut functions: Vec<_> = lints
+        .into_iter()
+        .map(|lint| (lint.function.function, lint.location.range.start.line))
+        .collect();
+    functions.sort();
+    assert_eq!(
+        functions,
+        vec![
+            ("Repo::count".to_string(), 21),
+            ("always_ok".to_string(), 2),
+            ("no_return_type".to_string(), 5),
+        ]
+    );
+}
+
+#[test]
+fn detect_all_function_kinds() {
+    let source = r#"
+        fn free() {
+            fn nested_in_free() {}
+        }
+
+        trait Service {
+            fn required(&self);
+
+            fn provided(&self) {
+                fn nested_in_provided() {}
+            }
+
+            fn associated() -> Self where Self: Sized { todo!() }
+        }
+
+        impl Repo {
+            fn get(&self) {
+                fn nested_in_method() {}
+            }
+        }
+        "#;
+
+    let all = AllFunctionsQuery::try_new(&UserQueries::default(), &SyntaxErrors::default())
+        .unwrap()
+        .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+
+    let mut functions: Vec<_> = all
+        .into_iter()
+        .map(|info| (info.id.function, info.kind))
+        .collect();
+    functions.sort();
+    assert_eq!(
+        functions,
+        vec![
+            ("Repo::get".to_string(), Some(FunctionKind::Method)),
+            (
+                "Service::associated".to_string(),
+                Some(FunctionKind::TraitDefaultMethod)
+            ),
+            (
+                "Service::provided".to_string(),
+                Some(FunctionKind::TraitDefaultMethod)
+            ),
+            ("free".to_string(), Some(FunctionKind::Function)),
+            (
+                "nested_in_free".to_string(),
+                Some(FunctionKind::NestedFunction)
+            ),
+            (
+                "nested_in_method".to_string(),
+                Some(FunctionKind::NestedFunction)
+            ),
+            (
+                "nested_in_provided".to_string(),
+                Some(FunctionKind::NestedFunction)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn detect_syntax_errors() {
+    let source = r#"
+        #[autometrics]
+        fn main() {
+            let x = ;
+        }
+        "#;
+
+    let tree = new_parser().unwrap().parse(source, None).unwrap();
+    let errors = crate::syntax_errors(FILE_NAME, &tree);
+
+    assert_eq!(errors.len(), 1, "Complete list is {errors:?}");
+    assert_eq!(errors[0].file, FILE_NAME);
+    assert_eq!(errors[0].range.start.line, 3);
+
+    let valid_source = r#"
+        #[autometrics]
+        fn main() {}
+        "#;
+    let valid_tree = new_parser().unwrap().parse(valid_source, None).unwrap();
+    assert!(crate::syntax_errors(FILE_NAME, &valid_tree).is_empty());
+}
+
+#[test]
+fn detect_with_user_queries() {
+    let source = r#"
+        #[traced_endpoint]
+        fn handler() {}
+
+        fn not_instrumented() {}
+        "#;
+
+    let queries_dir = test_project(&[(
+        "rust/autometrics.extra.scm",
+        r#"
+((attribute_item
+   (attribute
+     (identifier) @attr))
+ .
+ (function_item
+   name: (identifier) @func.name)
+ (#eq? @attr "traced_endpoint"))
+"#,
+    )]);
+    let user_queries = UserQueries::load(queries_dir.path()).unwrap();
+
+    let list = AmQuery::try_new(&user_queries, &SyntaxErrors::default())
+        .unwrap()
+        .list_function_names(FILE_NAME, MODULE_NAME.to_string(), source)
+        .unwrap();
+
+    let location = Location {
+        file: FILE_NAME.to_string(),
+        range: Range {
+            start: Position {
+                line: 2,
+                column: 11,
+            },
+            end: Position {
+                line: 2,
+                column: 11 + "handler".len(),
+            },
+        },
+    };
+
+    assert_eq!(list.len(), 1, "Complete list is {list:?}");
+    assert_eq!(
+        list[0],
+        FunctionInfo {
+            id: (MODULE_NAME, "handler").into(),
+            instrumentation: Some(location.clone()),
+            definition: Some(location),
+            kind: Some(FunctionKind::Function),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn detect_cargo_targets() {
+    let dir = test_project(&[
+        (
+            "Cargo.toml",
+            r#"
+[package]
+name = "server"
+
+[[bin]]
+name = "migrate"
+path = "tools/migrate.rs"
+"#,
+        ),
+        ("src/lib.rs", "fn lib_root() {}"),
+        ("src/db/pool.rs", "fn acquire() {}"),
+        ("src/main.rs", "fn main() {}"),
+        ("src/bin/worker.rs", "fn worker_main() {}"),
+        ("tools/migrate.rs", "fn migrate_main() {}"),
+        ("examples/demo.rs", "fn demo() {}"),
+        ("benches/queries.rs", "fn bench_queries() {}"),
+        ("tests/api.rs", "fn api_test() {}"),
+        ("tests/smoke.rs", "fn smoke_test() {}"),
+        ("tests/common/mod.rs", "fn setup() {}"),
+        ("build.rs", "fn build_main() {}"),
+    ]);
+
+    let targets = |implementation: &mut Impl| {
+        let mut targets: Vec<_> = implementation
+            .list_all_function_definitions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| (info.id.function, info.target))
+            .collect();
+        targets.sort();
+        targets
+    };
+    let expected = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(function, target)| (function.to_string(), Some(target.to_string())))
+            .collect::<Vec<_>>()
+    };
+
+    let mut implementation = Impl::default();
+    assert_eq!(
+        targets(&mut implementation),
+        expected(&[
+            ("acquire", "lib"),
+            ("api_test", "test:api"),
+            ("bench_queries", "bench:queries"),
+            ("build_main", "build"),
+            ("demo", "example:demo"),
+            ("lib_root", "lib"),
+            ("main", "bin:server"),
+            ("migrate_main", "bin:migrate"),
+            ("setup", "test"),
+            ("smoke_test", "test:smoke"),
+            ("worker_main", "bin:worker"),
+        ])
+    );
+
+    implementation.targets = vec![TargetKind::Lib, TargetKind::Bin];
+    assert_eq!(
+        targets(&mut implementation),
+        expected(&[
+            ("acquire", "lib"),
+            ("lib_root", "lib"),
+            ("main", "bin:server"),
+            ("migrate_main", "bin:migrate"),
+            ("worker_main", "bin:worker"),
+        ]),
+        "only the production targets"
+    );
+}
+
+#[test]
+fn exclude_test_code() {
+    let dir = test_project(&[
+        ("Cargo.toml", "[package]\nname = \"server\"\n"),
+        (
+            "src/lib.rs",
+            r#"
+        mod db;
+
+        pub fn serve() {}
+
+        #[test]
+        fn serves() {}
+
+        #[tokio::test]
+        async fn serves_async() {
+            fn helper() {}
+        }
+
+        #[cfg(test)]
+        mod tests {
+            fn fixture() {}
+        }
+
+        #[cfg(all(test, feature = "slow"))]
+        // Only run on demand.
+        mod slow_tests {
+            fn slow_fixture() {}
+        }
+
+        #[cfg(not(test))]
+        mod runtime {
+            fn start() {}
+        }
+
+        #[cfg(test)]
+        impl Server {
+            fn mock() -> Self {}
+        }
+        "#,
+        ),
+        (
+            "src/db.rs",
+            r#"
+        pub fn connect() {}
+
+        #[cfg(test)]
+        mod tests;
+        "#,
+        ),
+        ("src/db/tests.rs", "fn connects() {}"),
+        ("src/db/tests/fixtures.rs", "fn database() {}"),
+        ("tests/api.rs", "fn api_test() {}"),
+    ]);
+
+    let functions = |implementation: &mut Impl| {
+        let mut functions: Vec<_> = implementation
+            .list_all_function_definitions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| info.id.function)
+            .collect();
+        functions.sort();
+        functions
+    };
+
+    let mut implementation = Impl::default();
+    assert_eq!(
+        functions(&mut implementation),
+        vec![
+            "Server::mock",
+            "api_test",
+            "connect",
+            "connects",
+            "database",
+            "fixture",
+            "helper",
+            "serve",
+            "serves",
+            "serves_async",
+            "slow_fixture",
+            "start",
+        ]
+    );
+
+    implementation.exclude_tests = true;
+    assert_eq!(
+        functions(&mut implementation),
+        vec!["connect", "serve", "start"],
+        "only the production code"
+    );
+}
@@ -0,0 +1,24 @@
+//! Helpers shared by the tests of all the languages.
+
+use std::fs::{create_dir_all, write};
+
+use tempfile::TempDir;
+
+/// Create a project in a temporary directory, with the given files as
+/// `(path, contents)` pairs, the paths being relative to the project root.
+///
+/// The default prefix of temporary directories would make them hidden, and
+/// skipped by all the implementations, so the project directory gets another
+/// prefix.
+pub fn test_project(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::Builder::new()
+        .prefix("project")
+        .tempdir()
+        .unwrap();
+    for (path, contents) in files {
+        let path = dir.path().join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, contents).unwrap();
+    }
+    dir
+}
@@ -1,20 +1,56 @@
+mod build;
+mod grammar;
 mod imports;
 mod queries;
+mod resolver;
+mod sfc;
+mod symbols;
+mod workspaces;
 
-use crate::{FunctionInfo, ListAmFunctions, Result};
+use crate::{
+    config::FileFilter, user_queries::UserQueries, AmlError, FunctionInfo, ListAmFunctions,
+    Location, Result, SyntaxErrors,
+};
 use rayon::prelude::*;
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::HashSet,
     fs::read_to_string,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 use walkdir::{DirEntry, WalkDir};
 
-use self::queries::{AllFunctionsQuery, AmQuery};
+use self::{
+    build::BuildOutput,
+    grammar::{Grammar, PerGrammar},
+    queries::{AllFunctionsQuery, AmQuery, ExportsQuery, ImportsMapQuery},
+    resolver::ModuleResolver,
+    symbols::SymbolTable,
+    workspaces::Workspaces,
+};
 
 /// Implementation of the Typescript support for listing autometricized functions.
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Impl {}
+#[derive(Clone, Debug, Default)]
+pub struct Impl {
+    /// Filter on the files to scan in the project.
+    pub filter: FileFilter,
+    /// User-supplied queries that override or extend the built-in ones.
+    pub queries: UserQueries,
+    /// Additional names of the autometrics wrapper function to look for, on top
+    /// of the `autometrics` import detected in each file.
+    pub wrappers: Vec<String>,
+    /// Report the path of the compiled files as modules, like autometrics does
+    /// at runtime, instead of the path of the sources.
+    pub compiled_modules: bool,
+    /// Build output directory containing the source maps of the compiled files,
+    /// relative to the project root. Setting it implies `compiled_modules`.
+    pub build_dir: Option<PathBuf>,
+    /// Skip the test files (`*.test.ts`, `*.spec.ts` and the `__tests__`
+    /// directories).
+    pub exclude_tests: bool,
+    /// Syntax errors found in the files parsed by the listings.
+    pub syntax_errors: SyntaxErrors,
+}
 
 impl Impl {
     fn is_hidden(entry: &DirEntry) -> bool {
@@ -35,38 +71,152 @@ impl Impl {
                 .extension()
                 .map(|ext| {
                     let ext = ext.to_str().unwrap_or("");
-                    ["js", "jsx", "ts", "tsx", "mjs"].contains(&ext)
+                    ["js", "jsx", "ts", "tsx", "mjs", "cjs", "mts", "cts"].contains(&ext)
+                        || sfc::SFC_EXTENSIONS.contains(&ext)
                 })
                 .unwrap_or(false)
     }
 
-    fn qualified_module_name(entry: &DirEntry) -> String {
-        let mut current_depth = entry.depth();
-        let mut mod_name_elements = VecDeque::with_capacity(8);
-        let mut path = entry.path();
+    /// Return true if the `entry` is a test file (`*.test.ts`, `*.spec.ts` and
+    /// their Javascript variants), or a `__tests__` directory.
+    fn is_test_file(entry: &DirEntry) -> bool {
+        let Some(name) = entry.file_name().to_str() else {
+            return false;
+        };
+        if entry.file_type().is_dir() {
+            return name == "__tests__";
+        }
+        let mut parts = name.rsplit('.');
+        let _extension = parts.next();
+        parts
+            .next()
+            .map_or(false, |part| part == "test" || part == "spec")
+    }
+
+    /// Return true if the walked `entry` must be scanned.
+    fn accepts(&self, project_root: &Path, entry: &DirEntry) -> bool {
+        Self::is_valid(entry)
+            && !(self.exclude_tests && Self::is_test_file(entry))
+            && self.filter.accepts(project_root, entry)
+    }
+
+    /// Read the source file at `path`, and return its contents with the
+    /// grammar to parse them with.
+    ///
+    /// Only the script blocks of single-file components are kept, at their
+    /// original position.
+    fn read_source(path: &Path) -> Option<(String, Grammar)> {
+        let source = read_to_string(path).ok()?;
+        if sfc::is_sfc(path) {
+            Some(sfc::extract_scripts(&source))
+        } else {
+            Some((source, Grammar::for_path(path)))
+        }
+    }
+
+    /// Compute the module name of the file at `relative_path`, relative to
+    /// the project root.
+    ///
+    /// In a workspace, the module name is computed from the root of the package
+    /// containing the file.
+    fn qualified_module_name(workspaces: &Workspaces, relative_path: &Path) -> String {
+        let unit_path = workspaces
+            .package_of(relative_path)
+            .and_then(|package| relative_path.strip_prefix(&package.dir).ok())
+            .unwrap_or(relative_path);
 
         // NOTE(magic)
-        // This "1" magic constant bears the assumption "am_list" is called
-        // from the root of a typescript repository.
-        while current_depth > 1 {
-            if path.is_dir() {
-                if let Some(component) = path.file_name() {
-                    mod_name_elements.push_front(component.to_string_lossy().to_string());
-                }
-            } else if path.is_file() {
-                if let Some(stem) = path.file_name().and_then(|os_str| os_str.to_str()) {
-                    mod_name_elements.push_front(stem.to_string());
-                }
-            }
+        // Skipping the first component bears the assumption "am_list" is called
+        // from the root of a typescript repository (or package), with the
+        // sources in a first-level directory like `src`.
+        let mod_name_elements = unit_path
+            .components()
+            .skip(1)
+            .map(|component| component.as_os_str().to_string_lossy().to_string());
+        itertools::intersperse(mod_name_elements, "/".to_string()).collect()
+    }
+
+    /// Compute the module name of a compiled file at `relative_path`, relative
+    /// to the project root.
+    ///
+    /// autometrics reports the path of the executed file relative to the
+    /// working directory of the process, which is the root of the package in a
+    /// workspace.
+    fn compiled_module_name(workspaces: &Workspaces, relative_path: &Path) -> String {
+        let unit_path = workspaces
+            .package_of(relative_path)
+            .and_then(|package| relative_path.strip_prefix(&package.dir).ok())
+            .unwrap_or(relative_path);
+        let mod_name_elements = unit_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string());
+        itertools::intersperse(mod_name_elements, "/".to_string()).collect()
+    }
+
+    /// List the source files of the project, with their module name.
+    fn source_modules(
+        &self,
+        project_root: &Path,
+        workspaces: &Workspaces,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let paths: Vec<PathBuf> = WalkDir::new(project_root)
+            .into_iter()
+            .filter_entry(|entry| self.accepts(project_root, entry))
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(DirEntry::into_path)
+            .collect();
+
+        let build_output = if self.compiled_modules || self.build_dir.is_some() {
+            let resolver = ModuleResolver::discover(project_root)?;
+            let build_dir = self.build_dir.as_ref().map(|dir| project_root.join(dir));
+            Some(BuildOutput::discover(
+                project_root,
+                build_dir.as_deref(),
+                &paths,
+                &resolver,
+            )?)
+        } else {
+            None
+        };
+        let absolute_root = build::absolute(project_root);
+
+        Ok(paths
+            .into_iter()
+            .filter_map(|path| {
+                let relative_path = path.strip_prefix(project_root).ok()?;
+                let compiled_path = build_output
+                    .as_ref()
+                    .and_then(|build_output| build_output.compiled_path(&path))
+                    .and_then(|compiled| compiled.strip_prefix(&absolute_root).ok());
+                let module = match compiled_path {
+                    Some(compiled_path) => Self::compiled_module_name(workspaces, compiled_path),
+                    None => Self::qualified_module_name(workspaces, relative_path),
+                };
+                Some((path, module))
+            })
+            .collect())
+    }
 
-            if path.parent().is_some() {
-                path = path.parent().unwrap();
-                current_depth -= 1;
-            } else {
-                break;
+    /// Report the workspace package of each function, from the file defining it
+    /// (or instrumenting it, when the definition is not known).
+    fn set_packages(workspaces: &Workspaces, functions: &mut [FunctionInfo]) {
+        for function in functions {
+            // Functions from outside of the project do not belong to a package.
+            if function.id.module.starts_with("ext://")
+                || function.id.module.starts_with("sibling://")
+            {
+                continue;
             }
+            let file = function
+                .definition
+                .as_ref()
+                .or(function.instrumentation.as_ref())
+                .map(|location| Path::new(&location.file));
+            function.id.package = file
+                .and_then(|file| workspaces.package_of(file))
+                .map(|package| package.name.clone());
         }
-        itertools::intersperse(mod_name_elements, "/".to_string()).collect()
     }
 }
 
@@ -75,35 +225,79 @@ impl ListAmFunctions for Impl {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
 
-        let walker = WalkDir::new(project_root).into_iter();
-        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            let module = Self::qualified_module_name(&entry);
-            Some((entry.path().to_path_buf(), module))
-        }));
+        let workspaces = Workspaces::discover(project_root)?;
+        let source_mod_pairs = self.source_modules(project_root, &workspaces)?;
 
-        list.par_extend(
-            source_mod_pairs
-                .par_iter()
-                .filter_map(move |(path, module)| {
-                    let query = AmQuery::try_new().ok()?;
-                    let source = read_to_string(path).ok()?;
-                    let file_name = PathBuf::from(path)
-                        .strip_prefix(project_root)
-                        .expect("path comes from a project_root WalkDir")
-                        .to_str()
-                        .expect("file_name is a valid path as it is part of `path`")
-                        .to_string();
-                    let names = query
-                        .list_function_names(&file_name, module, &source, Some(path))
-                        .ok()?;
-                    Some(names.into_iter().collect::<Vec<_>>())
-                }),
-        );
+        // First pass: collect the symbols of all the files, to resolve the
+        // wrapped functions to their definition.
+        let exports_queries =
+            PerGrammar::try_new(|grammar| ExportsQuery::try_new(grammar, &self.queries))?;
+        let imports_queries =
+            PerGrammar::try_new(|grammar| ImportsMapQuery::try_new(grammar, &self.queries))?;
+        let resolver = Arc::new(ModuleResolver::discover(project_root)?);
+        let sources: Vec<(PathBuf, String, String, String, Grammar)> = source_mod_pairs
+            .par_iter()
+            .filter_map(|(path, module)| {
+                let (source, grammar) = Self::read_source(path)?;
+                let file_name = path
+                    .strip_prefix(project_root)
+                    .expect("path comes from a project_root WalkDir")
+                    .to_str()
+                    .expect("file_name is a valid path as it is part of `path`")
+                    .to_string();
+                Some((path.clone(), file_name, module.clone(), source, grammar))
+            })
+            .collect();
+        let mut symbols = SymbolTable::new(project_root);
+        for (file_name, file_symbols) in sources
+            .par_iter()
+            .filter_map(|(path, file_name, module, source, grammar)| {
+                let grammar = *grammar;
+                let imports = imports_queries
+                    .get(grammar)
+                    .list_imports(path.parent(), source, &resolver)
+                    .ok()?;
+                let file_symbols = exports_queries
+                    .get(grammar)
+                    .list_symbols(
+                        file_name,
+                        module,
+                        source,
+                        path.parent(),
+                        &imports,
+                        &resolver,
+                    )
+                    .ok()?;
+                Some((file_name, file_symbols))
+            })
+            .collect::<Vec<_>>()
+        {
+            symbols.insert(file_name, file_symbols);
+        }
+
+        // Second pass: list the wrapped functions.
+        let symbols = Arc::new(symbols);
+        let queries = PerGrammar::try_new(|grammar| {
+            Ok::<_, AmlError>(
+                AmQuery::try_new(grammar, &self.queries, &self.syntax_errors)?
+                    .with_extra_wrappers(&self.wrappers)
+                    .with_symbols(symbols.clone())
+                    .with_resolver(resolver.clone()),
+            )
+        })?;
+        list.par_extend(sources.par_iter().filter_map(
+            move |(path, file_name, module, source, grammar)| {
+                let names = queries
+                    .get(*grammar)
+                    .list_function_names(file_name, module, source, path.parent())
+                    .ok()?;
+                Some(names.into_iter().collect::<Vec<_>>())
+            },
+        ));
 
         let mut result = Vec::with_capacity(PREALLOCATED_ELEMS);
         result.extend(list.into_iter().flatten());
+        Self::set_packages(&workspaces, &mut result);
         Ok(result)
     }
 
@@ -111,34 +305,25 @@ impl ListAmFunctions for Impl {
         const PREALLOCATED_ELEMS: usize = 100;
         let mut list = HashSet::with_capacity(PREALLOCATED_ELEMS);
 
-        let walker = WalkDir::new(project_root).into_iter();
-        let mut source_mod_pairs = Vec::with_capacity(PREALLOCATED_ELEMS);
-        source_mod_pairs.extend(walker.filter_entry(Self::is_valid).filter_map(|entry| {
-            let entry = entry.ok()?;
-            let module = Self::qualified_module_name(&entry);
-            Some((
-                entry
-                    .path()
-                    .to_str()
-                    .map(ToString::to_string)
-                    .unwrap_or_default(),
-                module,
-            ))
-        }));
+        let workspaces = Workspaces::discover(project_root)?;
+        let source_mod_pairs = self.source_modules(project_root, &workspaces)?;
 
+        let queries = PerGrammar::try_new(|grammar| {
+            AllFunctionsQuery::try_new(grammar, &self.queries, &self.syntax_errors)
+        })?;
         list.par_extend(
             source_mod_pairs
                 .par_iter()
                 .filter_map(move |(path, module)| {
-                    let source = read_to_string(path).ok()?;
-                    let query = AllFunctionsQuery::try_new().ok()?;
-                    let file_name = PathBuf::from(path)
+                    let (source, grammar) = Self::read_source(path)?;
+                    let file_name = path
                         .strip_prefix(project_root)
                         .expect("path comes from a project_root WalkDir")
                         .to_str()
                         .expect("file_name is a valid path as it is part of `path`")
                         .to_string();
-                    let names = query
+                    let names = queries
+                        .get(grammar)
                         .list_function_names(&file_name, module, &source)
                         .ok()?;
                     Some(names.into_iter().collect::<Vec<_>>())
@@ -147,8 +332,13 @@ impl ListAmFunctions for Impl {
 
         let mut result = Vec::with_capacity(PREALLOCATED_ELEMS);
         result.extend(list.into_iter().flatten());
+        Self::set_packages(&workspaces, &mut result);
         Ok(result)
     }
+
+    fn list_syntax_errors(&mut self) -> Vec<Location> {
+        self.syntax_errors.take()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,207 @@
+//! Compiled files of the project, to predict the module labels.
+//!
+//! autometrics-ts reports the path of the file that is actually executed as the
+//! module of a function, which is the compiled Javascript file instead of the
+//! Typescript source. The compiled file of each source is found through:
+//! - the source maps (`.map` files) of the build output directory, which also
+//!   cover bundles that merge several sources in a single file,
+//! - otherwise the `outDir` and `rootDir` options of the compiler
+//!   configuration of the source, except for single-file components.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use sourcemap::DecodedMap;
+use walkdir::WalkDir;
+
+use crate::{AmlError, Result};
+
+use super::{resolver::ModuleResolver, sfc::is_sfc, symbols::normalize};
+
+/// Suffixes of the source maps of declaration files.
+const DECLARATION_MAP_SUFFIXES: [&str; 3] = [".d.ts.map", ".d.mts.map", ".d.cts.map"];
+
+/// The compiled file of each source file of the project.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOutput {
+    /// Absolute paths of the compiled files, indexed by absolute source path.
+    compiled: HashMap<PathBuf, PathBuf>,
+}
+
+impl BuildOutput {
+    /// Find the compiled files of the `sources`, from the source maps of the
+    /// `build_dir` directory if given, and from the compiler configurations.
+    pub fn discover(
+        project_root: &Path,
+        build_dir: Option<&Path>,
+        sources: &[PathBuf],
+        resolver: &ModuleResolver,
+    ) -> Result<Self> {
+        let mut from_source_maps = HashMap::new();
+        if let Some(build_dir) = build_dir {
+            read_source_maps(project_root, build_dir, &mut from_source_maps)?;
+        }
+
+        // The default `rootDir` is the common directory of all the sources of a
+        // configuration. The compiler never emits single-file components, which
+        // are only compiled by bundlers: without a source map, their module
+        // name stays the one of the source.
+        let mut common_dirs: HashMap<PathBuf, PathBuf> = HashMap::new();
+        let emitted: Vec<_> = sources
+            .iter()
+            .filter(|source| !is_sfc(source))
+            .filter_map(|source| {
+                let options = resolver.emit_options(source.parent()?)?;
+                if options.root_dir.is_none() {
+                    let dir = absolute(source.parent()?);
+                    common_dirs
+                        .entry(options.config_dir.clone())
+                        .and_modify(|common| *common = common_ancestor(common, &dir))
+                        .or_insert(dir);
+                }
+                Some((source, options))
+            })
+            .filter(|(source, _)| !from_source_maps.contains_key(&absolute(source)))
+            .collect();
+
+        let mut compiled = from_source_maps;
+        for (source, options) in emitted {
+            let Some(root_dir) = options
+                .root_dir
+                .or_else(|| common_dirs.get(&options.config_dir).cloned())
+            else {
+                continue;
+            };
+            let source = absolute(source);
+            let Ok(relative) = source.strip_prefix(absolute(&root_dir)) else {
+                warn!(
+                    "{} is outside of the rootDir {}",
+                    source.display(),
+                    root_dir.display()
+                );
+                continue;
+            };
+            let target = absolute(&options.out_dir.join(relative));
+            compiled.insert(source, compiled_extension(&target));
+        }
+
+        Ok(Self { compiled })
+    }
+
+    /// Return the absolute path of the compiled file of the source file at
+    /// `source`.
+    pub fn compiled_path(&self, source: &Path) -> Option<&Path> {
+        self.compiled.get(&absolute(source)).map(PathBuf::as_path)
+    }
+}
+
+/// Read all the source maps in `build_dir`, and add the compiled file of each
+/// of their sources to `compiled`.
+fn read_source_maps(
+    project_root: &Path,
+    build_dir: &Path,
+    compiled: &mut HashMap<PathBuf, PathBuf>,
+) -> Result<()> {
+    let mut maps: Vec<PathBuf> = WalkDir::new(build_dir)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "node_modules")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "map"))
+        .filter(|path| !is_declaration_map(path))
+        .collect();
+    // Sort the maps so that a source present in several maps always gets the
+    // same compiled file.
+    maps.sort();
+
+    for map_path in maps {
+        let file = File::open(&map_path).map_err(|err| AmlError::Io(map_path.clone(), err))?;
+        let sources: Vec<String> = match sourcemap::decode(BufReader::new(file)) {
+            Ok(DecodedMap::Regular(map)) => map.sources().map(ToString::to_string).collect(),
+            Ok(DecodedMap::Hermes(map)) => map.sources().map(ToString::to_string).collect(),
+            Ok(DecodedMap::Index(index)) => match index.flatten() {
+                Ok(map) => map.sources().map(ToString::to_string).collect(),
+                Err(err) => {
+                    warn!("Ignoring invalid source map {}: {err}", map_path.display());
+                    continue;
+                }
+            },
+            Err(err) => {
+                warn!("Ignoring invalid source map {}: {err}", map_path.display());
+                continue;
+            }
+        };
+
+        let compiled_file = absolute(&map_path.with_extension(""));
+        let map_dir = map_path.parent().unwrap_or(Path::new(""));
+        for source in &sources {
+            if let Some(source) = source_path(project_root, map_dir, source) {
+                compiled
+                    .entry(absolute(&source))
+                    .or_insert_with(|| compiled_file.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Return true if the source map at `path` maps declaration files
+/// (`declarationMap` option of the compiler), which are never executed.
+fn is_declaration_map(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| {
+            DECLARATION_MAP_SUFFIXES
+                .iter()
+                .any(|suffix| name.ends_with(suffix))
+        })
+}
+
+/// Return the path of a source listed in a source map.
+///
+/// Bundlers use URLs like `webpack://app/./src/user.ts`, whose path is relative
+/// to the root of the project.
+fn source_path(project_root: &Path, map_dir: &Path, source: &str) -> Option<PathBuf> {
+    if let Some(path) = source.strip_prefix("file://") {
+        return Some(PathBuf::from(path));
+    }
+    match source.split_once("://") {
+        Some((_, url_path)) => {
+            let (_namespace, path) = url_path.split_once('/')?;
+            Some(project_root.join(path))
+        }
+        None => Some(map_dir.join(source)),
+    }
+}
+
+/// Return the compiled file path for a path in the output directory, which
+/// still has the extension of the source.
+fn compiled_extension(path: &Path) -> PathBuf {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("mts") => path.with_extension("mjs"),
+        Some("cts") => path.with_extension("cjs"),
+        Some("ts" | "tsx" | "jsx") => path.with_extension("js"),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Return the absolute, normalized version of `path`, so that the paths found
+/// in the source maps can be compared with the paths of the walked sources.
+pub(super) fn absolute(path: &Path) -> PathBuf {
+    let path = std::env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path));
+    normalize(&path)
+}
+
+/// Return the deepest directory containing both `a` and `b`.
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(component, _)| component)
+        .collect()
+}
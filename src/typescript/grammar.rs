@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use tree_sitter::Language;
+
+/// The tree-sitter grammars used to parse the Typescript and Javascript files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Grammar {
+    Typescript,
+    /// Typescript with JSX, also used for `.jsx` files.
+    Tsx,
+    Javascript,
+}
+
+impl Grammar {
+    /// Return the grammar to use for the file at `path`, based on its extension.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tsx" | "jsx") => Grammar::Tsx,
+            Some("js" | "mjs" | "cjs") => Grammar::Javascript,
+            _ => Grammar::Typescript,
+        }
+    }
+
+    pub fn language(self) -> Language {
+        match self {
+            Grammar::Typescript => tree_sitter_typescript::language_typescript(),
+            Grammar::Tsx => tree_sitter_typescript::language_tsx(),
+            Grammar::Javascript => tree_sitter_javascript::language(),
+        }
+    }
+
+    /// Name of the directory containing the queries for this grammar, in the
+    /// built-in and the user-supplied queries.
+    pub fn queries_dir(self) -> &'static str {
+        match self {
+            Grammar::Typescript | Grammar::Tsx => "typescript",
+            Grammar::Javascript => "javascript",
+        }
+    }
+}
+
+/// A value (usually a compiled query) for each of the grammars.
+#[derive(Debug)]
+pub struct PerGrammar<T> {
+    typescript: T,
+    tsx: T,
+    javascript: T,
+}
+
+impl<T> PerGrammar<T> {
+    pub fn try_new<E>(mut f: impl FnMut(Grammar) -> Result<T, E>) -> Result<Self, E> {
+        Ok(Self {
+            typescript: f(Grammar::Typescript)?,
+            tsx: f(Grammar::Tsx)?,
+            javascript: f(Grammar::Javascript)?,
+        })
+    }
+
+    pub fn get(&self, grammar: Grammar) -> &T {
+        match grammar {
+            Grammar::Typescript => &self.typescript,
+            Grammar::Tsx => &self.tsx,
+            Grammar::Javascript => &self.javascript,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    fmt::Display,
     path::{Path, PathBuf},
 };
 
@@ -13,9 +14,9 @@ impl<T: Into<String>> From<T> for Source {
     }
 }
 
-impl ToString for Source {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
@@ -24,14 +25,15 @@ impl Source {
         if import_statement_location.map_or(true, |path| path.to_string_lossy().is_empty()) {
             // This base case is reached when we called `import_statement_location.parent()` too
             // many times, which means the import is a sibling of the import_statement_location given in the beginning.
-            return CanonicalSource::from(format!("sibling://{}", self.0));
+            return CanonicalSource::from(format!("sibling://{}", self.0.trim_start_matches("./")));
         }
 
         let import_location = import_statement_location.unwrap();
 
         let relative_path = PathBuf::from(self.0);
         if let Ok(sibling) = relative_path.strip_prefix("..") {
-            return Source::from(sibling.to_string_lossy())
+            // The rest of the path is relative to the parent directory.
+            return Source::from(Path::new(".").join(sibling).to_string_lossy())
                 .into_canonical(import_location.parent());
         }
 
@@ -61,9 +63,20 @@ impl<T: Into<String>> From<T> for CanonicalSource {
     }
 }
 
-impl ToString for CanonicalSource {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl CanonicalSource {
+    /// Return the path of the import, if it comes from the current project.
+    pub fn as_path(&self) -> Option<&Path> {
+        if self.0.starts_with("ext://") || self.0.starts_with("sibling://") {
+            None
+        } else {
+            Some(Path::new(&self.0))
+        }
+    }
+}
+
+impl Display for CanonicalSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
@@ -77,9 +90,9 @@ impl<T: Into<String>> From<T> for Identifier {
     }
 }
 
-impl ToString for Identifier {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl Display for Identifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
@@ -138,4 +151,13 @@ impl ImportsMap {
             self.find_identifier(&ident)
         }
     }
+
+    /// Return the name in its source and the source of a member of a named
+    /// import, like `ns.fn` after `import { ns } from './barrel'`.
+    pub fn resolve_member(&self, ident: &Identifier) -> Option<(Identifier, CanonicalSource)> {
+        let ident_str = ident.to_string();
+        let (head, member) = ident_str.split_once('.')?;
+        self.find_identifier(&Identifier::from(head))
+            .map(|(name, source)| (Identifier::from(format!("{name}.{member}")), source))
+    }
 }
@@ -1,63 +1,146 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+    sync::{Arc, RwLock},
+};
 
 use log::warn;
-use tree_sitter::{Parser, Query};
-use tree_sitter_typescript::language_typescript as language;
+use tree_sitter::{Node, Parser, Query};
 
-use crate::{AmlError, FunctionInfo, Location, Result, FUNC_NAME_CAPTURE};
+use crate::{
+    user_queries::{LoadedQuery, UserQueries},
+    AmlError, FunctionId, FunctionInfo, Location, Result, SyntaxErrors, FUNC_NAME_CAPTURE,
+};
 
-use super::imports::{Identifier, ImportsMap, Source};
+use super::{
+    grammar::Grammar,
+    imports::{Identifier, ImportsMap, Source},
+    resolver::ModuleResolver,
+    symbols::{Export, FileSymbols, SymbolTable, DEFAULT_EXPORT},
+};
 
 const TYPE_NAME_CAPTURE: &str = "type.name";
 const METHOD_NAME_CAPTURE: &str = "method.name";
 const WRAPPER_DIRECT_NAME_CAPTURE: &str = "wrapperdirect.name";
 const WRAPPER_NAME_CAPTURE: &str = "wrapper.name";
-const WRAPPER_ARGS_MODULE_CAPTURE: &str = "module.name";
+const AUTOMETRICS_NAMESPACE_CAPTURE: &str = "autometrics.namespace";
+const METHOD_DECORATOR_CAPTURE: &str = "method.decorator";
+const DECORATOR_OPTIONS_CAPTURE: &str = "decorator.options";
+const WRAPPER_OPTIONS_CAPTURE: &str = "wrapper.options";
 
 const IMPORTS_IDENT_NAME_CAPTURE: &str = "inst.ident";
 const IMPORTS_REAL_NAME_CAPTURE: &str = "inst.realname";
 const IMPORTS_SOURCE_CAPTURE: &str = "inst.source";
 const IMPORTS_PREFIX_CAPTURE: &str = "inst.prefix";
 
-fn new_parser() -> Result<Parser> {
+const DEFINITION_NAME_CAPTURE: &str = "definition.name";
+const EXPORT_LOCAL_CAPTURE: &str = "export.local";
+const EXPORT_NAME_CAPTURE: &str = "export.name";
+const EXPORT_DEFAULT_CAPTURE: &str = "export.default";
+const REEXPORT_SOURCE_CAPTURE: &str = "reexport.source";
+const REEXPORT_ALL_CAPTURE: &str = "reexport.all";
+const REEXPORT_NAMESPACE_CAPTURE: &str = "reexport.namespace";
+
+fn new_parser(grammar: Grammar) -> Result<Parser> {
     let mut parser = Parser::new();
-    parser.set_language(language())?;
+    parser.set_language(grammar.language())?;
     Ok(parser)
 }
 
+/// Load the query called `name` for the given grammar.
+///
+/// The Javascript grammar lacks the type annotations nodes, so some built-in
+/// queries have a `javascript` variant; the other ones are shared by all the
+/// grammars.
+fn load_query(
+    grammar: Grammar,
+    user_queries: &UserQueries,
+    name: &str,
+    typescript_builtin: &str,
+    javascript_builtin: &str,
+    template_args: &[&str],
+) -> Result<LoadedQuery> {
+    let builtin = match grammar {
+        Grammar::Typescript | Grammar::Tsx => typescript_builtin,
+        Grammar::Javascript => javascript_builtin,
+    };
+    user_queries.query(
+        grammar.language(),
+        &format!("{}/{name}", grammar.queries_dir()),
+        builtin,
+        template_args,
+    )
+}
+
+/// Return the properties of an object literal given as options, by name, with
+/// the source text of their value.
+fn object_options(object: Node, source: &str) -> BTreeMap<String, String> {
+    let text = |node: Node| {
+        node.utf8_text(source.as_bytes())
+            .ok()
+            .map(ToString::to_string)
+    };
+    let mut cursor = object.walk();
+    let options = object
+        .named_children(&mut cursor)
+        .filter_map(|property| match property.kind() {
+            "pair" => {
+                let key = property.child_by_field_name("key")?;
+                // Quoted keys are stored without their quotes.
+                let key = match key.named_child(0) {
+                    Some(fragment) if key.kind() == "string" => text(fragment)?,
+                    _ => text(key)?,
+                };
+                Some((key, text(property.child_by_field_name("value")?)?))
+            }
+            // `{ objective }` passes a variable with the same name.
+            "shorthand_property_identifier" => Some((text(property)?, text(property)?)),
+            _ => None,
+        })
+        .collect();
+    options
+}
+
 /// Query wrapper for "all functions in source"
 #[derive(Debug)]
 pub(super) struct AllFunctionsQuery {
     query: Query,
+    grammar: Grammar,
     /// Index of the capture for a function name.
     func_name_idx: u32,
     /// Index of the capture for the name of a class that is defined in file.
     type_name_idx: u32,
     /// Index of the capture for the contents of a method that is defined in file.
     method_name_idx: u32,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AllFunctionsQuery {
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
-            language(),
+    pub fn try_new(
+        grammar: Grammar,
+        user_queries: &UserQueries,
+        syntax_errors: &SyntaxErrors,
+    ) -> Result<Self> {
+        let loaded = load_query(
+            grammar,
+            user_queries,
+            "all_functions.scm",
             include_str!("../../runtime/queries/typescript/all_functions.scm"),
+            include_str!("../../runtime/queries/javascript/all_functions.scm"),
+            &[],
         )?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
-        let type_name_idx = query
-            .capture_index_for_name(TYPE_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(TYPE_NAME_CAPTURE.to_string()))?;
-        let method_name_idx = query
-            .capture_index_for_name(METHOD_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(METHOD_NAME_CAPTURE.to_string()))?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let type_name_idx = loaded.capture_index(TYPE_NAME_CAPTURE)?;
+        let method_name_idx = loaded.capture_index(METHOD_NAME_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
+            grammar,
             func_name_idx,
             type_name_idx,
             method_name_idx,
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
@@ -67,8 +150,9 @@ impl AllFunctionsQuery {
         module_name: &str,
         source: &str,
     ) -> Result<Vec<FunctionInfo>> {
-        let mut parser = new_parser()?;
+        let mut parser = new_parser(self.grammar)?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
         let mut cursor = tree_sitter::QueryCursor::new();
         let functions = cursor
             .matches(&self.query, parsed_source.root_node(), source.as_bytes())
@@ -100,6 +184,7 @@ impl AllFunctionsQuery {
                             id: (module_name, bare_function_name).into(),
                             instrumentation,
                             definition,
+                            ..Default::default()
                         })
                     }
                     (_, Some(Ok(method_name)), Some(Ok(class_name))) => {
@@ -116,6 +201,7 @@ impl AllFunctionsQuery {
                             id: (module_name, qual_fn_name).into(),
                             instrumentation,
                             definition,
+                            ..Default::default()
                         })
                     }
                     (_, None, Some(_)) => {
@@ -151,6 +237,7 @@ impl AllFunctionsQuery {
 #[derive(Debug)]
 pub(super) struct AmQuery {
     query: Query,
+    grammar: Grammar,
     /// Index of the capture for a class name defined in the file.
     type_name_idx: u32,
     /// Index of the capture for a method name defined in the file.
@@ -161,42 +248,131 @@ pub(super) struct AmQuery {
     /// Index of the capture for the name of the autometrics wrapper that takes
     /// 2 arguments.
     wrapper_name_idx: u32,
+    /// Index of the capture for the name of the imported autometrics module,
+    /// whose wrappers are called as members (`am.autometrics(fn)`).
+    namespace_idx: u32,
+    /// Index of the capture for a decorator of a single method, as opposed to
+    /// a class decorator.
+    method_decorator_idx: u32,
+    /// Index of the capture for the options object given to a decorator.
+    decorator_options_idx: u32,
+    /// Additional names of the autometrics wrapper to look for, even if they
+    /// are not imported from autometrics in the file.
+    extra_wrapper_names: Vec<String>,
+    /// User-supplied queries, used to create the subqueries.
+    user_queries: UserQueries,
+    /// Subqueries for the calls to each wrapper name, compiled on first use.
+    wrapper_subqueries: RwLock<HashMap<String, Arc<WrapperSubqueries>>>,
+    /// Query for the imports of the source.
+    imports_query: ImportsMapQuery,
+    /// Symbols of the project, used to find the definition of wrapped functions.
+    symbols: Arc<SymbolTable>,
+    /// Resolver for the non-relative imports of the project.
+    resolver: Arc<ModuleResolver>,
+    /// Collector of the syntax errors of the parsed files.
+    syntax_errors: SyntaxErrors,
 }
 
 impl AmQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
-            language(),
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    ///
+    /// The syntax errors of the parsed files are recorded in `syntax_errors`.
+    pub fn try_new(
+        grammar: Grammar,
+        user_queries: &UserQueries,
+        syntax_errors: &SyntaxErrors,
+    ) -> Result<Self> {
+        let imports_query = ImportsMapQuery::try_new(grammar, user_queries)?;
+        // Build the templated subqueries of the default wrapper name right away
+        // to report invalid user queries early
+        let default_subqueries = WrapperSubqueries::try_new("autometrics", grammar, user_queries)?;
+
+        let loaded = load_query(
+            grammar,
+            user_queries,
+            "autometrics.scm",
             include_str!("../../runtime/queries/typescript/autometrics.scm"),
+            include_str!("../../runtime/queries/javascript/autometrics.scm"),
+            &[],
         )?;
-        let type_name_idx = query
-            .capture_index_for_name(TYPE_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(TYPE_NAME_CAPTURE.to_string()))?;
-        let method_name_idx = query
-            .capture_index_for_name(METHOD_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(METHOD_NAME_CAPTURE.to_string()))?;
-        let wrapper_direct_name_idx = query
-            .capture_index_for_name(WRAPPER_DIRECT_NAME_CAPTURE)
-            .ok_or_else(|| {
-                AmlError::MissingNamedCapture(WRAPPER_DIRECT_NAME_CAPTURE.to_string())
-            })?;
-        let wrapper_name_idx = query
-            .capture_index_for_name(WRAPPER_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(WRAPPER_NAME_CAPTURE.to_string()))?;
+        let type_name_idx = loaded.capture_index(TYPE_NAME_CAPTURE)?;
+        let method_name_idx = loaded.capture_index(METHOD_NAME_CAPTURE)?;
+        let wrapper_direct_name_idx = loaded.capture_index(WRAPPER_DIRECT_NAME_CAPTURE)?;
+        let wrapper_name_idx = loaded.capture_index(WRAPPER_NAME_CAPTURE)?;
+        let namespace_idx = loaded.capture_index(AUTOMETRICS_NAMESPACE_CAPTURE)?;
+        let method_decorator_idx = loaded.capture_index(METHOD_DECORATOR_CAPTURE)?;
+        let decorator_options_idx = loaded.capture_index(DECORATOR_OPTIONS_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
+            grammar,
             type_name_idx,
             method_name_idx,
             wrapper_direct_name_idx,
             wrapper_name_idx,
+            namespace_idx,
+            method_decorator_idx,
+            decorator_options_idx,
+            extra_wrapper_names: Vec::new(),
+            user_queries: user_queries.clone(),
+            wrapper_subqueries: RwLock::new(HashMap::from([(
+                "autometrics".to_string(),
+                Arc::new(default_subqueries),
+            )])),
+            imports_query,
+            symbols: Arc::default(),
+            resolver: Arc::default(),
+            syntax_errors: syntax_errors.clone(),
         })
     }
 
+    /// Also look for calls to the given wrapper names in all the sources.
+    pub fn with_extra_wrappers(mut self, wrapper_names: &[String]) -> Self {
+        self.extra_wrapper_names.extend_from_slice(wrapper_names);
+        self
+    }
+
+    /// Resolve the wrapped functions to their definition using the given
+    /// project symbols.
+    pub fn with_symbols(mut self, symbols: Arc<SymbolTable>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Resolve the non-relative imports with the given resolver.
+    pub fn with_resolver(mut self, resolver: Arc<ModuleResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Return the subqueries for the calls to `wrapper_name`, compiling them
+    /// the first time the name is looked for.
+    fn wrapper_subqueries(&self, wrapper_name: &str) -> Result<Arc<WrapperSubqueries>> {
+        if let Some(subqueries) = self
+            .wrapper_subqueries
+            .read()
+            .expect("no thread panics while holding the lock")
+            .get(wrapper_name)
+        {
+            return Ok(subqueries.clone());
+        }
+        let subqueries = Arc::new(WrapperSubqueries::try_new(
+            wrapper_name,
+            self.grammar,
+            &self.user_queries,
+        )?);
+        Ok(self
+            .wrapper_subqueries
+            .write()
+            .expect("no thread panics while holding the lock")
+            .entry(wrapper_name.to_string())
+            .or_insert(subqueries)
+            .clone())
+    }
+
     pub fn list_function_names(
         &self,
         file_name: &str,
@@ -204,60 +380,76 @@ impl AmQuery {
         source: &str,
         path: Option<&Path>,
     ) -> Result<Vec<FunctionInfo>> {
-        let mut parser = new_parser()?;
+        let mut parser = new_parser(self.grammar)?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        self.syntax_errors.record(file_name, &parsed_source);
 
-        let imports_query = ImportsMapQuery::try_new()?;
-        let imports_map = imports_query.list_imports(path, source)?;
+        let imports_map = self
+            .imports_query
+            .list_imports(path, source, &self.resolver)?;
 
         let mut cursor = tree_sitter::QueryCursor::new();
-        let wrapper_direct_name = cursor
-            .matches(&self.query, parsed_source.root_node(), source.as_bytes())
-            .filter_map(|capture| {
-                capture
-                    .nodes_for_capture_index(self.wrapper_direct_name_idx)
-                    .next()
-            })
-            .map(|node| {
-                node.utf8_text(source.as_bytes())
-                    .map(ToString::to_string)
-                    .map_err(|_| AmlError::InvalidText)
-            })
-            .next()
-            .transpose()?;
-        let mut wrapped_fns_list = if wrapper_direct_name.is_none() {
-            Vec::new()
-        } else {
-            let subquery = AmWrapperDirectSubquery::try_new(wrapper_direct_name.unwrap())?;
-            subquery.list_function_names(file_name, module_name, source, imports_map)?
+        let mut all_captures = |idx: u32| {
+            cursor
+                .matches(&self.query, parsed_source.root_node(), source.as_bytes())
+                .filter_map(|capture| capture.nodes_for_capture_index(idx).next())
+                .map(|node| {
+                    node.utf8_text(source.as_bytes())
+                        .map(ToString::to_string)
+                        .map_err(|_| AmlError::InvalidText)
+                })
+                .collect::<Result<Vec<_>>>()
         };
+        // A file can import the wrappers several times, under different names,
+        // and call them with or without options: every wrapper name is looked
+        // for with both call forms.
+        let mut wrapper_names: BTreeSet<String> = all_captures(self.wrapper_direct_name_idx)?
+            .into_iter()
+            .chain(all_captures(self.wrapper_name_idx)?)
+            .collect();
+        // When the whole module is imported, the wrappers are members of the
+        // module object.
+        wrapper_names.extend(
+            all_captures(self.namespace_idx)?
+                .into_iter()
+                .map(|namespace| format!("{namespace}.autometrics")),
+        );
+        wrapper_names.extend(self.extra_wrapper_names.iter().cloned());
 
-        let wrapper_name = cursor
-            .matches(&self.query, parsed_source.root_node(), source.as_bytes())
-            .filter_map(|capture| {
-                capture
-                    .nodes_for_capture_index(self.wrapper_name_idx)
-                    .next()
-            })
-            .map(|node| {
-                node.utf8_text(source.as_bytes())
-                    .map(ToString::to_string)
-                    .map_err(|_| AmlError::InvalidText)
-            })
-            .next()
-            .transpose()?;
-        if let Some(wrapper_name) = wrapper_name {
-            let subquery = AmWrapperSubquery::try_new(wrapper_name)?;
-            wrapped_fns_list.extend(subquery.list_function_names(file_name, source)?)
+        let mut wrapped_fns_list = Vec::new();
+        for wrapper_name in wrapper_names {
+            let subqueries = self.wrapper_subqueries(&wrapper_name)?;
+            wrapped_fns_list.extend(subqueries.direct.list_function_names(
+                file_name,
+                module_name,
+                source,
+                &imports_map,
+                &self.symbols,
+            )?);
+            wrapped_fns_list.extend(subqueries.with_options.list_function_names(
+                file_name,
+                module_name,
+                source,
+                &imports_map,
+                &self.symbols,
+            )?);
         }
 
-        cursor = tree_sitter::QueryCursor::new();
-        let mut method_list: Vec<FunctionInfo> = cursor
+        let mut cursor = tree_sitter::QueryCursor::new();
+        let mut decorated_methods: Vec<(bool, FunctionInfo)> = cursor
             .matches(&self.query, parsed_source.root_node(), source.as_bytes())
-            .filter_map(|capture| -> Option<FunctionInfo> {
+            .filter_map(|capture| -> Option<(bool, FunctionInfo)> {
                 // Bare functions are handled by the subquery list_function_names method
                 let method_name_node = capture.nodes_for_capture_index(self.method_name_idx).next();
                 let type_name_node = capture.nodes_for_capture_index(self.type_name_idx).next();
+                let is_method_decorator = capture
+                    .nodes_for_capture_index(self.method_decorator_idx)
+                    .next()
+                    .is_some();
+                let options = capture
+                    .nodes_for_capture_index(self.decorator_options_idx)
+                    .next()
+                    .map(|node| object_options(node, source));
                 match (
                     // Test for Method name capture
                     method_name_node
@@ -276,11 +468,16 @@ impl AmQuery {
                             .end_position();
                         let instrumentation = Some(Location::from((file_name, start, end)));
                         let definition = Some(Location::from((file_name, start, end)));
-                        Some(FunctionInfo {
-                            id: (module_name, qual_fn_name).into(),
-                            instrumentation,
-                            definition,
-                        })
+                        Some((
+                            is_method_decorator,
+                            FunctionInfo {
+                                id: (module_name, qual_fn_name).into(),
+                                instrumentation,
+                                definition,
+                                options,
+                                ..Default::default()
+                            },
+                        ))
                     }
                     (None, Some(_)) => {
                         warn!("Found a class without a method in the capture");
@@ -303,96 +500,150 @@ impl AmQuery {
             })
             .collect();
 
+        // A method can be decorated in a decorated class: the options of the
+        // method decorator override the ones of the class decorator.
+        decorated_methods.sort_by_key(|(is_method_decorator, _)| *is_method_decorator);
+        let mut methods: HashMap<FunctionId, FunctionInfo> = HashMap::new();
+        for (_, info) in decorated_methods {
+            match methods.get_mut(&info.id) {
+                Some(existing) => {
+                    if let Some(options) = info.options {
+                        existing
+                            .options
+                            .get_or_insert_with(BTreeMap::new)
+                            .extend(options);
+                    }
+                    existing.instrumentation = info.instrumentation;
+                }
+                None => {
+                    methods.insert(info.id.clone(), info);
+                }
+            }
+        }
+        let mut method_list: Vec<FunctionInfo> = methods.into_values().collect();
+
         // Concatenate list of methods and list of wrapped functions
         method_list.append(&mut wrapped_fns_list);
         Ok(method_list)
     }
 }
 
+/// The subqueries for the calls to an autometrics wrapper, with or without
+/// options.
+#[derive(Debug)]
+struct WrapperSubqueries {
+    direct: AmWrapperDirectSubquery,
+    with_options: AmWrapperSubquery,
+}
+
+impl WrapperSubqueries {
+    fn try_new(wrapper_name: &str, grammar: Grammar, user_queries: &UserQueries) -> Result<Self> {
+        Ok(Self {
+            direct: AmWrapperDirectSubquery::try_new(
+                wrapper_name.to_string(),
+                grammar,
+                user_queries,
+            )?,
+            with_options: AmWrapperSubquery::try_new(
+                wrapper_name.to_string(),
+                grammar,
+                user_queries,
+            )?,
+        })
+    }
+}
+
 /// Query wrapper for "all function arguments to the given wrapper_name in source"
 #[derive(Debug)]
 struct AmWrapperSubquery {
     query: Query,
+    grammar: Grammar,
     /// Name of the wrapper function to look for
     // Having the wrapper_name is useful when debugging the queries
     #[allow(dead_code)]
     wrapper_name: String,
-    /// Index of the capture for a function name.
+    /// Index of the capture for the wrapped function.
     func_name_idx: u32,
-    /// Index of the capture for a module name.
-    module_name_idx: u32,
+    /// Index of the capture for the options object given to the wrapper.
+    options_idx: u32,
 }
 
 impl AmWrapperSubquery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new(wrapper_name: String) -> Result<Self> {
-        let wrapped_query_str = format!(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    pub fn try_new(
+        wrapper_name: String,
+        grammar: Grammar,
+        user_queries: &UserQueries,
+    ) -> Result<Self> {
+        let loaded = load_query(
+            grammar,
+            user_queries,
+            "wrapper_call.scm.tpl",
             include_str!("../../runtime/queries/typescript/wrapper_call.scm.tpl"),
-            wrapper_name
-        );
-        let query = Query::new(language(), &wrapped_query_str)?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
-        let module_name_idx = query
-            .capture_index_for_name(WRAPPER_ARGS_MODULE_CAPTURE)
-            .ok_or_else(|| {
-                AmlError::MissingNamedCapture(WRAPPER_ARGS_MODULE_CAPTURE.to_string())
-            })?;
+            include_str!("../../runtime/queries/typescript/wrapper_call.scm.tpl"),
+            &[&wrapper_name],
+        )?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
+        let options_idx = loaded.capture_index(WRAPPER_OPTIONS_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
+            grammar,
             wrapper_name,
             func_name_idx,
-            module_name_idx,
+            options_idx,
         })
     }
 
-    pub fn list_function_names(&self, file_name: &str, source: &str) -> Result<Vec<FunctionInfo>> {
-        let mut parser = new_parser()?;
+    pub fn list_function_names(
+        &self,
+        file_name: &str,
+        module_name: &str,
+        source: &str,
+        imports_map: &ImportsMap,
+        symbols: &SymbolTable,
+    ) -> Result<Vec<FunctionInfo>> {
+        let mut parser = new_parser(self.grammar)?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
         let mut cursor = tree_sitter::QueryCursor::new();
         let functions = cursor
             .matches(&self.query, parsed_source.root_node(), source.as_bytes())
             .filter_map(|capture| -> Option<FunctionInfo> {
-                // Bare function
-                let func_name_node = capture.nodes_for_capture_index(self.func_name_idx).next();
-                let module_name_node = capture.nodes_for_capture_index(self.module_name_idx).next();
-
-                match (
-                    module_name_node
-                        .map(|node| node.utf8_text(source.as_bytes()).map(ToString::to_string)),
-                    func_name_node
-                        .map(|node| node.utf8_text(source.as_bytes()).map(ToString::to_string)),
-                ) {
-                    (Some(Ok(module)), Some(Ok(function))) => {
-                        let start = func_name_node
-                            .expect("just extracted a name from the node")
-                            .start_position();
-                        let end = func_name_node
-                            .expect("just extracted a name from the node")
-                            .end_position();
-                        let definition = None;
-                        let instrumentation = Some(Location::from((file_name, start, end)));
-                        Some(FunctionInfo {
-                            id: (module, function).into(),
-                            instrumentation,
-                            definition,
-                        })
-                    }
-                    (_, Some(Err(e))) => {
-                        warn!("Could not extract a function name: {e}");
-                        None
-                    }
-                    (Some(Err(e)), _) => {
-                        warn!("Could not extract a module name: {e}");
-                        None
-                    }
-                    _ => None,
+                let func_node = capture.nodes_for_capture_index(self.func_name_idx).next()?;
+                let options_node = capture.nodes_for_capture_index(self.options_idx).next()?;
+                let function_name = string_option(options_node, "functionName", source);
+                let module = string_option(options_node, "moduleName", source);
+                if function_name.is_none() && module.is_none() {
+                    // Same naming as when the function is given alone.
+                    return wrapped_function(
+                        func_node,
+                        file_name,
+                        module_name,
+                        source,
+                        imports_map,
+                        symbols,
+                    );
                 }
+
+                let (function, name_node) = match function_name {
+                    Some(name) => name,
+                    None => inferred_name(func_node, source)?,
+                };
+                let module = module.map_or_else(|| module_name.to_string(), |(module, _)| module);
+                let instrumentation = Some(Location::from((
+                    file_name,
+                    name_node.start_position(),
+                    name_node.end_position(),
+                )));
+                Some(FunctionInfo {
+                    id: (module, function).into(),
+                    instrumentation,
+                    definition: None,
+                    ..Default::default()
+                })
             })
             .collect();
         Ok(functions)
@@ -404,31 +655,38 @@ impl AmWrapperSubquery {
 #[derive(Debug)]
 struct AmWrapperDirectSubquery {
     query: Query,
+    grammar: Grammar,
     /// Name of the wrapper function to look for
     // Having the wrapper_name is useful when debugging the queries
     #[allow(dead_code)]
     wrapper_name: String,
-    /// Index of the capture for the function name being called in the wrapper.
+    /// Index of the capture for the function being called in the wrapper.
     func_name_idx: u32,
 }
 
 impl AmWrapperDirectSubquery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new(wrapper_name: String) -> Result<Self> {
-        let wrapped_query_str = format!(
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    pub fn try_new(
+        wrapper_name: String,
+        grammar: Grammar,
+        user_queries: &UserQueries,
+    ) -> Result<Self> {
+        let loaded = load_query(
+            grammar,
+            user_queries,
+            "wrapper_direct_call.scm.tpl",
             include_str!("../../runtime/queries/typescript/wrapper_direct_call.scm.tpl"),
-            wrapper_name
-        );
-        let query = Query::new(language(), &wrapped_query_str)?;
-        let func_name_idx = query
-            .capture_index_for_name(FUNC_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(FUNC_NAME_CAPTURE.to_string()))?;
+            include_str!("../../runtime/queries/javascript/wrapper_direct_call.scm.tpl"),
+            &[&wrapper_name],
+        )?;
+        let func_name_idx = loaded.capture_index(FUNC_NAME_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
+            grammar,
             wrapper_name,
             func_name_idx,
         })
@@ -439,60 +697,169 @@ impl AmWrapperDirectSubquery {
         file_name: &str,
         module_name: &str,
         source: &str,
-        imports_map: ImportsMap,
+        imports_map: &ImportsMap,
+        symbols: &SymbolTable,
     ) -> Result<Vec<FunctionInfo>> {
-        let mut parser = new_parser()?;
+        let mut parser = new_parser(self.grammar)?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
         let mut cursor = tree_sitter::QueryCursor::new();
         let functions = cursor
             .matches(&self.query, parsed_source.root_node(), source.as_bytes())
             .filter_map(|capture| -> Option<FunctionInfo> {
-                // Bare function
-                let func_name_node = capture.nodes_for_capture_index(self.func_name_idx).next();
-                match func_name_node
-                    .map(|node| node.utf8_text(source.as_bytes()).map(ToString::to_string))
-                {
-                    Some(Ok(fn_name)) => {
-                        let start = func_name_node
-                            .expect("just extracted a name from the node")
-                            .start_position();
-                        let end = func_name_node
-                            .expect("just extracted a name from the node")
-                            .end_position();
-                        let definition = None;
-                        let instrumentation = Some(Location::from((file_name, start, end)));
-                        if let Some((ident, source)) =
-                            imports_map.resolve_ident(Identifier::from(&fn_name))
-                        {
-                            Some(FunctionInfo {
-                                id: (source, ident).into(),
-                                instrumentation,
-                                definition,
-                            })
-                        } else {
-                            Some(FunctionInfo {
-                                id: (module_name, fn_name).into(),
-                                instrumentation,
-                                definition,
-                            })
-                        }
-                    }
-                    Some(Err(e)) => {
-                        warn!("Could not extract a function name: {e}");
-                        None
-                    }
-                    _ => None,
-                }
+                let func_node = capture.nodes_for_capture_index(self.func_name_idx).next()?;
+                wrapped_function(
+                    func_node,
+                    file_name,
+                    module_name,
+                    source,
+                    imports_map,
+                    symbols,
+                )
             })
             .collect();
         Ok(functions)
     }
 }
 
+/// Return the information of the function given to an autometrics wrapper.
+///
+/// Imported functions are resolved to their definition; local functions are
+/// named like autometrics names them at runtime, from their `name`.
+fn wrapped_function(
+    func_node: Node,
+    file_name: &str,
+    module_name: &str,
+    source: &str,
+    imports_map: &ImportsMap,
+    symbols: &SymbolTable,
+) -> Option<FunctionInfo> {
+    let (fn_name, name_node) = match func_node.kind() {
+        "identifier" | "member_expression" => match func_node.utf8_text(source.as_bytes()) {
+            Ok(text) => (text.to_string(), func_node),
+            Err(e) => {
+                warn!("Could not extract a function name: {e}");
+                return None;
+            }
+        },
+        _ => inferred_name(func_node, source)?,
+    };
+    let instrumentation = Some(Location::from((
+        file_name,
+        name_node.start_position(),
+        name_node.end_position(),
+    )));
+
+    let ident = Identifier::from(&fn_name);
+    if let Some((ident, source)) = imports_map.resolve_ident(ident.clone()) {
+        match symbols.resolve_import(&source, &ident) {
+            Some(symbol) => Some(FunctionInfo {
+                id: (symbol.module, symbol.name).into(),
+                instrumentation,
+                definition: Some(symbol.definition),
+                ..Default::default()
+            }),
+            None => Some(FunctionInfo {
+                id: (source, ident).into(),
+                instrumentation,
+                definition: None,
+                ..Default::default()
+            }),
+        }
+    } else if let Some(symbol) = imports_map
+        .resolve_member(&ident)
+        .and_then(|(ident, source)| symbols.resolve_import(&source, &ident))
+    {
+        // Member of an imported namespace re-export
+        Some(FunctionInfo {
+            id: (symbol.module, symbol.name).into(),
+            instrumentation,
+            definition: Some(symbol.definition),
+            ..Default::default()
+        })
+    } else {
+        // The `name` of a method (`obj.method`) is its property name.
+        let fn_name = match fn_name.rsplit_once('.') {
+            Some((_, property)) => property.to_string(),
+            None => fn_name,
+        };
+        let definition = symbols
+            .resolve_local(file_name, &Identifier::from(&fn_name))
+            .map(|symbol| symbol.definition);
+        Some(FunctionInfo {
+            id: (module_name, fn_name).into(),
+            instrumentation,
+            definition,
+            ..Default::default()
+        })
+    }
+}
+
+/// Return the name a function gets at runtime, and the node that holds it.
+///
+/// Anonymous functions are named after the variable or property the wrapped
+/// function is assigned to (`const getUser = autometrics(async () => {})`).
+fn inferred_name<'tree>(func_node: Node<'tree>, source: &str) -> Option<(String, Node<'tree>)> {
+    let name_node = match func_node.kind() {
+        "identifier" => Some(func_node),
+        "member_expression" => func_node.child_by_field_name("property"),
+        _ => func_node.child_by_field_name("name").or_else(|| {
+            // The function is an argument of the wrapper call.
+            let call = func_node.parent()?.parent()?;
+            let holder = call.parent()?;
+            match holder.kind() {
+                "variable_declarator" => holder.child_by_field_name("name"),
+                "assignment_expression" => holder.child_by_field_name("left"),
+                "pair" => holder.child_by_field_name("key"),
+                _ => None,
+            }
+            .filter(|name| name.kind() == "identifier" || name.kind() == "property_identifier")
+        }),
+    };
+    let Some(name_node) = name_node else {
+        warn!("Could not infer the name of an anonymous wrapped function");
+        return None;
+    };
+    match name_node.utf8_text(source.as_bytes()) {
+        Ok(name) => Some((name.to_string(), name_node)),
+        Err(e) => {
+            warn!("Could not extract a function name: {e}");
+            None
+        }
+    }
+}
+
+/// Return the value of a string property of an options object, and the node of
+/// the string contents.
+fn string_option<'tree>(
+    object: Node<'tree>,
+    key: &str,
+    source: &str,
+) -> Option<(String, Node<'tree>)> {
+    let mut cursor = object.walk();
+    let value = object
+        .named_children(&mut cursor)
+        .filter(|property| property.kind() == "pair")
+        .find(|pair| {
+            pair.child_by_field_name("key")
+                .and_then(|key| key.utf8_text(source.as_bytes()).ok())
+                .map_or(false, |text| {
+                    text.trim_matches(|c| c == '"' || c == '\'') == key
+                })
+        })?
+        .child_by_field_name("value")?;
+    let fragment = match value.kind() {
+        "string" => value.named_child(0)?,
+        _ => return None,
+    };
+    let text = fragment.utf8_text(source.as_bytes()).ok()?.to_string();
+    Some((text, fragment))
+}
+
 /// Query wrapper for imports in the source
 #[derive(Debug)]
 pub(super) struct ImportsMapQuery {
     query: Query,
+    grammar: Grammar,
     /// Index of the capture for a named import in the source.
     named_import_idx: u32,
     /// Index of the capture for a namespace import in the source.
@@ -507,28 +874,25 @@ pub(super) struct ImportsMapQuery {
 impl ImportsMapQuery {
     /// Failible constructor.
     ///
-    /// The constructor only fails if the given tree-sitter query does not have the
-    /// necessary named captures.
-    pub fn try_new() -> Result<Self> {
-        let query = Query::new(
-            language(),
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    pub fn try_new(grammar: Grammar, user_queries: &UserQueries) -> Result<Self> {
+        let loaded = load_query(
+            grammar,
+            user_queries,
+            "imports_map.scm",
             include_str!("../../runtime/queries/typescript/imports_map.scm"),
+            include_str!("../../runtime/queries/typescript/imports_map.scm"),
+            &[],
         )?;
-        let named_import_idx = query
-            .capture_index_for_name(IMPORTS_IDENT_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPORTS_IDENT_NAME_CAPTURE.to_string()))?;
-        let prefixed_import_idx = query
-            .capture_index_for_name(IMPORTS_PREFIX_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPORTS_PREFIX_CAPTURE.to_string()))?;
-        let import_og_name_idx = query
-            .capture_index_for_name(IMPORTS_REAL_NAME_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPORTS_REAL_NAME_CAPTURE.to_string()))?;
-        let source_idx = query
-            .capture_index_for_name(IMPORTS_SOURCE_CAPTURE)
-            .ok_or_else(|| AmlError::MissingNamedCapture(IMPORTS_SOURCE_CAPTURE.to_string()))?;
+        let named_import_idx = loaded.capture_index(IMPORTS_IDENT_NAME_CAPTURE)?;
+        let prefixed_import_idx = loaded.capture_index(IMPORTS_PREFIX_CAPTURE)?;
+        let import_og_name_idx = loaded.capture_index(IMPORTS_REAL_NAME_CAPTURE)?;
+        let source_idx = loaded.capture_index(IMPORTS_SOURCE_CAPTURE)?;
 
         Ok(Self {
-            query,
+            query: loaded.query,
+            grammar,
             named_import_idx,
             prefixed_import_idx,
             import_og_name_idx,
@@ -536,10 +900,16 @@ impl ImportsMapQuery {
         })
     }
 
-    pub fn list_imports(&self, file_path: Option<&Path>, source: &str) -> Result<ImportsMap> {
+    /// List the imports of the source, for a file in the `file_path` directory.
+    pub fn list_imports(
+        &self,
+        file_path: Option<&Path>,
+        source: &str,
+        resolver: &ModuleResolver,
+    ) -> Result<ImportsMap> {
         let mut res = ImportsMap::default();
 
-        let mut parser = new_parser()?;
+        let mut parser = new_parser(self.grammar)?;
         let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
         let mut cursor = tree_sitter::QueryCursor::new();
         for capture in cursor.matches(&self.query, parsed_source.root_node(), source.as_bytes()) {
@@ -566,7 +936,7 @@ impl ImportsMapQuery {
                     .map_err(|_| AmlError::InvalidText)?
                     .into();
 
-                res.add_namespace(prefix, import_source.into_canonical(file_path));
+                res.add_namespace(prefix, resolver.resolve(import_source, file_path));
             }
 
             // Check for the other capture
@@ -604,10 +974,10 @@ impl ImportsMapQuery {
                     res.add_aliased_import(
                         ident_name,
                         real_name,
-                        import_source.into_canonical(file_path),
+                        resolver.resolve(import_source, file_path),
                     );
                 } else {
-                    res.add_named_import(ident_name, import_source.into_canonical(file_path));
+                    res.add_named_import(ident_name, resolver.resolve(import_source, file_path));
                 }
             }
         }
@@ -615,3 +985,173 @@ impl ImportsMapQuery {
         Ok(res)
     }
 }
+
+/// Query wrapper for the top-level definitions and the exports of a source
+#[derive(Debug)]
+pub(super) struct ExportsQuery {
+    query: Query,
+    grammar: Grammar,
+    /// Index of the capture for the name of a top-level definition.
+    definition_name_idx: u32,
+    /// Index of the capture for the local name of an exported symbol.
+    export_local_idx: u32,
+    /// Index of the capture for the exported name of a symbol, when it is
+    /// exported under another name than its local one.
+    export_name_idx: u32,
+    /// Index of the capture for the `default` keyword of default exports.
+    export_default_idx: u32,
+    /// Index of the capture for the source of a re-export.
+    reexport_source_idx: u32,
+    /// Index of the capture for the `*` of `export * from` statements.
+    reexport_all_idx: u32,
+    /// Index of the capture for the namespace of `export * as ns from` statements.
+    reexport_namespace_idx: u32,
+}
+
+impl ExportsQuery {
+    /// Failible constructor.
+    ///
+    /// The constructor only fails if the given tree-sitter query (built-in or
+    /// user-supplied) is invalid or does not have the necessary named captures.
+    pub fn try_new(grammar: Grammar, user_queries: &UserQueries) -> Result<Self> {
+        let loaded = load_query(
+            grammar,
+            user_queries,
+            "exports.scm",
+            include_str!("../../runtime/queries/typescript/exports.scm"),
+            include_str!("../../runtime/queries/javascript/exports.scm"),
+            &[],
+        )?;
+        let definition_name_idx = loaded.capture_index(DEFINITION_NAME_CAPTURE)?;
+        let export_local_idx = loaded.capture_index(EXPORT_LOCAL_CAPTURE)?;
+        let export_name_idx = loaded.capture_index(EXPORT_NAME_CAPTURE)?;
+        let export_default_idx = loaded.capture_index(EXPORT_DEFAULT_CAPTURE)?;
+        let reexport_source_idx = loaded.capture_index(REEXPORT_SOURCE_CAPTURE)?;
+        let reexport_all_idx = loaded.capture_index(REEXPORT_ALL_CAPTURE)?;
+        let reexport_namespace_idx = loaded.capture_index(REEXPORT_NAMESPACE_CAPTURE)?;
+
+        Ok(Self {
+            query: loaded.query,
+            grammar,
+            definition_name_idx,
+            export_local_idx,
+            export_name_idx,
+            export_default_idx,
+            reexport_source_idx,
+            reexport_all_idx,
+            reexport_namespace_idx,
+        })
+    }
+
+    /// List the top-level definitions and the exports of the source, for a
+    /// file in the `file_path` directory.
+    ///
+    /// `imports` are the imports of the file, to detect the exports of imported
+    /// symbols.
+    pub fn list_symbols(
+        &self,
+        file_name: &str,
+        module_name: &str,
+        source: &str,
+        file_path: Option<&Path>,
+        imports: &ImportsMap,
+        resolver: &ModuleResolver,
+    ) -> Result<FileSymbols> {
+        let mut res = FileSymbols {
+            module: module_name.to_string(),
+            ..Default::default()
+        };
+
+        let mut parser = new_parser(self.grammar)?;
+        let parsed_source = parser.parse(source, None).ok_or(AmlError::Parsing)?;
+        let text = |node: tree_sitter::Node| -> Result<String> {
+            node.utf8_text(source.as_bytes())
+                .map(ToString::to_string)
+                .map_err(|_| AmlError::InvalidText)
+        };
+
+        // Exports of local names are only resolved once all the definitions are
+        // known, as they can be exported before being defined.
+        let mut local_exports = Vec::new();
+        let mut cursor = tree_sitter::QueryCursor::new();
+        for capture in cursor.matches(&self.query, parsed_source.root_node(), source.as_bytes()) {
+            if let Some(node) = capture
+                .nodes_for_capture_index(self.definition_name_idx)
+                .next()
+            {
+                let location =
+                    Location::from((file_name, node.start_position(), node.end_position()));
+                res.definitions.insert(text(node)?.into(), location);
+            }
+
+            let reexport_source = capture
+                .nodes_for_capture_index(self.reexport_source_idx)
+                .next()
+                .map(|node| -> Result<_> {
+                    Ok(resolver.resolve(Source::from(text(node)?), file_path))
+                })
+                .transpose()?;
+
+            if let Some(reexport_source) = &reexport_source {
+                if capture
+                    .nodes_for_capture_index(self.reexport_all_idx)
+                    .next()
+                    .is_some()
+                {
+                    res.star_reexports.push(reexport_source.clone());
+                }
+                if let Some(namespace) = capture
+                    .nodes_for_capture_index(self.reexport_namespace_idx)
+                    .next()
+                {
+                    res.exports.insert(
+                        text(namespace)?.into(),
+                        Export::Namespace(reexport_source.clone()),
+                    );
+                }
+            }
+
+            if let Some(local) = capture
+                .nodes_for_capture_index(self.export_local_idx)
+                .next()
+            {
+                let local = Identifier::from(text(local)?);
+                let exported = if capture
+                    .nodes_for_capture_index(self.export_default_idx)
+                    .next()
+                    .is_some()
+                {
+                    Identifier::from(DEFAULT_EXPORT)
+                } else if let Some(name) =
+                    capture.nodes_for_capture_index(self.export_name_idx).next()
+                {
+                    text(name)?.into()
+                } else {
+                    local.clone()
+                };
+                match reexport_source {
+                    Some(reexport_source) => {
+                        res.exports
+                            .insert(exported, Export::Reexport(reexport_source, local));
+                    }
+                    None => local_exports.push((exported, local)),
+                }
+            }
+        }
+
+        for (exported, local) in local_exports {
+            let export = if res.definitions.contains_key(&local) {
+                Export::Local(local)
+            } else if let Some((name, import_source)) = imports.find_identifier(&local) {
+                Export::Reexport(import_source, name)
+            } else if let Some(import_source) = imports.find_namespace(&local) {
+                Export::Namespace(import_source)
+            } else {
+                Export::Local(local)
+            };
+            res.exports.insert(exported, export);
+        }
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,438 @@
+//! Resolution of non-relative imports to files of the project.
+//!
+//! Bundlers and the Typescript compiler allow to import project files without
+//! relative paths, through:
+//! - the `paths` and `baseUrl` compiler options of `tsconfig.json` (or
+//!   `jsconfig.json`), possibly inherited through `extends`,
+//! - the `exports` of the `package.json` of workspace packages, when a package
+//!   imports itself or another package of the repository by name.
+//!
+//! The compiler configurations also give where the compiled files are emitted,
+//! with the `outDir` and `rootDir` options.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::warn;
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use crate::{AmlError, Result};
+
+use super::{
+    imports::{CanonicalSource, Source},
+    symbols::{with_added_extension, RESOLVED_EXTENSIONS},
+};
+
+/// Names of the compiler configuration files, by order of precedence.
+const CONFIG_FILE_NAMES: [&str; 2] = ["tsconfig.json", "jsconfig.json"];
+
+/// Conditions of package `exports` to follow, by order of preference.
+///
+/// `types` comes last as it usually points to generated declaration files.
+const EXPORTS_CONDITIONS: [&str; 7] = [
+    "source", "import", "module", "default", "require", "node", "types",
+];
+
+/// Maximum length of an `extends` chain, to protect against cycles.
+const MAX_EXTENDS_DEPTH: usize = 16;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawConfig {
+    extends: Option<Extends>,
+    #[serde(default)]
+    compiler_options: RawCompilerOptions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Extends {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawCompilerOptions {
+    base_url: Option<String>,
+    paths: Option<BTreeMap<String, Vec<String>>>,
+    out_dir: Option<String>,
+    root_dir: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawPackageJson {
+    name: Option<String>,
+    exports: Option<serde_json::Value>,
+    main: Option<String>,
+    module: Option<String>,
+}
+
+/// The options of a compiler configuration used by am_list, after following
+/// the `extends` chain.
+#[derive(Clone, Debug, Default)]
+struct CompilerOptions {
+    /// Absolute `baseUrl`.
+    base_url: Option<PathBuf>,
+    /// `paths` patterns, with their targets.
+    paths: Vec<(String, Vec<String>)>,
+    /// Directory of the configuration file that defines `paths`, against which
+    /// targets are resolved when there is no `baseUrl`.
+    paths_dir: PathBuf,
+    /// Absolute `outDir`.
+    out_dir: Option<PathBuf>,
+    /// Absolute `rootDir`.
+    root_dir: Option<PathBuf>,
+}
+
+impl CompilerOptions {
+    /// Load the configuration file at `path`, and its base configurations.
+    fn load(path: &Path, depth: usize) -> Result<Self> {
+        let contents = read_to_string(path).map_err(|err| AmlError::Io(path.to_path_buf(), err))?;
+        let raw: RawConfig = match json5::from_str(&contents) {
+            Ok(raw) => raw,
+            Err(err) => {
+                warn!("Ignoring invalid {}: {err}", path.display());
+                return Ok(Self::default());
+            }
+        };
+        let config_dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut res = Self::default();
+        let bases = match raw.extends {
+            Some(Extends::One(base)) => vec![base],
+            Some(Extends::Many(bases)) => bases,
+            None => Vec::new(),
+        };
+        for base in bases {
+            if depth >= MAX_EXTENDS_DEPTH {
+                warn!("Too many nested `extends` in {}", path.display());
+                break;
+            }
+            match find_base_config(config_dir, &base) {
+                Some(base_path) => res.merge(Self::load(&base_path, depth + 1)?),
+                None => warn!(
+                    "Could not find the configuration {base} extended by {}",
+                    path.display()
+                ),
+            }
+        }
+
+        res.merge(Self {
+            base_url: raw
+                .compiler_options
+                .base_url
+                .map(|base_url| config_dir.join(base_url)),
+            out_dir: raw
+                .compiler_options
+                .out_dir
+                .map(|out_dir| config_dir.join(out_dir)),
+            root_dir: raw
+                .compiler_options
+                .root_dir
+                .map(|root_dir| config_dir.join(root_dir)),
+            paths: raw
+                .compiler_options
+                .paths
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            paths_dir: config_dir.to_path_buf(),
+        });
+        Ok(res)
+    }
+
+    /// Override the options of `self` with the ones set in `other`.
+    fn merge(&mut self, other: Self) {
+        if other.base_url.is_some() {
+            self.base_url = other.base_url;
+        }
+        if !other.paths.is_empty() {
+            self.paths = other.paths;
+            self.paths_dir = other.paths_dir;
+        }
+        if other.out_dir.is_some() {
+            self.out_dir = other.out_dir;
+        }
+        if other.root_dir.is_some() {
+            self.root_dir = other.root_dir;
+        }
+    }
+
+    /// Resolve a non-relative import specifier to a file, if the options map it.
+    fn resolve(&self, specifier: &str) -> Option<PathBuf> {
+        let targets_dir = self.base_url.as_ref().unwrap_or(&self.paths_dir);
+
+        // The pattern with the longest prefix wins, like in the compiler.
+        let best_match = self
+            .paths
+            .iter()
+            .filter_map(|(pattern, targets)| {
+                let captured = match_pattern(pattern, specifier)?;
+                let prefix_len = pattern.find('*').unwrap_or(pattern.len());
+                Some((prefix_len, captured, targets))
+            })
+            .max_by_key(|(prefix_len, _, _)| *prefix_len);
+        if let Some((_, captured, targets)) = best_match {
+            let resolved = targets
+                .iter()
+                .find_map(|target| probe(&targets_dir.join(target.replacen('*', captured, 1))));
+            if resolved.is_some() {
+                return resolved;
+            }
+        }
+
+        self.base_url
+            .as_ref()
+            .and_then(|base_url| probe(&base_url.join(specifier)))
+    }
+}
+
+/// Where the compiler emits the files of a directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmitOptions {
+    /// Directory of the compiler configuration file.
+    pub config_dir: PathBuf,
+    /// Output directory of the compiled files.
+    pub out_dir: PathBuf,
+    /// Directory whose layout is reproduced in `out_dir`. When it is not set,
+    /// the compiler uses the common directory of all the source files.
+    pub root_dir: Option<PathBuf>,
+}
+
+/// A package of the workspace, that can be imported by name.
+#[derive(Clone, Debug)]
+struct WorkspacePackage {
+    name: String,
+    dir: PathBuf,
+    exports: Option<serde_json::Value>,
+    main: Option<String>,
+}
+
+impl WorkspacePackage {
+    /// Resolve the `subpath` of the package (`.` for the package itself, or
+    /// like `./client`) to a file.
+    fn resolve(&self, subpath: &str) -> Option<PathBuf> {
+        if let Some(exports) = &self.exports {
+            return exports_targets(exports, subpath)
+                .into_iter()
+                .find_map(|target| probe(&self.dir.join(target)));
+        }
+        match subpath {
+            "." => self
+                .main
+                .as_ref()
+                .and_then(|main| probe(&self.dir.join(main)))
+                .or_else(|| probe(&self.dir.join("index"))),
+            _ => probe(&self.dir.join(subpath)),
+        }
+    }
+}
+
+/// Resolver of the import specifiers of the project.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleResolver {
+    /// Path mapping options, indexed by the directory of their configuration file.
+    configs: HashMap<PathBuf, Arc<CompilerOptions>>,
+    /// Workspace packages, the longest names first.
+    packages: Vec<WorkspacePackage>,
+}
+
+impl ModuleResolver {
+    /// Read the compiler configurations and the package manifests of the
+    /// project at `project_root`.
+    pub fn discover(project_root: &Path) -> Result<Self> {
+        let mut res = Self::default();
+
+        let walker = WalkDir::new(project_root)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || entry.file_name().to_str().map_or(false, |name| {
+                        !name.starts_with('.') && name != "node_modules"
+                    })
+            });
+        for entry in walker.filter_map(|entry| entry.ok()) {
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            let dir = entry.path();
+
+            if let Some(config) = CONFIG_FILE_NAMES
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+            {
+                res.configs.insert(
+                    dir.to_path_buf(),
+                    Arc::new(CompilerOptions::load(&config, 0)?),
+                );
+            }
+
+            let manifest = dir.join("package.json");
+            if manifest.is_file() {
+                let contents =
+                    read_to_string(&manifest).map_err(|err| AmlError::Io(manifest.clone(), err))?;
+                match serde_json::from_str::<RawPackageJson>(&contents) {
+                    Ok(RawPackageJson {
+                        name: Some(name),
+                        exports,
+                        main,
+                        module,
+                    }) => res.packages.push(WorkspacePackage {
+                        name,
+                        dir: dir.to_path_buf(),
+                        exports,
+                        main: module.or(main),
+                    }),
+                    Ok(_) => {}
+                    Err(err) => warn!("Ignoring invalid {}: {err}", manifest.display()),
+                }
+            }
+        }
+
+        res.packages
+            .sort_by_key(|package| std::cmp::Reverse(package.name.len()));
+        Ok(res)
+    }
+
+    /// Compute the canonical source of an import found in a file of the
+    /// `import_dir` directory.
+    pub fn resolve(&self, source: Source, import_dir: Option<&Path>) -> CanonicalSource {
+        let specifier = source.to_string();
+        if let Some(dir) = import_dir {
+            if !specifier.starts_with('.') {
+                if let Some(path) = self.resolve_non_relative(&specifier, dir) {
+                    return CanonicalSource::from(path.to_string_lossy());
+                }
+            }
+        }
+        source.into_canonical(import_dir)
+    }
+
+    /// Return where the compiler emits the files of `dir`, if their
+    /// configuration has an `outDir`.
+    pub fn emit_options(&self, dir: &Path) -> Option<EmitOptions> {
+        let (config_dir, config) = dir
+            .ancestors()
+            .find_map(|ancestor| self.configs.get_key_value(ancestor))?;
+        Some(EmitOptions {
+            config_dir: config_dir.clone(),
+            out_dir: config.out_dir.clone()?,
+            root_dir: config.root_dir.clone(),
+        })
+    }
+
+    fn resolve_non_relative(&self, specifier: &str, import_dir: &Path) -> Option<PathBuf> {
+        let config = import_dir
+            .ancestors()
+            .find_map(|ancestor| self.configs.get(ancestor));
+        if let Some(path) = config.and_then(|config| config.resolve(specifier)) {
+            return Some(path);
+        }
+
+        self.packages.iter().find_map(|package| {
+            let subpath = if specifier == package.name {
+                ".".to_string()
+            } else {
+                format!(".{}", specifier.strip_prefix(&package.name)?)
+            };
+            if subpath != "." && !subpath.starts_with("./") {
+                return None;
+            }
+            package.resolve(&subpath)
+        })
+    }
+}
+
+/// Return the base configuration file designated by an `extends` entry.
+fn find_base_config(config_dir: &Path, base: &str) -> Option<PathBuf> {
+    let candidates = |path: PathBuf| {
+        [
+            path.clone(),
+            with_added_extension(&path, "json"),
+            path.join("tsconfig.json"),
+        ]
+    };
+    if base.starts_with('.') || Path::new(base).is_absolute() {
+        return candidates(config_dir.join(base))
+            .into_iter()
+            .find(|path| path.is_file());
+    }
+    // Shared configurations are installed as packages.
+    config_dir.ancestors().find_map(|dir| {
+        candidates(dir.join("node_modules").join(base))
+            .into_iter()
+            .find(|path| path.is_file())
+    })
+}
+
+/// Return the part of `specifier` matched by the `*` of `pattern`, or an
+/// empty string for an exact match.
+fn match_pattern<'a>(pattern: &str, specifier: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => specifier.strip_prefix(prefix)?.strip_suffix(suffix),
+        None => (pattern == specifier).then_some(""),
+    }
+}
+
+/// Return the targets of the `exports` of a package for the given subpath, by
+/// order of preference.
+fn exports_targets(exports: &serde_json::Value, subpath: &str) -> Vec<String> {
+    use serde_json::Value;
+
+    match exports {
+        Value::String(target) if subpath == "." => vec![target.clone()],
+        Value::Array(alternatives) => alternatives
+            .iter()
+            .flat_map(|exports| exports_targets(exports, subpath))
+            .collect(),
+        Value::Object(map) if map.keys().any(|key| key.starts_with('.')) => {
+            if let Some(exports) = map.get(subpath) {
+                return exports_targets(exports, ".");
+            }
+            map.iter()
+                .filter_map(|(pattern, exports)| {
+                    let captured = match_pattern(pattern, subpath)?;
+                    let prefix_len = pattern.find('*')?;
+                    Some((prefix_len, captured, exports))
+                })
+                .max_by_key(|(prefix_len, _, _)| *prefix_len)
+                .map(|(_, captured, exports)| {
+                    exports_targets(exports, ".")
+                        .into_iter()
+                        .map(|target| target.replace('*', captured))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+        Value::Object(conditions) if subpath == "." => EXPORTS_CONDITIONS
+            .iter()
+            .filter_map(|condition| conditions.get(*condition))
+            .flat_map(|exports| exports_targets(exports, "."))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Return the file designated by `path`, trying the usual extensions and
+/// `index` files.
+fn probe(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    RESOLVED_EXTENSIONS
+        .iter()
+        .map(|ext| with_added_extension(path, ext))
+        .chain(
+            RESOLVED_EXTENSIONS
+                .iter()
+                .map(|ext| path.join(format!("index.{ext}"))),
+        )
+        .find(|candidate| candidate.is_file())
+}
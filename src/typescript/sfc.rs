@@ -0,0 +1,111 @@
+//! Script blocks of Vue and Svelte single-file components.
+//!
+//! A component mixes markup, styles and `<script>` blocks in a single file.
+//! Only the scripts are analyzed: everything outside of them is blanked out
+//! (each byte replaced by a space, newlines kept), so that the resulting source
+//! can be parsed with the Typescript grammars while keeping every node at the
+//! same line and column as in the component.
+
+use std::path::Path;
+
+use super::grammar::Grammar;
+
+/// Extensions of the single-file components.
+pub const SFC_EXTENSIONS: [&str; 2] = ["vue", "svelte"];
+
+/// Return true if the file at `path` is a single-file component.
+pub fn is_sfc(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| SFC_EXTENSIONS.contains(&ext))
+}
+
+/// Keep only the contents of the `<script>` blocks of a component, and return
+/// them with the grammar to parse them with.
+///
+/// The grammar comes from the `lang` attribute of the blocks: `ts` for
+/// Typescript, `tsx` and `jsx` for TSX, and Javascript without attribute.
+pub fn extract_scripts(source: &str) -> (String, Grammar) {
+    let mut scripts = vec![b' '; source.len()];
+    let mut grammar = None;
+
+    let lowercase = source.to_ascii_lowercase();
+    let mut cursor = 0;
+    while let Some(start) = find_script_tag(&lowercase, cursor) {
+        let Some(tag_end) = lowercase[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        let attributes = &source[start + "<script".len()..tag_end];
+        let content_start = tag_end + 1;
+        let content_end = lowercase[content_start..]
+            .find("</script")
+            .map_or(source.len(), |end| content_start + end);
+
+        // Self-closing tags (`<script src="..." />`) have no contents.
+        if !attributes.trim_end().ends_with('/') {
+            scripts[content_start..content_end]
+                .copy_from_slice(&source.as_bytes()[content_start..content_end]);
+            grammar = grammar.or_else(|| lang_attribute(attributes).map(grammar_for_lang));
+        }
+        cursor = content_end;
+    }
+
+    for (blank, byte) in scripts.iter_mut().zip(source.bytes()) {
+        if byte == b'\n' || byte == b'\r' {
+            *blank = byte;
+        }
+    }
+
+    let scripts = String::from_utf8(scripts)
+        .expect("the script blocks are delimited by ASCII tags, and the rest is blanked");
+    (scripts, grammar.unwrap_or(Grammar::Javascript))
+}
+
+/// Find the start of the next `<script` opening tag in the lowercase source,
+/// from `from`.
+fn find_script_tag(lowercase: &str, from: usize) -> Option<usize> {
+    let mut from = from;
+    while let Some(start) = lowercase[from..].find("<script").map(|start| from + start) {
+        let after = lowercase[start + "<script".len()..].chars().next();
+        if after.map_or(false, |c| c == '>' || c == '/' || c.is_ascii_whitespace()) {
+            return Some(start);
+        }
+        from = start + "<script".len();
+    }
+    None
+}
+
+/// Return the value of the `lang` attribute in the attributes of a tag.
+fn lang_attribute(attributes: &str) -> Option<&str> {
+    let mut rest = attributes;
+    while let Some(index) = rest.find("lang") {
+        let preceded_by_space = rest[..index]
+            .chars()
+            .next_back()
+            .map_or(true, |c| c.is_ascii_whitespace());
+        let value = rest[index + "lang".len()..].trim_start();
+        rest = &rest[index + "lang".len()..];
+        if !preceded_by_space {
+            continue;
+        }
+        let Some(value) = value.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        return match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+            _ => value
+                .split(|c: char| c.is_ascii_whitespace() || c == '/')
+                .next(),
+        };
+    }
+    None
+}
+
+fn grammar_for_lang(lang: &str) -> Grammar {
+    match lang {
+        "ts" | "typescript" => Grammar::Typescript,
+        "tsx" | "jsx" => Grammar::Tsx,
+        _ => Grammar::Javascript,
+    }
+}
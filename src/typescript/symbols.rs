@@ -0,0 +1,199 @@
+//! Project-wide symbol table, to resolve imported functions to the file that
+//! defines them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::Location;
+
+use super::imports::{CanonicalSource, Identifier};
+
+/// Extensions tried, in order, when resolving an import to a file.
+pub(super) const RESOLVED_EXTENSIONS: [&str; 8] =
+    ["ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs"];
+
+/// Name under which the default export of a module is imported.
+pub const DEFAULT_EXPORT: &str = "default";
+
+/// A symbol exported by a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Export {
+    /// A symbol of the file, by local name.
+    Local(Identifier),
+    /// A symbol re-exported from another module, by its name in that module.
+    Reexport(CanonicalSource, Identifier),
+    /// A whole module re-exported as a namespace (`export * as ns from`).
+    Namespace(CanonicalSource),
+}
+
+/// The symbols defined and exported by a single source file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileSymbols {
+    /// Module name of the file, as reported for the functions it defines.
+    pub module: String,
+    /// Location of the top-level definitions of the file, by name.
+    pub definitions: HashMap<Identifier, Location>,
+    /// Exports of the file, by exported name.
+    pub exports: HashMap<Identifier, Export>,
+    /// Modules whose exports are all re-exported (`export * from`).
+    pub star_reexports: Vec<CanonicalSource>,
+}
+
+/// A function resolved to its definition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// Module name of the file defining the function.
+    pub module: String,
+    /// Name of the function in the file defining it.
+    pub name: Identifier,
+    pub definition: Location,
+}
+
+/// Symbols of all the source files in a project.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    root: PathBuf,
+    /// Symbols of each file, indexed by path relative to the project root.
+    files: HashMap<PathBuf, FileSymbols>,
+}
+
+impl SymbolTable {
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            root: normalize(project_root),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Add the symbols of the file at `file_name`, relative to the project root.
+    pub fn insert(&mut self, file_name: &str, symbols: FileSymbols) {
+        self.files.insert(normalize(Path::new(file_name)), symbols);
+    }
+
+    /// Resolve a function defined in the file at `file_name`, relative to the
+    /// project root.
+    pub fn resolve_local(&self, file_name: &str, ident: &Identifier) -> Option<Symbol> {
+        let symbols = self.files.get(&normalize(Path::new(file_name)))?;
+        Self::definition(symbols, ident)
+    }
+
+    /// Resolve a function imported as `ident` from `source`.
+    ///
+    /// Re-exports are followed until the file that defines the function, and
+    /// `ident` can designate a member of a re-exported namespace (`ns.fn`).
+    ///
+    /// This returns `None` if the source is outside of the project, or if it
+    /// does not export a function defined in the project under that name.
+    pub fn resolve_import(&self, source: &CanonicalSource, ident: &Identifier) -> Option<Symbol> {
+        self.resolve_export(source, &ident.to_string(), &mut HashSet::new())
+    }
+
+    /// Resolve the export `ident` of `source`.
+    ///
+    /// `visited` holds the files and names already looked up while following
+    /// the re-exports, so that cycles between barrel files end the resolution.
+    fn resolve_export<'a>(
+        &'a self,
+        source: &CanonicalSource,
+        ident: &str,
+        visited: &mut HashSet<(&'a Path, String)>,
+    ) -> Option<Symbol> {
+        let (file, symbols) = self.find_file(source.as_path()?)?;
+        if !visited.insert((file, ident.to_string())) {
+            return None;
+        }
+        let (head, member) = match ident.split_once('.') {
+            Some((head, member)) => (head, Some(member)),
+            None => (ident, None),
+        };
+
+        match (symbols.exports.get(&Identifier::from(head)), member) {
+            (Some(Export::Local(local_name)), None) => Self::definition(symbols, local_name),
+            (Some(Export::Reexport(source, name)), _) => {
+                let name = match member {
+                    Some(member) => format!("{name}.{member}"),
+                    None => name.to_string(),
+                };
+                self.resolve_export(source, &name, visited)
+            }
+            (Some(Export::Namespace(source)), Some(member)) => {
+                self.resolve_export(source, member, visited)
+            }
+            (Some(_), _) => None,
+            // `export *` does not re-export the default export.
+            (None, _) if head == DEFAULT_EXPORT => None,
+            (None, _) => symbols
+                .star_reexports
+                .iter()
+                .find_map(|source| self.resolve_export(source, ident, visited)),
+        }
+    }
+
+    fn definition(symbols: &FileSymbols, ident: &Identifier) -> Option<Symbol> {
+        symbols.definitions.get(ident).map(|definition| Symbol {
+            module: symbols.module.clone(),
+            name: ident.clone(),
+            definition: definition.clone(),
+        })
+    }
+
+    /// Find the file an import path designates, following the module
+    /// resolution rules of bundlers: the path can omit the extension of the
+    /// file, or designate a directory with an `index` file.
+    ///
+    /// This returns the path of the file, relative to the project root, along
+    /// with its symbols.
+    fn find_file(&self, import_path: &Path) -> Option<(&Path, &FileSymbols)> {
+        let import_path = normalize(import_path);
+        let relative = import_path.strip_prefix(&self.root).ok()?;
+
+        if let Some((file, symbols)) = self.files.get_key_value(relative) {
+            return Some((file, symbols));
+        }
+        // `./user.js` is the usual way to import `./user.ts` with ESM.
+        let stem = match relative.extension() {
+            Some(ext) if RESOLVED_EXTENSIONS.iter().any(|known| ext == *known) => {
+                relative.with_extension("")
+            }
+            _ => relative.to_path_buf(),
+        };
+        RESOLVED_EXTENSIONS
+            .iter()
+            .map(|ext| with_added_extension(&stem, ext))
+            .chain(
+                RESOLVED_EXTENSIONS
+                    .iter()
+                    .map(|ext| relative.join(format!("index.{ext}"))),
+            )
+            .find_map(|candidate| self.files.get_key_value(&candidate))
+            .map(|(file, symbols)| (file.as_path(), symbols))
+    }
+}
+
+/// Append an extension to a path, keeping the existing one (`user.service`
+/// becomes `user.service.ts`).
+pub(super) fn with_added_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut res = path.as_os_str().to_owned();
+    res.push(".");
+    res.push(ext);
+    res.into()
+}
+
+/// Lexically remove the `.` and `..` components of a path.
+pub(super) fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(res.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                res.pop();
+            }
+            _ => res.push(component),
+        }
+    }
+    res
+}
@@ -4,11 +4,13 @@
 //! language to then merge the sets so that functions that get detected by both
 //! queries have their information merged.
 
-use crate::{Location, Position, Range};
+use crate::{test_utils::test_project, Location, Position, Range};
 
 use super::{
+    grammar::Grammar,
     imports::{CanonicalSource, Identifier},
     queries::ImportsMapQuery,
+    resolver::ModuleResolver,
     *,
 };
 
@@ -41,14 +43,22 @@ const asyncCallMetricized = autometrics(async function asyncCall() {
 });
         "#;
 
-    let list = AmQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source, None)
-        .unwrap();
-    let all = AllFunctionsQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source)
-        .unwrap();
+    let list = AmQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+    .unwrap();
+    let all = AllFunctionsQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source)
+    .unwrap();
     let resolve_location = Location {
         file: FILE_NAME.into(),
         range: Range {
@@ -77,11 +87,13 @@ const asyncCallMetricized = autometrics(async function asyncCall() {
         id: (MODULE_NAME, "resolveAfterHalfSecond").into(),
         instrumentation: None,
         definition: Some(resolve_location),
+        ..Default::default()
     };
     let async_call = FunctionInfo {
         id: (MODULE_NAME, "asyncCall").into(),
         instrumentation: None,
         definition: Some(async_location.clone()),
+        ..Default::default()
     };
     let async_call_instrumented = FunctionInfo {
         id: (MODULE_NAME, "asyncCall").into(),
@@ -93,6 +105,7 @@ const asyncCallMetricized = autometrics(async function asyncCall() {
         // AllFunctionsQuery is supposed to catch the definition and eventually we want to merge the
         // lists.
         definition: None,
+        ..Default::default()
     };
 
     assert_eq!(
@@ -130,14 +143,22 @@ app.get("/bad", autometrics(badRoute));
 app.get("/async", autometrics(asyncRoute));
         "#;
 
-    let list = AmQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source, None)
-        .unwrap();
-    let all = AllFunctionsQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source)
-        .unwrap();
+    let list = AmQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+    .unwrap();
+    let all = AllFunctionsQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source)
+    .unwrap();
 
     let bad_location = Location {
         file: FILE_NAME.to_string(),
@@ -170,11 +191,13 @@ app.get("/async", autometrics(asyncRoute));
         id: (MODULE_NAME, "badRoute").into(),
         instrumentation: Some(bad_location),
         definition: None,
+        ..Default::default()
     };
     let async_route = FunctionInfo {
         id: (MODULE_NAME, "asyncRoute").into(),
         instrumentation: Some(async_location),
         definition: None,
+        ..Default::default()
     };
 
     assert_eq!(
@@ -227,14 +250,22 @@ class NotGood {
 }
         "#;
 
-    let list = AmQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source, None)
-        .unwrap();
-    let all = AllFunctionsQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source)
-        .unwrap();
+    let list = AmQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+    .unwrap();
+    let all = AllFunctionsQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source)
+    .unwrap();
 
     let foo_constructor_location = Location {
         file: FILE_NAME.to_string(),
@@ -287,31 +318,37 @@ class NotGood {
         id: (MODULE_NAME, "Foo.constructor").into(),
         instrumentation: Some(foo_constructor_location.clone()),
         definition: Some(foo_constructor_location.clone()),
+        ..Default::default()
     };
     let method_b_instrumented = FunctionInfo {
         id: (MODULE_NAME, "Foo.method_b").into(),
         instrumentation: Some(foo_method_b_location.clone()),
         definition: Some(foo_method_b_location.clone()),
+        ..Default::default()
     };
     let foo_constructor = FunctionInfo {
         id: (MODULE_NAME, "Foo.constructor").into(),
         instrumentation: None,
         definition: Some(foo_constructor_location),
+        ..Default::default()
     };
     let method_b = FunctionInfo {
         id: (MODULE_NAME, "Foo.method_b").into(),
         instrumentation: None,
         definition: Some(foo_method_b_location),
+        ..Default::default()
     };
     let not_good_constructor = FunctionInfo {
         id: (MODULE_NAME, "NotGood.constructor").into(),
         instrumentation: None,
         definition: Some(not_good_constructor_location),
+        ..Default::default()
     };
     let gotgot_method = FunctionInfo {
         id: (MODULE_NAME, "NotGood.gotgot").into(),
         instrumentation: None,
         definition: Some(not_good_gotgot_location),
+        ..Default::default()
     };
 
     assert_eq!(
@@ -365,9 +402,14 @@ const instrumentedRoute = autometrics(myRoute);
 const instrumentedOther = autometrics(other.stuff);
         "#;
 
-    let imports_query = ImportsMapQuery::try_new().expect("can build the imports map query");
+    let imports_query = ImportsMapQuery::try_new(Grammar::Typescript, &UserQueries::default())
+        .expect("can build the imports map query");
     let imports_map = imports_query
-        .list_imports(Some(&PathBuf::try_from("src/").unwrap()), source)
+        .list_imports(
+            Some(&PathBuf::from("src/")),
+            source,
+            &ModuleResolver::default(),
+        )
         .expect("can build the imports map from a query");
 
     let other_import = CanonicalSource::from("sibling://other");
@@ -423,14 +465,22 @@ const instrumentedRoute = autometrics(myRoute);
 const instrumentedOther = autometrics(other.stuff);
         "#;
 
-    let list = AmQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source, Some(&PathBuf::from("src/")))
-        .unwrap();
-    let all = AllFunctionsQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source)
-        .unwrap();
+    let list = AmQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source, Some(&PathBuf::from("src/")))
+    .unwrap();
+    let all = AllFunctionsQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source)
+    .unwrap();
 
     let exec_location = Location {
         file: FILE_NAME.to_string(),
@@ -476,16 +526,19 @@ const instrumentedOther = autometrics(other.stuff);
         id: ("ext://child_process", "exec").into(),
         instrumentation: Some(exec_location),
         definition: None,
+        ..Default::default()
     };
     let any_route = FunctionInfo {
         id: ("src/handlers", "anyRoute").into(),
         instrumentation: Some(route_location),
         definition: None,
+        ..Default::default()
     };
     let stuff = FunctionInfo {
         id: ("sibling://other", "stuff").into(),
         instrumentation: Some(other_location),
         definition: None,
+        ..Default::default()
     };
 
     assert_eq!(
@@ -531,14 +584,22 @@ fn detect_two_args_wrapper() {
   );
         "#;
 
-    let list = AmQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source, None)
-        .unwrap();
-    let all = AllFunctionsQuery::try_new()
-        .unwrap()
-        .list_function_names(FILE_NAME, MODULE_NAME, source)
-        .unwrap();
+    let list = AmQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+    .unwrap();
+    let all = AllFunctionsQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source)
+    .unwrap();
     let get_wow_location = Location {
         file: FILE_NAME.to_string(),
         range: Range {
@@ -563,6 +624,7 @@ fn detect_two_args_wrapper() {
         // AllFunctionsQuery is supposed to catch the definition and eventually we want to merge the
         // lists.
         definition: None,
+        ..Default::default()
     };
 
     assert_eq!(
@@ -578,3 +640,977 @@ fn detect_two_args_wrapper() {
         "list of all functions should have 0 items, got this instead: {all:?}"
     );
 }
+
+#[test]
+fn resolve_wrapped_functions_definitions() {
+    let dir = test_project(&[
+        (
+            "src/services/user.ts",
+            r#"
+export function getUser(id: string) {}
+
+function deleteUser(id: string) {}
+export { deleteUser as removeUser };
+"#,
+        ),
+        (
+            "src/index.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+import { getUser as fetchUser, removeUser } from "./services/user";
+import { exec } from "child_process";
+
+function localRoute() {}
+
+const a = autometrics(fetchUser);
+const b = autometrics(removeUser);
+const c = autometrics(exec);
+const d = autometrics(localRoute);
+"#,
+        ),
+    ]);
+
+    let mut list = Impl::default()
+        .list_autometrics_functions(dir.path())
+        .unwrap();
+    list.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let location = |file: &str, line, column, name: &str| {
+        Some(Location {
+            file: file.to_string(),
+            range: Range {
+                start: Position { line, column },
+                end: Position {
+                    line,
+                    column: column + name.len(),
+                },
+            },
+        })
+    };
+    let ids_and_definitions: Vec<_> = list
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function, info.definition))
+        .collect();
+    assert_eq!(
+        ids_and_definitions,
+        vec![
+            ("ext://child_process".to_string(), "exec".to_string(), None),
+            (
+                "index.ts".to_string(),
+                "localRoute".to_string(),
+                location("src/index.ts", 5, 9, "localRoute")
+            ),
+            (
+                "services/user.ts".to_string(),
+                "deleteUser".to_string(),
+                location("src/services/user.ts", 3, 9, "deleteUser")
+            ),
+            (
+                "services/user.ts".to_string(),
+                "getUser".to_string(),
+                location("src/services/user.ts", 1, 16, "getUser")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn resolve_aliased_imports() {
+    let dir = test_project(&[
+        (
+            "tsconfig.base.json",
+            r#"{
+  // Comments and trailing commas are allowed in compiler configurations
+  "compilerOptions": {
+    "paths": { "@/*": ["src/*"], "~db": ["packages/db/src/index.ts"], },
+  },
+}"#,
+        ),
+        (
+            "tsconfig.json",
+            r#"{ "extends": "./tsconfig.base", "compilerOptions": { "baseUrl": "." } }"#,
+        ),
+        (
+            "packages/db/package.json",
+            r#"{
+  "name": "@acme/db",
+  "exports": {
+    ".": "./src/index.ts",
+    "./client": { "types": "./dist/client.d.ts", "import": "./src/client.ts" }
+  }
+}"#,
+        ),
+        ("packages/db/src/index.ts", "export function migrate() {}"),
+        ("packages/db/src/client.ts", "export function connect() {}"),
+        ("src/services/user.ts", "export function getUser() {}"),
+        ("src/lib/log.ts", "export function log() {}"),
+        (
+            "src/index.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+import { getUser } from "@/services/user";
+import { log } from "src/lib/log";
+import { migrate } from "~db";
+import { connect } from "@acme/db/client";
+
+const a = autometrics(getUser);
+const b = autometrics(log);
+const c = autometrics(migrate);
+const d = autometrics(connect);
+"#,
+        ),
+    ]);
+
+    let mut ids: Vec<_> = Impl::default()
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+    ids.sort();
+
+    let expected: Vec<(String, String)> = [
+        ("db/src/client.ts", "connect"),
+        ("db/src/index.ts", "migrate"),
+        ("lib/log.ts", "log"),
+        ("services/user.ts", "getUser"),
+    ]
+    .into_iter()
+    .map(|(module, function)| (module.to_string(), function.to_string()))
+    .collect();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn resolve_barrel_reexports() {
+    let dir = test_project(&[
+        (
+            "src/services/user.ts",
+            "export function getUser() {}\nexport function deleteUser() {}",
+        ),
+        ("src/services/order.ts", "export function placeOrder() {}"),
+        ("src/services/billing.ts", "export function charge() {}"),
+        ("src/services/audit.ts", "export function record() {}"),
+        (
+            "src/services/index.ts",
+            r#"
+export * from "./user";
+export { placeOrder as order } from "./order";
+export * as billing from "./billing";
+import { record } from "./audit";
+export { record };
+"#,
+        ),
+        ("src/index.ts", r#"export * from "./services";"#),
+        (
+            "src/app/main.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+import { getUser, order, billing, record } from "..";
+import * as services from "../services";
+
+const a = autometrics(getUser);
+const b = autometrics(order);
+const c = autometrics(billing.charge);
+const d = autometrics(record);
+const e = autometrics(services.deleteUser);
+"#,
+        ),
+    ]);
+
+    let mut ids: Vec<_> = Impl::default()
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+    ids.sort();
+
+    let expected: Vec<(String, String)> = [
+        ("services/audit.ts", "record"),
+        ("services/billing.ts", "charge"),
+        ("services/order.ts", "placeOrder"),
+        ("services/user.ts", "deleteUser"),
+        ("services/user.ts", "getUser"),
+    ]
+    .into_iter()
+    .map(|(module, function)| (module.to_string(), function.to_string()))
+    .collect();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn resolve_cyclic_barrel_reexports() {
+    let dir = test_project(&[
+        (
+            "src/a/index.ts",
+            "export * from \"../b\";\nexport function fromA() {}",
+        ),
+        (
+            "src/b/index.ts",
+            "export * from \"../a\";\nexport function fromB() {}",
+        ),
+        (
+            "src/main.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+import { fromB, missing } from "./a";
+
+const found = autometrics(fromB);
+const notFound = autometrics(missing);
+"#,
+        ),
+    ]);
+
+    let ids: Vec<_> = Impl::default()
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+
+    assert!(
+        ids.contains(&("b/index.ts".to_string(), "fromB".to_string())),
+        "Complete list is {ids:?}"
+    );
+    assert!(
+        ids.iter()
+            .any(|(module, function)| function == "missing" && !module.ends_with(".ts")),
+        "the cycle between the barrels ends the resolution of unknown names: {ids:?}"
+    );
+}
+
+#[test]
+fn detect_jsx_files() {
+    let dir = test_project(&[
+        (
+            "src/App.tsx",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+import { Button } from "./Button";
+
+type Props<T> = { items: T[] };
+
+function List<T>({ items }: Props<T>) {
+  return (
+    <ul className="list">
+      {items.map((item) => (
+        <li key={String(item)}>
+          <Button onClick={() => alert(item as string)}>{item}</Button>
+        </li>
+      ))}
+    </ul>
+  );
+}
+
+export function App() {
+  return <List<string> items={["a", "b"]} />;
+}
+
+const InstrumentedApp = autometrics(App);
+"#,
+        ),
+        (
+            "src/Button.jsx",
+            r#"
+export function Button({ onClick, children }) {
+  return <button onClick={onClick}>{children}</button>;
+}
+"#,
+        ),
+        (
+            "src/server.js",
+            r#"
+import { autometrics } from "autometrics";
+import { Button } from "./Button";
+
+class Server {
+  start() {}
+}
+
+function handler(req, res) {}
+
+const instrumented = autometrics(handler);
+const instrumentedButton = autometrics(Button);
+"#,
+        ),
+    ]);
+
+    let mut implementation = Impl::default();
+    let mut all: Vec<_> = implementation
+        .list_all_function_definitions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+    all.sort();
+    let expected: Vec<(String, String)> = [
+        ("App.tsx", "App"),
+        ("App.tsx", "List"),
+        ("Button.jsx", "Button"),
+        ("server.js", "Server.start"),
+        ("server.js", "handler"),
+    ]
+    .into_iter()
+    .map(|(module, function)| (module.to_string(), function.to_string()))
+    .collect();
+    assert_eq!(all, expected);
+    assert_eq!(implementation.list_syntax_errors(), vec![]);
+
+    let mut instrumented: Vec<_> = implementation
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+    instrumented.sort();
+    let expected: Vec<(String, String)> = [
+        ("App.tsx", "App"),
+        ("Button.jsx", "Button"),
+        ("server.js", "handler"),
+    ]
+    .into_iter()
+    .map(|(module, function)| (module.to_string(), function.to_string()))
+    .collect();
+    assert_eq!(instrumented, expected);
+}
+
+#[test]
+fn detect_anonymous_functions() {
+    let source = r#"
+export const getUser = async (id: string) => fetchUser(id);
+const deleteUser = function (id: string) {};
+let updateUser;
+updateUser = (id: string) => {};
+const named = function realName() {};
+const notAFunction = getUser("1");
+
+export const handlers = {
+    create() {},
+    remove: async () => {},
+    update: function () {},
+};
+
+class Router {
+    route = (path: string) => {};
+    count = 0;
+    start() {}
+}
+"#;
+    let js_source = source.replace(": string", "");
+
+    for (grammar, source) in [
+        (Grammar::Typescript, source),
+        (Grammar::Javascript, js_source.as_str()),
+    ] {
+        let mut all: Vec<_> =
+            AllFunctionsQuery::try_new(grammar, &UserQueries::default(), &SyntaxErrors::default())
+                .unwrap()
+                .list_function_names(FILE_NAME, MODULE_NAME, source)
+                .unwrap()
+                .into_iter()
+                .map(|info| info.id.function)
+                .collect();
+        all.sort();
+        assert_eq!(
+            all,
+            vec![
+                "Router.route",
+                "Router.start",
+                "create",
+                "deleteUser",
+                "getUser",
+                "realName",
+                "remove",
+                "update",
+                "updateUser",
+            ],
+            "functions listed with the {grammar:?} grammar"
+        );
+    }
+}
+
+#[test]
+fn detect_module_and_commonjs_imports() {
+    let instrumented = |grammar: Grammar, source: &str| {
+        let mut names: Vec<_> =
+            AmQuery::try_new(grammar, &UserQueries::default(), &SyntaxErrors::default())
+                .unwrap()
+                .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+                .unwrap()
+                .into_iter()
+                .map(|info| info.id.function)
+                .collect();
+        names.sort();
+        names
+    };
+
+    let namespace = r#"
+import * as am from "@autometrics/autometrics";
+
+function getUser() {}
+function notInstrumented() {}
+const instrumented = am.autometrics(getUser);
+const named = am.autometrics({ functionName: "deleteUser", moduleName: "users" }, () => {});
+"#;
+    assert_eq!(
+        instrumented(Grammar::Typescript, namespace),
+        vec!["deleteUser", "getUser"]
+    );
+
+    let default_import = r#"
+import am from "@autometrics/autometrics";
+
+function getUser() {}
+const instrumented = am.autometrics(getUser);
+"#;
+    assert_eq!(
+        instrumented(Grammar::Typescript, default_import),
+        vec!["getUser"]
+    );
+
+    let import_require = r#"
+import am = require("@autometrics/autometrics");
+
+function getUser() {}
+const instrumented = am.autometrics(getUser);
+"#;
+    assert_eq!(
+        instrumented(Grammar::Typescript, import_require),
+        vec!["getUser"]
+    );
+
+    let destructured = r#"
+const { autometrics } = require("@autometrics/autometrics");
+const express = require("express");
+
+function getUser(req, res) {}
+function notInstrumented(req, res) {}
+module.exports = { getUser: autometrics(getUser) };
+"#;
+    let aliased = r#"
+const { autometrics: am } = require("@autometrics/autometrics");
+
+function getUser(req, res) {}
+module.exports = { getUser: am(getUser) };
+"#;
+    let module_object = r#"
+const autometrics = require("@autometrics/autometrics");
+
+function getUser(req, res) {}
+module.exports = { getUser: autometrics.autometrics(getUser) };
+"#;
+    for source in [destructured, aliased, module_object] {
+        assert_eq!(
+            instrumented(Grammar::Javascript, source),
+            vec!["getUser"],
+            "instrumented functions in {source}"
+        );
+    }
+}
+
+#[test]
+fn detect_decorators_with_options() {
+    let source = r#"
+import { Autometrics } from "@autometrics/autometrics";
+
+@Autometrics({ objective: API_SLO, trackConcurrency: true })
+export class UserService {
+    getUser() {}
+
+    @Autometrics({ trackConcurrency: false })
+    deleteUser() {}
+}
+
+@Autometrics()
+class HealthCheck {
+    ping() {}
+}
+
+class Controller {
+    @Autometrics({ "objective": API_SLO })
+    @Get("/users")
+    list() {}
+
+    @Post("/users")
+    @Autometrics
+    create() {}
+
+    notInstrumented() {}
+}
+"#;
+    let options = |pairs: &[(&str, &str)]| {
+        Some(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<std::collections::BTreeMap<_, _>>(),
+        )
+    };
+    let expected = vec![
+        ("Controller.create".to_string(), None),
+        (
+            "Controller.list".to_string(),
+            options(&[("objective", "API_SLO")]),
+        ),
+        ("HealthCheck.ping".to_string(), None),
+        (
+            "UserService.deleteUser".to_string(),
+            options(&[("objective", "API_SLO"), ("trackConcurrency", "false")]),
+        ),
+        (
+            "UserService.getUser".to_string(),
+            options(&[("objective", "API_SLO"), ("trackConcurrency", "true")]),
+        ),
+    ];
+
+    for grammar in [Grammar::Typescript, Grammar::Javascript] {
+        let mut list: Vec<_> =
+            AmQuery::try_new(grammar, &UserQueries::default(), &SyntaxErrors::default())
+                .unwrap()
+                .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+                .unwrap()
+                .into_iter()
+                .map(|info| (info.id.function, info.options))
+                .collect();
+        list.sort();
+        assert_eq!(
+            list, expected,
+            "decorated methods with the {grammar:?} grammar"
+        );
+    }
+}
+
+#[test]
+fn detect_mixed_wrapper_calls() {
+    let source = r#"
+import { autometrics } from "@autometrics/autometrics";
+import { autometrics as instrument } from "@autometrics/autometrics";
+
+function getUser() {}
+function deleteUser() {}
+const userService = { updateUser() {} };
+
+const instrumentedGetUser = autometrics(getUser);
+const instrumentedDeleteUser = instrument(deleteUser);
+const named = autometrics({ functionName: "listUsers", moduleName: "users" }, async () => {});
+const withObjective = autometrics({ objective: API_SLO }, deleteUser);
+const createUser = autometrics(async (user) => {});
+const banUser = instrument({ moduleName: "admin" }, (user) => {});
+const instrumentedUpdate = autometrics(userService.updateUser);
+export const handlers = {
+    health: autometrics(function () {}),
+};
+"#;
+
+    let mut list: Vec<_> = AmQuery::try_new(
+        Grammar::Typescript,
+        &UserQueries::default(),
+        &SyntaxErrors::default(),
+    )
+    .unwrap()
+    .list_function_names(FILE_NAME, MODULE_NAME, source, None)
+    .unwrap()
+    .into_iter()
+    .map(|info| (info.id.module, info.id.function))
+    .collect();
+    list.sort();
+    list.dedup();
+    let expected: Vec<(String, String)> = [
+        ("admin", "banUser"),
+        (MODULE_NAME, "createUser"),
+        (MODULE_NAME, "deleteUser"),
+        (MODULE_NAME, "getUser"),
+        (MODULE_NAME, "health"),
+        (MODULE_NAME, "updateUser"),
+        ("users", "listUsers"),
+    ]
+    .into_iter()
+    .map(|(module, function)| (module.to_string(), function.to_string()))
+    .collect();
+    assert_eq!(list, expected);
+}
+
+#[test]
+fn detect_workspace_packages() {
+    for (workspace_file, workspace_contents) in [
+        (
+            "package.json",
+            r#"{ "private": true, "workspaces": ["packages/*", "!packages/legacy"] }"#,
+        ),
+        (
+            "pnpm-workspace.yaml",
+            "packages:\n  - 'packages/*'\n  - '!packages/legacy'\n",
+        ),
+        (
+            "pnpm-workspace.yaml",
+            r#"# Packages of the monorepo
+packages:
+# all the packages
+- packages/* # but one
+- "!packages/legacy"
+catalog:
+  - react: ^18.0.0
+"#,
+        ),
+        (
+            "pnpm-workspace.yaml",
+            "packages: ['packages/*', \"!packages/legacy\"] # flow style\n",
+        ),
+    ] {
+        let user_service = r#"
+import { autometrics } from "@autometrics/autometrics";
+
+function getUser() {}
+
+export const instrumentedGetUser = autometrics(getUser);
+"#;
+        let dir = test_project(&[
+            (workspace_file, workspace_contents),
+            ("packages/api/package.json", r#"{ "name": "@acme/api" }"#),
+            ("packages/api/src/user.ts", user_service),
+            (
+                "packages/worker/package.json",
+                r#"{ "name": "@acme/worker" }"#,
+            ),
+            ("packages/worker/src/user.ts", user_service),
+            (
+                "packages/legacy/package.json",
+                r#"{ "name": "@acme/legacy" }"#,
+            ),
+            ("packages/legacy/src/user.ts", user_service),
+        ]);
+
+        let mut implementation = Impl::default();
+        let mut functions: Vec<_> = implementation
+            .list_all_functions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| {
+                (
+                    info.id.package,
+                    info.id.module,
+                    info.id.function,
+                    info.instrumentation.is_some(),
+                )
+            })
+            .collect();
+        functions.sort();
+        let expected: Vec<_> = [
+            (None, "legacy/src/user.ts", "getUser", true),
+            (Some("@acme/api"), "user.ts", "getUser", true),
+            (Some("@acme/worker"), "user.ts", "getUser", true),
+        ]
+        .into_iter()
+        .map(|(package, module, function, instrumented)| {
+            (
+                package.map(ToString::to_string),
+                module.to_string(),
+                function.to_string(),
+                instrumented,
+            )
+        })
+        .collect();
+        assert_eq!(
+            functions, expected,
+            "workspace declared in {workspace_file}:\n{workspace_contents}"
+        );
+    }
+}
+
+#[test]
+fn compiled_module_names() {
+    let dir = test_project(&[
+        (
+            "tsconfig.json",
+            r#"{
+  // The default rootDir is the common directory of the sources: `src`.
+  "compilerOptions": { "outDir": "./dist", "module": "esnext" },
+}"#,
+        ),
+        (
+            "src/services/user.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+
+export function getUser() {}
+
+export const instrumentedGetUser = autometrics(getUser);
+"#,
+        ),
+        (
+            "src/index.mts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+import { getUser } from "./services/user.js";
+
+function main() {}
+
+const instrumentedMain = autometrics(main);
+"#,
+        ),
+    ]);
+    let modules = |implementation: &mut Impl| {
+        let mut modules: Vec<_> = implementation
+            .list_all_functions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| (info.id.module, info.id.function))
+            .collect();
+        modules.sort();
+        modules
+    };
+    let expected = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(module, function)| (module.to_string(), function.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let mut implementation = Impl::default();
+    assert_eq!(
+        modules(&mut implementation),
+        expected(&[("index.mts", "main"), ("services/user.ts", "getUser")]),
+        "source modules by default"
+    );
+
+    implementation.compiled_modules = true;
+    assert_eq!(
+        modules(&mut implementation),
+        expected(&[
+            ("dist/index.mjs", "main"),
+            ("dist/services/user.js", "getUser")
+        ]),
+        "compiled modules from the tsconfig.json options"
+    );
+
+    // A bundle merges the user service with other sources.
+    std::fs::create_dir_all(dir.path().join("build")).unwrap();
+    std::fs::write(
+        dir.path().join("build/server.js.map"),
+        r#"{
+  "version": 3,
+  "file": "server.js",
+  "sources": ["webpack://server/./src/services/user.ts", "webpack://server/./src/db.ts"],
+  "names": [],
+  "mappings": "AAAA"
+}"#,
+    )
+    .unwrap();
+    implementation.build_dir = Some(PathBuf::from("build"));
+    assert_eq!(
+        modules(&mut implementation),
+        expected(&[("build/server.js", "getUser"), ("dist/index.mjs", "main"),]),
+        "compiled modules from the source maps"
+    );
+}
+
+#[test]
+fn compiled_module_names_of_components() {
+    let component = r#"
+<script setup lang="ts">
+import { autometrics } from "@autometrics/autometrics";
+
+function loadProfile() {}
+
+const instrumentedLoadProfile = autometrics(loadProfile);
+</script>
+"#;
+    let dir = test_project(&[
+        (
+            "tsconfig.json",
+            r#"{ "compilerOptions": { "outDir": "./dist", "rootDir": "./src" } }"#,
+        ),
+        (
+            "src/services/user.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+
+export function getUser() {}
+
+export const instrumentedGetUser = autometrics(getUser);
+"#,
+        ),
+        ("src/components/Profile.vue", component),
+    ]);
+    let mut implementation = Impl {
+        compiled_modules: true,
+        ..Impl::default()
+    };
+    let mut modules: Vec<_> = implementation
+        .list_all_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+    modules.sort();
+    let expected: Vec<_> = [
+        ("components/Profile.vue", "loadProfile"),
+        ("dist/services/user.js", "getUser"),
+    ]
+    .into_iter()
+    .map(|(module, function)| (module.to_string(), function.to_string()))
+    .collect();
+    assert_eq!(
+        modules, expected,
+        "components are only compiled by bundlers, through source maps"
+    );
+}
+
+#[test]
+fn ignore_declaration_source_maps() {
+    let dir = test_project(&[
+        (
+            "src/user.ts",
+            r#"
+import { autometrics } from "@autometrics/autometrics";
+
+export function getUser() {}
+
+export const instrumentedGetUser = autometrics(getUser);
+"#,
+        ),
+        (
+            "build/user.d.ts.map",
+            r#"{"version": 3, "file": "user.d.ts", "sources": ["../src/user.ts"], "names": [], "mappings": "AAAA"}"#,
+        ),
+        (
+            "build/user.js.map",
+            r#"{"version": 3, "file": "user.js", "sources": ["../src/user.ts"], "names": [], "mappings": "AAAA"}"#,
+        ),
+    ]);
+
+    let mut implementation = Impl {
+        compiled_modules: true,
+        build_dir: Some(PathBuf::from("build")),
+        ..Impl::default()
+    };
+    let modules: Vec<_> = implementation
+        .list_all_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| (info.id.module, info.id.function))
+        .collect();
+
+    assert_eq!(
+        modules,
+        vec![("build/user.js".to_string(), "getUser".to_string())],
+        "the declaration files are not executed"
+    );
+}
+
+#[test]
+fn detect_single_file_components() {
+    let dir = test_project(&[
+        (
+            "src/UserCard.vue",
+            r#"<template>
+  <p>Héllo {{ user.name }} <button @click="refresh">↻</button></p>
+</template>
+
+<script setup lang="ts">
+import { autometrics } from "@autometrics/autometrics";
+
+function fetchUser(id: string): Promise<User> {}
+
+const refresh = autometrics(fetchUser);
+</script>
+
+<style scoped>
+p { color: red; }
+</style>
+"#,
+        ),
+        (
+            "src/Counter.svelte",
+            r#"<script context="module">
+  export const prerender = true;
+</script>
+
+<script lang="ts">
+  import { autometrics } from "@autometrics/autometrics";
+
+  const increment = autometrics(function increment() {});
+</script>
+
+<button on:click={increment}>{count} ✓</button>
+"#,
+        ),
+    ]);
+
+    let mut implementation = Impl::default();
+    let mut functions: Vec<_> = implementation
+        .list_autometrics_functions(dir.path())
+        .unwrap()
+        .into_iter()
+        .map(|info| {
+            let instrumentation = info.instrumentation.unwrap();
+            (
+                info.id.module,
+                info.id.function,
+                instrumentation.file,
+                instrumentation.range.start,
+            )
+        })
+        .collect();
+    functions.sort();
+    assert_eq!(
+        functions,
+        vec![
+            (
+                "Counter.svelte".to_string(),
+                "increment".to_string(),
+                "src/Counter.svelte".to_string(),
+                Position {
+                    line: 7,
+                    column: 41
+                }
+            ),
+            (
+                "UserCard.vue".to_string(),
+                "fetchUser".to_string(),
+                "src/UserCard.vue".to_string(),
+                Position {
+                    line: 9,
+                    column: 28
+                }
+            ),
+        ]
+    );
+
+    assert_eq!(
+        implementation.list_syntax_errors(),
+        vec![],
+        "the markup around the scripts is not parsed"
+    );
+}
+
+#[test]
+fn exclude_test_files() {
+    let dir = test_project(&[
+        ("src/user.ts", "export function getUser() {}"),
+        ("src/user.test.ts", "function testGetUser() {}"),
+        ("src/user.spec.js", "function specGetUser() {}"),
+        ("src/__tests__/helpers.ts", "function mockUser() {}"),
+    ]);
+
+    let functions = |implementation: &mut Impl| {
+        let mut functions: Vec<_> = implementation
+            .list_all_function_definitions(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|info| info.id.function)
+            .collect();
+        functions.sort();
+        functions
+    };
+
+    let mut implementation = Impl::default();
+    assert_eq!(
+        functions(&mut implementation),
+        vec!["getUser", "mockUser", "specGetUser", "testGetUser"]
+    );
+
+    implementation.exclude_tests = true;
+    assert_eq!(functions(&mut implementation), vec!["getUser"]);
+}
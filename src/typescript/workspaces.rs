@@ -0,0 +1,227 @@
+//! Packages of npm, yarn and pnpm workspaces.
+//!
+//! In a monorepo, each package is built and deployed on its own, so the module
+//! names of its files are computed from the root of the package instead of the
+//! root of the repository, and the functions are reported with the name of
+//! their package.
+
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use log::warn;
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use crate::{AmlError, Result};
+
+#[derive(Debug, Default, Deserialize)]
+struct RawPackageJson {
+    name: Option<String>,
+    workspaces: Option<RawWorkspaces>,
+}
+
+/// The `workspaces` of npm and yarn, either a list of patterns or an object
+/// with a `packages` list (yarn classic with `nohoist`).
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawWorkspaces {
+    Patterns(Vec<String>),
+    Object {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+}
+
+/// A package of the workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Package {
+    /// Name of the package, from its `package.json`.
+    pub name: String,
+    /// Directory of the package, relative to the project root.
+    pub dir: PathBuf,
+}
+
+/// The packages of the workspace at the root of a project.
+#[derive(Clone, Debug, Default)]
+pub struct Workspaces {
+    /// Packages of the workspace, the deepest directories first.
+    packages: Vec<Package>,
+}
+
+impl Workspaces {
+    /// Read the workspace declared at `project_root`, in the `workspaces` of
+    /// `package.json` or in `pnpm-workspace.yaml`.
+    ///
+    /// A project that is not a workspace has no packages.
+    pub fn discover(project_root: &Path) -> Result<Self> {
+        let mut patterns = Vec::new();
+
+        let manifest = project_root.join("package.json");
+        if manifest.is_file() {
+            let contents =
+                read_to_string(&manifest).map_err(|err| AmlError::Io(manifest.clone(), err))?;
+            match serde_json::from_str::<RawPackageJson>(&contents) {
+                Ok(RawPackageJson {
+                    workspaces: Some(RawWorkspaces::Patterns(packages)),
+                    ..
+                })
+                | Ok(RawPackageJson {
+                    workspaces: Some(RawWorkspaces::Object { packages }),
+                    ..
+                }) => patterns.extend(packages),
+                Ok(_) => {}
+                Err(err) => warn!("Ignoring invalid {}: {err}", manifest.display()),
+            }
+        }
+
+        let pnpm_workspace = project_root.join("pnpm-workspace.yaml");
+        if pnpm_workspace.is_file() {
+            let contents = read_to_string(&pnpm_workspace)
+                .map_err(|err| AmlError::Io(pnpm_workspace.clone(), err))?;
+            patterns.extend(pnpm_packages(&contents));
+        }
+
+        if patterns.is_empty() {
+            return Ok(Self::default());
+        }
+        Self::from_patterns(project_root, &patterns)
+    }
+
+    /// Find the packages matching the given patterns. Patterns starting with
+    /// `!` exclude the directories they match.
+    fn from_patterns(project_root: &Path, patterns: &[String]) -> Result<Self> {
+        let mut include = GlobSetBuilder::new();
+        let mut exclude = GlobSetBuilder::new();
+        for pattern in patterns {
+            let (builder, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (&mut exclude, pattern),
+                None => (&mut include, pattern.as_str()),
+            };
+            let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+            builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        }
+        let (include, exclude): (GlobSet, GlobSet) = (include.build()?, exclude.build()?);
+
+        let mut packages = Vec::new();
+        let walker = WalkDir::new(project_root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.file_type().is_dir()
+                    && entry.file_name().to_str().map_or(false, |name| {
+                        !name.starts_with('.') && name != "node_modules"
+                    })
+            });
+        for entry in walker.filter_map(|entry| entry.ok()) {
+            let dir = entry
+                .path()
+                .strip_prefix(project_root)
+                .expect("path comes from a project_root WalkDir");
+            if !include.is_match(dir) || exclude.is_match(dir) {
+                continue;
+            }
+            let manifest = entry.path().join("package.json");
+            if !manifest.is_file() {
+                continue;
+            }
+            let contents =
+                read_to_string(&manifest).map_err(|err| AmlError::Io(manifest.clone(), err))?;
+            let name = match serde_json::from_str::<RawPackageJson>(&contents) {
+                Ok(RawPackageJson {
+                    name: Some(name), ..
+                }) => name,
+                Ok(_) => dir.to_string_lossy().to_string(),
+                Err(err) => {
+                    warn!("Ignoring invalid {}: {err}", manifest.display());
+                    continue;
+                }
+            };
+            packages.push(Package {
+                name,
+                dir: dir.to_path_buf(),
+            });
+        }
+
+        packages.sort_by_key(|package| std::cmp::Reverse(package.dir.components().count()));
+        Ok(Self { packages })
+    }
+
+    /// Return the package containing the file at `relative_path`, relative to
+    /// the project root.
+    pub fn package_of(&self, relative_path: &Path) -> Option<&Package> {
+        self.packages
+            .iter()
+            .find(|package| relative_path.starts_with(&package.dir))
+    }
+}
+
+/// Read the `packages` list of a `pnpm-workspace.yaml`.
+///
+/// Only this top-level key is read, either as a block sequence of (possibly
+/// quoted) patterns or as a flow sequence on a single line, so the file is
+/// parsed directly instead of going through a full YAML parser.
+fn pnpm_packages(contents: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let is_top_level = !line.starts_with([' ', '\t']);
+        if let Some(value) = trimmed.strip_prefix("packages:").filter(|_| is_top_level) {
+            in_packages = true;
+            let value = strip_yaml_comment(value).trim();
+            if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                packages.extend(
+                    items
+                        .split(',')
+                        .map(yaml_scalar)
+                        .filter(|item| !item.is_empty()),
+                );
+                in_packages = false;
+            }
+            continue;
+        }
+        match trimmed.strip_prefix('-') {
+            Some(item) if in_packages && (item.is_empty() || item.starts_with(' ')) => {
+                let item = yaml_scalar(strip_yaml_comment(item));
+                if !item.is_empty() {
+                    packages.push(item);
+                }
+            }
+            _ if is_top_level => in_packages = false,
+            _ => {}
+        }
+    }
+    packages
+}
+
+/// Remove the trailing ` # comment` of a YAML value, outside of quotes.
+fn strip_yaml_comment(value: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in value.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '#') if previous.is_whitespace() => return &value[..index],
+            _ => {}
+        }
+        previous = c;
+    }
+    value
+}
+
+/// The string of a plain or quoted YAML scalar.
+fn yaml_scalar(value: &str) -> String {
+    let value = value.trim();
+    match value.as_bytes() {
+        [b'\'', .., b'\''] if value.len() > 1 => value[1..value.len() - 1].replace("''", "'"),
+        [b'"', .., b'"'] if value.len() > 1 => value[1..value.len() - 1].to_string(),
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,239 @@
+//! User-supplied tree-sitter queries, loaded at runtime.
+//!
+//! The queries directory mirrors the layout of `runtime/queries`, with one
+//! sub-directory per language:
+//! - a file with the same name as a built-in query (e.g. `rust/autometrics.scm`
+//!   or `typescript/wrapper_call.scm.tpl`) replaces the built-in query entirely,
+//! - a file named `<query>.extra.scm` (e.g. `rust/autometrics.extra.scm`) adds
+//!   its patterns to the built-in (or overridden) `<query>` query.
+//!
+//! Overriding queries must use the named captures the built-in query uses, so
+//! that am_list can extract the function information from the matches. Extra
+//! patterns can use any subset of these captures.
+
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::{debug, warn};
+use tree_sitter::{Language, Query};
+use walkdir::WalkDir;
+
+use crate::{AmlError, Result};
+
+const EXTRA_QUERY_SUFFIX: &str = ".extra.scm";
+
+/// A query file read from the user queries directory.
+#[derive(Clone, Debug)]
+struct UserQuery {
+    path: PathBuf,
+    contents: String,
+}
+
+/// The set of user-supplied queries, indexed by their name relative to the
+/// queries directory (e.g. `rust/autometrics.scm`).
+#[derive(Clone, Debug, Default)]
+pub struct UserQueries {
+    overrides: Arc<HashMap<String, UserQuery>>,
+    /// Extra patterns, indexed by the name of the query they extend without
+    /// its extensions (e.g. `rust/autometrics`).
+    extras: Arc<HashMap<String, Vec<UserQuery>>>,
+}
+
+impl UserQueries {
+    /// Read all the queries in the given directory.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut overrides = HashMap::new();
+        let mut extras: HashMap<String, Vec<UserQuery>> = HashMap::new();
+
+        for entry in WalkDir::new(dir).min_depth(2).max_depth(2) {
+            let entry = entry.map_err(|err| {
+                AmlError::Io(
+                    err.path().unwrap_or(dir).to_path_buf(),
+                    err.into_io_error()
+                        .unwrap_or_else(|| std::io::ErrorKind::Other.into()),
+                )
+            })?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path().to_path_buf();
+            let Some(name) = path
+                .strip_prefix(dir)
+                .ok()
+                .and_then(Path::to_str)
+                .map(|name| name.replace(std::path::MAIN_SEPARATOR, "/"))
+            else {
+                warn!(
+                    "Skipping user query with a non UTF-8 path: {}",
+                    path.display()
+                );
+                continue;
+            };
+            if !name.ends_with(".scm") && !name.ends_with(".scm.tpl") {
+                debug!("Skipping non-query file in the queries directory: {name}");
+                continue;
+            }
+
+            let contents =
+                read_to_string(&path).map_err(|err| AmlError::Io(path.to_path_buf(), err))?;
+            let query = UserQuery { path, contents };
+            if let Some(base) = name.strip_suffix(EXTRA_QUERY_SUFFIX) {
+                extras.entry(base.to_string()).or_default().push(query);
+            } else {
+                overrides.insert(name, query);
+            }
+        }
+
+        Ok(Self {
+            overrides: Arc::new(overrides),
+            extras: Arc::new(extras),
+        })
+    }
+
+    /// Create the query called `name`, using the user-supplied queries when they
+    /// exist, and the `builtin` query otherwise.
+    ///
+    /// `template_args` are used to fill the `{0}`, `{1}`... placeholders when the
+    /// query is a template (a `.scm.tpl` file). The extra patterns for a template
+    /// are templates as well.
+    pub(crate) fn query(
+        &self,
+        language: Language,
+        name: &str,
+        builtin: &str,
+        template_args: &[&str],
+    ) -> Result<LoadedQuery> {
+        let is_template = name.ends_with(".tpl");
+        let render = |contents: &str| {
+            if is_template {
+                fill_template(contents, template_args)
+            } else {
+                contents.to_string()
+            }
+        };
+
+        let (mut contents, origin) = match self.overrides.get(name) {
+            Some(user_query) => (render(&user_query.contents), Some(user_query.path.clone())),
+            None => (render(builtin), None),
+        };
+
+        let base_name = name.split_once('.').map_or(name, |(base, _)| base);
+        let extras = self
+            .extras
+            .get(base_name)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if extras.is_empty() {
+            let query = Query::new(language, &contents)
+                .map_err(|err| wrap_error(origin.as_deref(), err.into()))?;
+            return Ok(LoadedQuery {
+                base_capture_names: query.capture_names().to_vec(),
+                base_pattern_count: query.pattern_count(),
+                query,
+                origin,
+            });
+        }
+
+        // Validate each part on its own first, so that the reported error
+        // positions are correct.
+        let base = Query::new(language, &contents)
+            .map_err(|err| wrap_error(origin.as_deref(), err.into()))?;
+        for extra in extras {
+            let extra_contents = render(&extra.contents);
+            Query::new(language, &extra_contents)
+                .map_err(|err| wrap_error(Some(&extra.path), err.into()))?;
+            contents.push('\n');
+            contents.push_str(&extra_contents);
+        }
+
+        let query = Query::new(language, &contents).map_err(|err| {
+            let last_extra = extras.last().map(|extra| extra.path.as_path());
+            wrap_error(last_extra, err.into())
+        })?;
+        Ok(LoadedQuery {
+            query,
+            origin,
+            base_capture_names: base.capture_names().to_vec(),
+            base_pattern_count: base.pattern_count(),
+        })
+    }
+}
+
+/// A query ready to be used, remembering where it comes from to report errors.
+pub(crate) struct LoadedQuery {
+    pub query: Query,
+    /// Path of the user query file that overrides the built-in query, if any.
+    origin: Option<PathBuf>,
+    /// Named captures of the built-in or overriding query, without the ones
+    /// only used by the extra patterns.
+    base_capture_names: Vec<String>,
+    /// Number of patterns of the built-in or overriding query: the patterns
+    /// with a greater index come from the extra queries.
+    pub base_pattern_count: usize,
+}
+
+impl LoadedQuery {
+    /// Return the index of the given named capture in the query.
+    ///
+    /// The built-in or overriding query must have the capture: the extra
+    /// patterns can use a subset of the captures, but cannot make up for a
+    /// capture missing from the query they extend.
+    pub fn capture_index(&self, capture_name: &str) -> Result<u32> {
+        self.base_capture_names
+            .iter()
+            .any(|name| name == capture_name)
+            .then(|| self.query.capture_index_for_name(capture_name))
+            .flatten()
+            .ok_or_else(|| {
+                wrap_error(
+                    self.origin.as_deref(),
+                    AmlError::MissingNamedCapture(capture_name.to_string()),
+                )
+            })
+    }
+}
+
+fn wrap_error(origin: Option<&Path>, err: AmlError) -> AmlError {
+    match origin {
+        Some(path) => AmlError::InvalidUserQuery(path.to_path_buf(), Box::new(err)),
+        None => err,
+    }
+}
+
+/// Fill the positional `{N}` placeholders of a query template, and unescape the
+/// `{{` and `}}` sequences, like `format!` does.
+fn fill_template(template: &str, args: &[&str]) -> String {
+    let mut res = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                res.push(c);
+            }
+            ('{', Some(next)) if next.is_ascii_digit() => {
+                let mut index = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    index.push(digit);
+                }
+                match (chars.next_if_eq(&'}'), index.parse::<usize>()) {
+                    (Some(_), Ok(i)) if i < args.len() => res.push_str(args[i]),
+                    (closing, _) => {
+                        res.push('{');
+                        res.push_str(&index);
+                        res.extend(closing);
+                    }
+                }
+            }
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests;
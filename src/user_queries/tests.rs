@@ -0,0 +1,139 @@
+use super::*;
+use crate::test_utils::test_project;
+use pretty_assertions::assert_eq;
+use tree_sitter_go::language;
+
+const BUILTIN: &str = "(function_declaration name: (identifier) @func.name)";
+
+#[test]
+fn fill_templates() {
+    assert_eq!(
+        fill_template(
+            r#"(#eq? @name "{0}") (#match? @x "a{{1,1}}") {1}"#,
+            &["am", "b"]
+        ),
+        r#"(#eq? @name "am") (#match? @x "a{1,1}") b"#
+    );
+    assert_eq!(fill_template("{3} {x}", &["am"]), "{3} {x}");
+}
+
+#[test]
+fn use_builtin_query_by_default() {
+    let loaded = UserQueries::default()
+        .query(language(), "go/all_functions.scm", BUILTIN, &[])
+        .unwrap();
+    assert_eq!(loaded.query.pattern_count(), 1);
+    assert!(loaded.capture_index("func.name").is_ok());
+    assert!(matches!(
+        loaded.capture_index("pack.name"),
+        Err(AmlError::MissingNamedCapture(_))
+    ));
+}
+
+#[test]
+fn override_and_extend_builtin_query() {
+    let dir = test_project(&[
+        (
+            "go/all_functions.scm",
+            "(method_declaration name: (field_identifier) @func.name)",
+        ),
+        (
+            "go/all_functions.extra.scm",
+            "(function_declaration name: (identifier) @func.name)",
+        ),
+        ("go/README.md", "not a query"),
+    ]);
+    let user_queries = UserQueries::load(dir.path()).unwrap();
+
+    let loaded = user_queries
+        .query(language(), "go/all_functions.scm", BUILTIN, &[])
+        .unwrap();
+    assert_eq!(loaded.query.pattern_count(), 2);
+
+    // Other queries are left untouched
+    let other = user_queries
+        .query(language(), "go/autometrics.scm", BUILTIN, &[])
+        .unwrap();
+    assert_eq!(other.query.pattern_count(), 1);
+}
+
+#[test]
+fn report_invalid_user_queries() {
+    let dir = test_project(&[
+        (
+            "go/autometrics.scm",
+            "(function_declaration name: (identifier) @fn)",
+        ),
+        ("go/all_functions.extra.scm", "(function_declaration name: "),
+    ]);
+    let user_queries = UserQueries::load(dir.path()).unwrap();
+
+    let missing_capture = user_queries
+        .query(language(), "go/autometrics.scm", BUILTIN, &[])
+        .unwrap()
+        .capture_index("func.name");
+    match missing_capture {
+        Err(AmlError::InvalidUserQuery(path, err)) => {
+            assert_eq!(path, dir.path().join("go/autometrics.scm"));
+            assert!(
+                matches!(*err, AmlError::MissingNamedCapture(ref capture) if capture == "func.name")
+            );
+        }
+        other => panic!("Expected a missing capture error, got {other:?}"),
+    }
+
+    let syntax_error = user_queries.query(language(), "go/all_functions.scm", BUILTIN, &[]);
+    match syntax_error {
+        Err(AmlError::InvalidUserQuery(path, err)) => {
+            assert_eq!(path, dir.path().join("go/all_functions.extra.scm"));
+            assert!(matches!(*err, AmlError::CreateQuery(_)));
+        }
+        other => panic!(
+            "Expected a query creation error, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+}
+
+#[test]
+fn report_missing_capture_of_overriding_query() {
+    let dir = test_project(&[
+        (
+            "go/autometrics.scm",
+            "(function_declaration name: (identifier) @fn)",
+        ),
+        (
+            "go/autometrics.extra.scm",
+            "(method_declaration name: (field_identifier) @func.name)",
+        ),
+        (
+            "go/all_functions.extra.scm",
+            "(method_declaration name: (field_identifier) @method)",
+        ),
+    ]);
+    let user_queries = UserQueries::load(dir.path()).unwrap();
+
+    let missing_capture = user_queries
+        .query(language(), "go/autometrics.scm", BUILTIN, &[])
+        .unwrap()
+        .capture_index("func.name");
+    match missing_capture {
+        Err(AmlError::InvalidUserQuery(path, err)) => {
+            assert_eq!(
+                path,
+                dir.path().join("go/autometrics.scm"),
+                "the override lacks the capture, not the extra patterns"
+            );
+            assert!(
+                matches!(*err, AmlError::MissingNamedCapture(ref capture) if capture == "func.name")
+            );
+        }
+        other => panic!("Expected a missing capture error, got {other:?}"),
+    }
+
+    // Extra patterns can use a subset of the captures of the query they extend.
+    let extended = user_queries
+        .query(language(), "go/all_functions.scm", BUILTIN, &[])
+        .unwrap();
+    assert!(extended.capture_index("func.name").is_ok());
+}